@@ -1,11 +1,12 @@
-use std::process::exit;
-
 use clap::Parser;
 
 mod picker;
 
-use ares_device_lib::cli::unwrap_or_exit;
-use ares_device_lib::{Device, DeviceManager};
+use ares_device_lib::cli::{
+    CliError, DeviceEntry, ErrorKind, OutputFormat, json_output, print_device_list, print_json,
+    set_output_format, unwrap_or_exit,
+};
+use ares_device_lib::{DeviceManager, Selector, prompt};
 use picker::{DeviceSelection, PickDevice};
 
 #[derive(Parser, Debug)]
@@ -18,76 +19,52 @@ struct Cli {
         num_args = 0..2,
         value_name = "DEVICE",
         env = "ARES_DEVICE",
-        help = "Specify DEVICE to use, show picker if no value specified"
+        help = "Specify DEVICE to use, show picker if no value specified. @GROUP or tag:TAG lists the devices it picks"
     )]
     device: Option<DeviceSelection>,
     #[arg(short = 'D', long = "device-list", help = "List the available devices")]
     device_list: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
     let manager = DeviceManager::default();
 
     if cli.device_list {
-        list_devices(&manager);
+        print_device_list(&unwrap_or_exit(manager.list(), "list devices"), false);
         return;
     }
 
-    let Some(device) = unwrap_or_exit(manager.pick(cli.device.as_ref()), "find device") else {
-        eprintln!("Device not found");
-        exit(1);
-    };
-    println!("{}", device.name);
-}
-
-fn list_devices(manager: &DeviceManager) {
-    let devices = unwrap_or_exit(manager.list(), "list devices");
-
-    let headers = ["name", "deviceinfo", "connection", "profile", "passphrase"];
-    let rows: Vec<[String; 5]> = devices.iter().map(device_row).collect();
-    print_table(&headers, &rows);
-}
-
-fn device_row(device: &Device) -> [String; 5] {
-    let name = if device.default == Some(true) {
-        format!("{} (default)", device.name)
-    } else {
-        device.name.clone()
-    };
-    [
-        name,
-        format!("{}@{}:{}", device.username, device.host, device.port),
-        String::from("ssh"),
-        device.profile.clone(),
-        device.passphrase.clone().unwrap_or_default(),
-    ]
-}
-
-/// Prints a left-aligned, space-padded table with a dashed header underline,
-/// mirroring the reference CLI's device list output.
-fn print_table<const N: usize>(headers: &[&str; N], rows: &[[String; N]]) {
-    let mut widths = headers.map(str::len);
-    for row in rows {
-        for (i, cell) in row.iter().enumerate() {
-            widths[i] = widths[i].max(cell.len());
-        }
+    if let Some(DeviceSelection::Name(target)) = &cli.device
+        && Selector::parse(target).is_set()
+    {
+        print_device_list(
+            &unwrap_or_exit(manager.resolve(&[target]), "find devices"),
+            false,
+        );
+        return;
     }
 
-    let print_row = |cells: &[String; N]| {
-        let line: Vec<String> = cells
-            .iter()
-            .enumerate()
-            .map(|(i, cell)| format!("{cell:<width$}", width = widths[i]))
-            .collect();
-        println!("{}", line.join("  ").trim_end());
+    let Some(device) = unwrap_or_exit(manager.pick(cli.device.as_ref()), "find device") else {
+        CliError::new(ErrorKind::NotFound, "Device not found").exit();
     };
-
-    let header_row: [String; N] = std::array::from_fn(|i| headers[i].to_string());
-    let separator_row: [String; N] = std::array::from_fn(|i| "-".repeat(widths[i]));
-    print_row(&header_row);
-    print_row(&separator_row);
-    for row in rows {
-        print_row(row);
+    if json_output() {
+        print_json(&DeviceEntry::from(&device));
+    } else {
+        println!("{}", device.name);
     }
 }
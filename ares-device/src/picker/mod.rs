@@ -29,7 +29,7 @@ impl PickDevice for DeviceManager {
     fn pick(&self, selection: Option<&DeviceSelection>) -> Result<Option<Device>, Error> {
         let devices = self.list()?;
         let device = match selection {
-            Some(DeviceSelection::Name(s)) => devices.iter().find(|d| &d.name == s).cloned(),
+            Some(DeviceSelection::Name(s)) => self.find_or_default(Some(s))?,
             Some(DeviceSelection::Pick) => {
                 cfg_if::cfg_if! {
                     if #[cfg(target_os="windows")] {
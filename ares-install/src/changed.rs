@@ -0,0 +1,256 @@
+use std::cmp::Ordering;
+use std::io::Cursor;
+use std::path::Path;
+
+use ares_connection_lib::luna::{Luna, LunaEmptyPayload};
+use ares_connection_lib::session::DeviceSession;
+use ares_connection_lib::transfer::{FileTransfer, TransferError};
+use serde::{Deserialize, Serialize};
+
+use crate::info::read_json;
+use crate::install::{InstallError, PackageControl};
+use crate::list::ListAppsResponse;
+
+/// Where the device keeps a record of the package each app was installed from.
+const RECORD_DIR: &str = "/media/developer/ares-install";
+
+/// Where developer mode installs packages.
+const PACKAGES_DIR: &str = "/media/developer/apps/usr/palm/packages";
+
+/// The package an app was installed from, as recorded by `ares-install`.
+#[derive(Serialize, Deserialize, Debug)]
+struct InstallRecord {
+    version: String,
+    sha256: String,
+}
+
+/// The `packageinfo.json` the device keeps for each installed package.
+#[derive(Deserialize, Debug)]
+struct InstalledPackage {
+    version: String,
+}
+
+/// What `--if-changed` does with a package.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    /// The app isn't on the device.
+    Install,
+    /// The device has an older version.
+    Upgrade { installed: String },
+    /// The device has this version, installed from a different package.
+    Reinstall,
+    /// The device has a newer version, and downgrades are allowed.
+    Downgrade { installed: String },
+    /// The device already has this version.
+    Unchanged,
+    /// The device has a newer version, and downgrades aren't allowed.
+    Newer { installed: String },
+}
+
+pub(crate) trait CheckInstalled {
+    /// Work out what installing the package `control` and `sha256` describe
+    /// would change on the device.
+    fn check_installed(
+        &self,
+        control: &PackageControl,
+        sha256: &str,
+        allow_downgrade: bool,
+    ) -> Result<Action, InstallError>;
+
+    /// Remember that the package is installed, for the next `--if-changed`.
+    fn record_install(&self, control: &PackageControl, sha256: &str) -> Result<(), TransferError>;
+
+    /// Forget the package `id` was installed from.
+    fn forget_install(&self, id: &str) -> Result<(), TransferError>;
+}
+
+impl CheckInstalled for DeviceSession {
+    fn check_installed(
+        &self,
+        control: &PackageControl,
+        sha256: &str,
+        allow_downgrade: bool,
+    ) -> Result<Action, InstallError> {
+        let installed = match &control.app_id {
+            Some(app_id) => {
+                let resp: ListAppsResponse = self.call(
+                    "luna://com.webos.applicationManager/dev/listApps",
+                    LunaEmptyPayload::default(),
+                    true,
+                )?;
+                resp.apps
+                    .into_iter()
+                    .find(|app| &app.id == app_id)
+                    .map(|app| app.version)
+            }
+            // No app to list, so read what the package left on the device.
+            None => read_json::<InstalledPackage>(
+                self,
+                &format!("{PACKAGES_DIR}/{}/packageinfo.json", control.id),
+            )
+            .map(|package| package.version),
+        };
+        let record = installed
+            .as_ref()
+            .and_then(|_| read_json(self, &record_path(control.installed_id())));
+        Ok(decide(
+            control,
+            sha256,
+            installed.as_deref(),
+            record.as_ref(),
+            allow_downgrade,
+        ))
+    }
+
+    fn record_install(&self, control: &PackageControl, sha256: &str) -> Result<(), TransferError> {
+        let record = InstallRecord {
+            version: control.version.clone(),
+            sha256: sha256.to_string(),
+        };
+        let data = serde_json::to_vec(&record).unwrap();
+        self.mkdir(Path::new(RECORD_DIR), 0o755)?;
+        self.put(
+            &mut Cursor::new(data),
+            record_path(control.installed_id()),
+            |_| {},
+        )
+    }
+
+    fn forget_install(&self, id: &str) -> Result<(), TransferError> {
+        self.rm(record_path(id))
+    }
+}
+
+fn record_path(id: &str) -> String {
+    format!("{RECORD_DIR}/{id}.json")
+}
+
+/// Pick the action for the package, given the installed version and the
+/// record left by the last install. A record for another version is out of
+/// date, as the app was installed some other way since.
+fn decide(
+    control: &PackageControl,
+    sha256: &str,
+    installed: Option<&str>,
+    record: Option<&InstallRecord>,
+    allow_downgrade: bool,
+) -> Action {
+    let Some(installed) = installed else {
+        return Action::Install;
+    };
+    match compare_versions(&control.version, installed) {
+        Ordering::Greater => Action::Upgrade {
+            installed: installed.to_string(),
+        },
+        Ordering::Less if allow_downgrade => Action::Downgrade {
+            installed: installed.to_string(),
+        },
+        Ordering::Less => Action::Newer {
+            installed: installed.to_string(),
+        },
+        Ordering::Equal => match record {
+            Some(record) if record.version == installed && record.sha256 != sha256 => {
+                Action::Reinstall
+            }
+            _ => Action::Unchanged,
+        },
+    }
+}
+
+/// Compare dotted versions part by part, as numbers where both parts are.
+/// Missing parts count as zero, so `1.0` equals `1.0.0`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a = a.trim().split('.');
+    let mut b = b.trim().split('.');
+    loop {
+        let (x, y) = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (x, y) => (x.unwrap_or("0"), y.unwrap_or("0")),
+        };
+        let order = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{Action, InstallRecord, PackageControl, compare_versions, decide};
+
+    fn package(version: &str) -> PackageControl {
+        PackageControl {
+            id: String::from("com.example.app"),
+            version: String::from(version),
+            app_id: Some(String::from("com.example.app")),
+        }
+    }
+
+    fn record(version: &str, sha256: &str) -> InstallRecord {
+        InstallRecord {
+            version: String::from(version),
+            sha256: String::from(sha256),
+        }
+    }
+
+    #[test]
+    fn versions_compare_by_number() {
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0.0", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.0.0-b", "1.0.0-a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn picks_an_action() {
+        let local = package("1.1.0");
+        assert_eq!(decide(&local, "aa", None, None, false), Action::Install);
+        assert_eq!(
+            decide(&local, "aa", Some("1.0.0"), None, false),
+            Action::Upgrade {
+                installed: String::from("1.0.0")
+            }
+        );
+        assert_eq!(
+            decide(&local, "aa", Some("1.2.0"), None, false),
+            Action::Newer {
+                installed: String::from("1.2.0")
+            }
+        );
+        assert_eq!(
+            decide(&local, "aa", Some("1.2.0"), None, true),
+            Action::Downgrade {
+                installed: String::from("1.2.0")
+            }
+        );
+    }
+
+    #[test]
+    fn same_version_checks_the_record() {
+        let local = package("1.0.0");
+        assert_eq!(
+            decide(&local, "aa", Some("1.0.0"), None, false),
+            Action::Unchanged
+        );
+        let same = record("1.0.0", "aa");
+        assert_eq!(
+            decide(&local, "aa", Some("1.0.0"), Some(&same), false),
+            Action::Unchanged
+        );
+        let rebuilt = record("1.0.0", "bb");
+        assert_eq!(
+            decide(&local, "aa", Some("1.0.0"), Some(&rebuilt), false),
+            Action::Reinstall
+        );
+        let stale = record("0.9.0", "bb");
+        assert_eq!(
+            decide(&local, "aa", Some("1.0.0"), Some(&stale), false),
+            Action::Unchanged
+        );
+    }
+}
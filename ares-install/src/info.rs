@@ -0,0 +1,238 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use ares_connection_lib::luna::{Luna, LunaEmptyPayload};
+use ares_connection_lib::session::DeviceSession;
+use ares_connection_lib::transfer::FileTransfer;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::install::InstallError;
+use crate::list::{App, ListAppsResponse, convert_json_to_list};
+use crate::storage::{disk_usage, find_dirs};
+
+pub(crate) trait AppDetails {
+    fn app_details(&self, id: &str) -> Result<AppReport, InstallError>;
+}
+
+/// Where an app came from, told apart by where it is installed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Origin {
+    /// Installed in developer mode, under `/media/developer`.
+    DevMode,
+    /// Installed from the LG Content Store, under `/media/cryptofs`.
+    Store,
+    /// Built into the firmware.
+    System,
+}
+
+/// Everything `--info` knows about an app.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AppReport {
+    pub id: String,
+    pub title: String,
+    pub version: String,
+    pub r#type: String,
+    pub origin: Origin,
+    /// The id is in webosbrew's `org.webosbrew.` namespace. That is how the
+    /// app is named, not where it was installed from.
+    pub webosbrew_id: bool,
+    pub location: Option<String>,
+    /// Bytes used by the app directory.
+    pub app_size: Option<u64>,
+    /// Bytes used by the app, its services, its package data and its data
+    /// directories together.
+    pub footprint: u64,
+    pub services: Vec<Directory>,
+    pub data_dirs: Vec<Directory>,
+    pub appinfo: Option<Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Directory {
+    pub path: String,
+    pub size: u64,
+}
+
+/// The `packageinfo.json` installed next to every app.
+#[derive(Deserialize, Debug, Default)]
+struct PackageInfo {
+    #[serde(default)]
+    services: Vec<String>,
+}
+
+impl AppDetails for DeviceSession {
+    fn app_details(&self, id: &str) -> Result<AppReport, InstallError> {
+        let resp: ListAppsResponse = self.call(
+            "luna://com.webos.applicationManager/dev/listApps",
+            LunaEmptyPayload::default(),
+            true,
+        )?;
+        let app = resp
+            .apps
+            .into_iter()
+            .find(|app| app.id == id)
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, format!("{id} is not installed")))?;
+        let location = app.folder_path().map(String::from);
+        let root = location.as_deref().and_then(|path| install_root(path, id));
+
+        let appinfo = location
+            .as_deref()
+            .and_then(|path| read_json::<Value>(self, &format!("{path}/appinfo.json")));
+        let service_dirs: Vec<String> = root
+            .and_then(|root| {
+                read_json::<PackageInfo>(
+                    self,
+                    &format!("{root}/usr/palm/packages/{id}/packageinfo.json"),
+                )
+            })
+            .unwrap_or_default()
+            .services
+            .iter()
+            .filter_map(|service| root.map(|root| format!("{root}/usr/palm/services/{service}")))
+            .collect();
+        let package_dir = root.map(|root| format!("{root}/usr/palm/packages/{id}"));
+        let data_dirs = data_dirs(self, &app)?;
+
+        let measured: Vec<&String> = location
+            .iter()
+            .chain(&package_dir)
+            .chain(&service_dirs)
+            .chain(&data_dirs)
+            .collect();
+        let sizes = disk_usage(self, &measured)?;
+        let directories = |paths: &[String]| -> Vec<Directory> {
+            paths
+                .iter()
+                .filter_map(|path| {
+                    sizes.get(path).map(|size| Directory {
+                        path: path.clone(),
+                        size: *size,
+                    })
+                })
+                .collect()
+        };
+
+        Ok(AppReport {
+            origin: origin(&app),
+            webosbrew_id: id.starts_with("org.webosbrew."),
+            app_size: location.as_ref().and_then(|path| sizes.get(path).copied()),
+            footprint: sizes.values().sum(),
+            services: directories(&service_dirs),
+            data_dirs: directories(&data_dirs),
+            location,
+            appinfo,
+            id: app.id,
+            title: app.title,
+            version: app.version,
+            r#type: app.r#type,
+        })
+    }
+}
+
+impl AppReport {
+    pub fn print(&self) {
+        let size = |size: Option<u64>| {
+            size.map_or_else(|| String::from("-"), |s| HumanBytes(s).to_string())
+        };
+        let origin = match self.origin {
+            Origin::DevMode => "developer mode",
+            Origin::Store => "content store",
+            Origin::System => "system",
+        };
+        if self.webosbrew_id {
+            println!("id        : {} (webosbrew)", self.id);
+        } else {
+            println!("id        : {}", self.id);
+        }
+        println!("title     : {}", self.title);
+        println!("version   : {}", self.version);
+        println!("type      : {}", self.r#type);
+        println!("origin    : {origin}");
+        println!("location  : {}", self.location.as_deref().unwrap_or("-"));
+        println!("app size  : {}", size(self.app_size));
+        println!("footprint : {}", HumanBytes(self.footprint));
+        for service in &self.services {
+            println!(
+                "service   : {} ({})",
+                service.path,
+                HumanBytes(service.size)
+            );
+        }
+        for dir in &self.data_dirs {
+            println!("data      : {} ({})", dir.path, HumanBytes(dir.size));
+        }
+        if let Some(appinfo) = &self.appinfo {
+            println!();
+            println!("appinfo.json");
+            print!("{}", convert_json_to_list(appinfo, 1));
+        }
+    }
+}
+
+/// The directory an app's package was installed into, such as
+/// `/media/developer/apps` for `/media/developer/apps/usr/palm/applications/ID`.
+fn install_root<'a>(folder: &'a str, id: &str) -> Option<&'a str> {
+    folder
+        .trim_end_matches('/')
+        .strip_suffix(id)?
+        .strip_suffix("/usr/palm/applications/")
+}
+
+fn origin(app: &App) -> Origin {
+    match app.folder_path() {
+        Some(path) if path.starts_with("/media/developer/") => Origin::DevMode,
+        Some(path) if path.starts_with("/media/cryptofs/") => Origin::Store,
+        _ => Origin::System,
+    }
+}
+
+/// Where an app keeps its data. Native apps run jailed with a home of their
+/// own. Web apps get a directory named after them under the web app manager,
+/// whose directory name changes with the webOS version.
+fn data_dirs(session: &DeviceSession, app: &App) -> Result<Vec<String>, IoError> {
+    let mut dirs = vec![format!("/var/palm/jail/{}", app.id)];
+    if app.r#type == "web" {
+        dirs.extend(find_dirs(session, "/var/lib/webappmanager*", &app.id, 2)?);
+    }
+    Ok(dirs)
+}
+
+/// Read a JSON file from the device, or `None` if it is missing or invalid.
+pub(crate) fn read_json<T: for<'de> Deserialize<'de>>(
+    session: &DeviceSession,
+    path: &str,
+) -> Option<T> {
+    let mut data = Vec::new();
+    session.get(path, &mut data, |_| {}).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::install_root;
+
+    #[test]
+    fn install_root_is_above_the_app_directory() {
+        assert_eq!(
+            install_root(
+                "/media/developer/apps/usr/palm/applications/com.example.app",
+                "com.example.app"
+            ),
+            Some("/media/developer/apps")
+        );
+        assert_eq!(
+            install_root(
+                "/usr/palm/applications/com.webos.app.home/",
+                "com.webos.app.home"
+            ),
+            Some("")
+        );
+        assert_eq!(
+            install_root("/opt/apps/com.example.app", "com.example.app"),
+            None
+        );
+    }
+}
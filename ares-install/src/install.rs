@@ -1,30 +1,85 @@
+use std::cell::Cell;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{Error as IoError, ErrorKind};
-use std::path::Path;
-use std::time::Duration;
+use std::io::{BufReader, Error as IoError, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use ares_connection_lib::luna::{Luna, LunaError, Message};
+use ares_connection_lib::luna::{Luna, LunaError, Message, Subscription};
 use ares_connection_lib::session::DeviceSession;
-use ares_connection_lib::transfer::{FileTransfer, TransferError};
-use indicatif::{ProgressBar, ProgressStyle};
+use ares_connection_lib::transfer::{Transfer, TransferError};
+use ares_device_lib::cli::{Classify, ErrorKind as FailureKind, json_output};
+use ares_device_lib::prompt;
+use ares_package_lib::reader::IpkContents;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Error as JsonError;
 
+use crate::changed::CheckInstalled;
+
+/// Set by Ctrl+C. Installs still running stop, and delete what they uploaded.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// How long to wait for an installer message before checking for Ctrl+C.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
 pub(crate) trait InstallApp {
-    fn install_app<P: AsRef<Path>>(&self, package: P) -> Result<(), InstallError>;
+    /// Upload `package` and install it. Returns the package id the installer
+    /// reports. With a `timeout`, the install is given up as hung once the
+    /// installer has said nothing for that long.
+    fn install_app(
+        &self,
+        package: &LocalPackage,
+        progress: &InstallProgress,
+        timeout: Option<Duration>,
+    ) -> Result<String, InstallError>;
+}
+
+/// A package file. Its control file is only read for `--if-changed`, so a
+/// plain install works with any package the device takes.
+pub(crate) struct LocalPackage {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub control: Option<PackageControl>,
+}
+
+/// The id and version from a package's control file, and the id of its app.
+pub(crate) struct PackageControl {
+    /// The `Package` field, which a project file can set apart from the app
+    /// id.
+    pub id: String,
+    pub version: String,
+    /// The id in the packaged `appinfo.json`. `None` for a package with
+    /// services only, which the device doesn't list as an app.
+    pub app_id: Option<String>,
+}
+
+/// One progress bar per device, for installs that run at the same time.
+/// With more than one device, every bar and message starts with its name.
+pub(crate) struct InstallProgress {
+    bars: MultiProgress,
+    device_width: Option<usize>,
 }
 
 #[derive(Debug)]
 pub enum InstallError {
     Response { error_code: i32, reason: String },
     ChecksumMismatch { expected: String, actual: String },
+    Timeout(Duration),
+    Cancelled,
     Luna(LunaError),
     Transfer(TransferError),
     Io(IoError),
 }
 
+/// What the installer says while it works.
+pub(crate) struct InstallStatus {
+    pub state: String,
+    pub percent: Option<u64>,
+}
+
 impl Display for InstallError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -35,6 +90,12 @@ impl Display for InstallError {
                 f,
                 "uploaded package is corrupted: expected sha256 {expected}, device has {actual}"
             ),
+            InstallError::Timeout(timeout) => write!(
+                f,
+                "installer gave no word for {} seconds, it may be hung",
+                timeout.as_secs()
+            ),
+            InstallError::Cancelled => write!(f, "cancelled"),
             InstallError::Luna(e) => write!(f, "{e}"),
             InstallError::Transfer(e) => write!(f, "{e}"),
             InstallError::Io(e) => write!(f, "{e}"),
@@ -44,6 +105,62 @@ impl Display for InstallError {
 
 impl std::error::Error for InstallError {}
 
+impl Classify for InstallError {
+    fn error_kind(&self) -> FailureKind {
+        match self {
+            InstallError::Response { .. } => FailureKind::Device,
+            InstallError::Transfer(e) => e.error_kind(),
+            InstallError::ChecksumMismatch { .. } => FailureKind::Transfer,
+            InstallError::Timeout(_) => FailureKind::Timeout,
+            InstallError::Cancelled => FailureKind::Cancelled,
+            InstallError::Luna(e) => e.error_kind(),
+            InstallError::Io(e) => e.error_kind(),
+        }
+    }
+
+    /// The exit code for this error, so scripts can tell failures apart.
+    fn exit_code(&self) -> i32 {
+        match self {
+            InstallError::Transfer(_) => 3,
+            InstallError::ChecksumMismatch { .. } => 4,
+            InstallError::Response { .. } | InstallError::Luna(LunaError::Service { .. }) => 5,
+            InstallError::Timeout(_) => 6,
+            InstallError::Cancelled => 130,
+            InstallError::Luna(e) => e.exit_code(),
+            InstallError::Io(_) => 1,
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        match self {
+            InstallError::Luna(e) => e.hint(),
+            _ => None,
+        }
+    }
+}
+
+/// Stop every install that is running. Returns whether this was already
+/// done, so a second Ctrl+C can quit at once.
+pub(crate) fn cancel() -> bool {
+    CANCELLED.swap(true, Ordering::SeqCst)
+}
+
+fn cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// A reader that fails once Ctrl+C is pressed, to stop an upload early.
+struct Cancellable<R>(R);
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if cancelled() {
+            return Err(IoError::other("Cancelled"));
+        }
+        self.0.read(buf)
+    }
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct InstallPayload {
@@ -64,70 +181,225 @@ struct InstallResponseDetails {
     state: Option<String>,
     error_code: Option<i32>,
     reason: Option<String>,
+    progress: Option<f64>,
+}
+
+impl LocalPackage {
+    /// Open the package at `path`, and read its control file with
+    /// `read_control`.
+    pub fn open(path: PathBuf, read_control: bool) -> Result<Self, InstallError> {
+        let control = if read_control {
+            Some(PackageControl::read(&path)?)
+        } else {
+            None
+        };
+        let sha256 = sha256::try_digest(&path).map_err(|e| {
+            IoError::other(format!(
+                "Failed to generate checksum for {}: {e:?}",
+                path.to_string_lossy()
+            ))
+        })?;
+        Ok(Self {
+            path,
+            sha256,
+            control,
+        })
+    }
+}
+
+impl PackageControl {
+    fn read(path: &Path) -> Result<Self, InstallError> {
+        let contents = IpkContents::read(BufReader::new(File::open(path)?))?;
+        let field = |name| {
+            contents.field(name).map(String::from).ok_or_else(|| {
+                IoError::new(
+                    ErrorKind::InvalidData,
+                    format!("Package has no {name} field"),
+                )
+            })
+        };
+        Ok(Self {
+            id: field("Package")?,
+            version: field("Version")?,
+            app_id: contents.app_id(),
+        })
+    }
+
+    /// The id the device knows the package by: the app's, or for services
+    /// only, the package's.
+    pub fn installed_id(&self) -> &str {
+        self.app_id.as_deref().unwrap_or(&self.id)
+    }
+}
+
+impl InstallProgress {
+    pub fn new<S: AsRef<str>>(devices: &[S]) -> Self {
+        let device_width = devices.iter().map(|d| d.as_ref().len()).max();
+        let bars = MultiProgress::new();
+        // A password or passphrase asked while connecting would be drawn over.
+        let hidden = bars.clone();
+        prompt::set_suspend(Some(Box::new(move |ask| hidden.suspend(ask))));
+        Self {
+            bars,
+            device_width: device_width.filter(|_| devices.len() > 1),
+        }
+    }
+
+    /// Print a message to stdout, or to stderr with `--output json`, where
+    /// stdout is kept for the result.
+    pub fn println(&self, device: &str, line: &str) {
+        let line = self.label(device, line);
+        if json_output() {
+            self.bars.suspend(|| eprintln!("{line}"));
+        } else {
+            self.bars.suspend(|| println!("{line}"));
+        }
+    }
+
+    pub fn eprintln(&self, device: &str, line: &str) {
+        let line = self.label(device, line);
+        self.bars.suspend(|| eprintln!("{line}"));
+    }
+
+    fn label(&self, device: &str, line: &str) -> String {
+        match self.device_width {
+            Some(_) => format!("{device}: {line}"),
+            None => line.to_string(),
+        }
+    }
+
+    fn add(&self, len: u64) -> ProgressBar {
+        self.bars.add(ProgressBar::new(len))
+    }
+
+    fn set_stage(&self, pb: &ProgressBar, device: &str, stage: &'static str) {
+        match self.device_width {
+            Some(width) => pb.set_prefix(format!("{device:<width$} {stage}")),
+            None => pb.set_prefix(stage),
+        }
+    }
+
+    /// Show installer status on `pb`. It turns into a bar at the first
+    /// percentage, as not every installer sends one.
+    fn show_status<'a>(&'a self, pb: &'a ProgressBar) -> impl Fn(InstallStatus) + 'a {
+        let has_percent = Cell::new(false);
+        move |status| {
+            if let Some(percent) = status.percent {
+                if !has_percent.replace(true) {
+                    pb.set_length(100);
+                    pb.set_style(self.style("{spinner} {pos:>3}% [{wide_bar}]"));
+                }
+                pb.set_position(percent);
+            }
+            pb.set_message(
+                status
+                    .state
+                    .strip_prefix("installing : ")
+                    .unwrap_or(&status.state)
+                    .to_string(),
+            );
+        }
+    }
+
+    /// A bar style of `template` after the prefix, which is wide enough for
+    /// the device name and the stage.
+    fn style(&self, template: &str) -> ProgressStyle {
+        let width = self.device_width.map_or(10, |width| width + 11);
+        ProgressStyle::with_template(&format!("{{prefix:{width}.bold.dim}} {template}")).unwrap()
+    }
+}
+
+impl Drop for InstallProgress {
+    fn drop(&mut self) {
+        prompt::set_suspend(None);
+    }
 }
 
 impl InstallApp for DeviceSession {
-    fn install_app<P: AsRef<Path>>(&self, package: P) -> Result<(), InstallError> {
-        let mut file = File::open(&package)?;
+    fn install_app(
+        &self,
+        package: &LocalPackage,
+        progress: &InstallProgress,
+        timeout: Option<Duration>,
+    ) -> Result<String, InstallError> {
+        if cancelled() {
+            return Err(InstallError::Cancelled);
+        }
+        let device = self.device.name.as_str();
+        let mut file = File::open(&package.path)?;
         let file_size = file.metadata()?.len();
-        let checksum = sha256::try_digest(package.as_ref()).map_err(|e| {
-            IoError::new(
-                ErrorKind::Other,
-                format!(
-                    "Failed to generate checksum for {}: {:?}",
-                    package.as_ref().to_string_lossy(),
-                    e
-                ),
-            )
-        })?;
+        let checksum = &package.sha256;
         let ipk_path = format!("/media/developer/temp/ares_install_{}.ipk", &checksum[..10]);
 
         let package_display_name = package
-            .as_ref()
+            .path
             .file_name()
             .map(|s| s.to_string_lossy())
-            .unwrap_or_else(|| package.as_ref().to_string_lossy());
+            .unwrap_or_else(|| package.path.to_string_lossy());
 
-        self.mkdir(Path::new("/media/developer/temp"), 0o777)?;
+        // One transport for the whole install, picked with what the probe
+        // found the device to support.
+        let checks_upload = self.capabilities().map_or(true, |c| c.sha256sum);
+        let transfer = Transfer::open(self);
+        transfer.mkdir(Path::new("/media/developer/temp"), 0o777)?;
 
-        let pb = ProgressBar::new(file_size);
-        pb.suspend(|| {
-            println!(
-                "Uploading {} to {}...",
-                package_display_name, self.device.name
-            )
-        });
+        let pb = progress.add(file_size);
+        progress.println(
+            device,
+            &format!("Uploading {package_display_name} to {device}..."),
+        );
         pb.enable_steady_tick(Duration::from_millis(50));
-        pb.set_prefix("Uploading");
-        pb.set_style(ProgressStyle::with_template("{prefix:10.bold.dim} {spinner} {percent:>3}% [{wide_bar}] {bytes}/{total_bytes}  {eta} ETA")
-            .unwrap());
+        progress.set_stage(&pb, device, "Uploading");
+        pb.set_style(
+            progress.style("{spinner} {percent:>3}% [{wide_bar}] {bytes}/{total_bytes}  {eta} ETA"),
+        );
 
-        self.put(&mut file, &ipk_path, |transferred| {
-            pb.set_position(transferred as u64);
-        })?;
+        let uploaded = transfer
+            .put(&mut Cancellable(&mut file), &ipk_path, |transferred| {
+                pb.set_position(transferred as u64);
+            })
+            .map_err(|e| {
+                if cancelled() {
+                    InstallError::Cancelled
+                } else {
+                    e.into()
+                }
+            });
 
-        let spinner_style =
-            ProgressStyle::with_template("{prefix:10.bold.dim} {spinner} {wide_msg}").unwrap();
-        pb.set_style(spinner_style);
+        pb.set_style(progress.style("{spinner} {wide_msg}"));
 
-        pb.set_prefix("Verifying");
-        pb.set_message("Checking uploaded package");
-        let verified = verify_upload(self, &ipk_path, &checksum);
-        if let Err(e) = &verified {
-            pb.suspend(|| eprintln!("Upload of {package_display_name} is broken: {e:?}"));
-        }
+        let verified = uploaded.and_then(|()| {
+            if !checks_upload {
+                progress.println(
+                    device,
+                    &format!("{device} has no sha256sum, so the upload isn't checked."),
+                );
+                return Ok(());
+            }
+            progress.set_stage(&pb, device, "Verifying");
+            pb.set_message("Checking uploaded package");
+            let verified = verify_upload(&transfer, &ipk_path, checksum);
+            if let Err(e) = &verified {
+                progress.eprintln(
+                    device,
+                    &format!("Upload of {package_display_name} is broken: {e:?}"),
+                );
+            }
+            verified
+        });
 
-        let result = verified.and_then(|_| {
-            pb.suspend(|| {
-                println!(
-                    "Installing {} on {}...",
-                    package_display_name, self.device.name
-                )
-            });
-            pb.set_prefix("Installing");
+        let result = verified.and_then(|()| {
+            if cancelled() {
+                return Err(InstallError::Cancelled);
+            }
+            progress.println(
+                device,
+                &format!("Installing {package_display_name} on {device}..."),
+            );
+            progress.set_stage(&pb, device, "Installing");
             pb.set_message("");
 
-            match self.subscribe(
+            let subscription = self.subscribe(
                 "luna://com.webos.appInstallService/dev/install",
                 InstallPayload {
                     id: String::from("com.ares.defaultName"),
@@ -135,55 +407,70 @@ impl InstallApp for DeviceSession {
                     subscribe: true,
                 },
                 true,
-            ) {
-                Ok(subscription) => subscription
-                    .filter_map(|item| {
-                        map_installer_message(
-                            item,
-                            &Regex::new(r"(?i)installed").unwrap(),
-                            |progress| {
-                                pb.set_message(
-                                    progress
-                                        .strip_prefix("installing : ")
-                                        .unwrap_or(&progress)
-                                        .to_string(),
-                                );
-                            },
-                        )
-                    })
-                    .next()
-                    .unwrap_or_else(|| Ok(String::new())),
-                Err(e) => Err(e.into()),
-            }
+            )?;
+            wait_for_install(subscription, timeout, progress.show_status(&pb))
         });
 
         if let Ok(package_id) = &result {
-            pb.suspend(|| println!("Installed package {}!", package_id));
+            progress.println(device, &format!("Installed package {package_id}!"));
+            if let Some(control) = &package.control
+                && let Err(e) = self.record_install(control, checksum)
+            {
+                progress.eprintln(
+                    device,
+                    &format!("Failed to record the installed package: {e}"),
+                );
+            }
         }
-        pb.suspend(|| println!("Deleting uploaded package..."));
+        progress.println(device, "Deleting uploaded package...");
 
-        pb.set_prefix("Cleanup");
+        progress.set_stage(&pb, device, "Cleanup");
         pb.set_message("Deleting uploaded package");
 
-        if let Err(e) = self.rm(&ipk_path) {
-            pb.suspend(|| {
-                eprintln!("Failed to delete {}: {:?}", ipk_path, e);
-            });
+        if let Err(e) = transfer.rm(&ipk_path) {
+            progress.eprintln(device, &format!("Failed to delete {ipk_path}: {e:?}"));
         }
         pb.finish_and_clear();
 
-        result?;
-        Ok(())
+        result
+    }
+}
+
+/// Read installer messages until the install ends. Gives up after `timeout`
+/// without a message, or when Ctrl+C is pressed.
+fn wait_for_install<F: Fn(InstallStatus)>(
+    mut subscription: Subscription,
+    timeout: Option<Duration>,
+    progress: F,
+) -> Result<String, InstallError> {
+    let expected = Regex::new(r"(?i)installed").unwrap();
+    let mut last_message = Instant::now();
+    loop {
+        if cancelled() {
+            return Err(InstallError::Cancelled);
+        }
+        let item = match subscription.next_before(Instant::now() + POLL_INTERVAL) {
+            None => return Ok(String::new()),
+            Some(Err(e)) if e.kind() == ErrorKind::TimedOut => {
+                if let Some(timeout) = timeout
+                    && last_message.elapsed() >= timeout
+                {
+                    return Err(InstallError::Timeout(timeout));
+                }
+                continue;
+            }
+            Some(item) => item,
+        };
+        last_message = Instant::now();
+        if let Some(result) = map_installer_message(item, &expected, &progress) {
+            return result;
+        }
     }
 }
 
 /// Compare the uploaded package against the local file. Devices without `sha256sum` skip the check.
-fn verify_upload(
-    session: &DeviceSession,
-    ipk_path: &str,
-    expected: &str,
-) -> Result<(), InstallError> {
-    let Some(actual) = session.sha256sum(ipk_path)? else {
+fn verify_upload(transfer: &Transfer, ipk_path: &str, expected: &str) -> Result<(), InstallError> {
+    let Some(actual) = transfer.sha256sum(ipk_path)? else {
         return Ok(());
     };
     if actual != expected {
@@ -195,13 +482,13 @@ fn verify_upload(
     Ok(())
 }
 
-pub(crate) fn map_installer_message<F: Fn(String)>(
+pub(crate) fn map_installer_message<F: Fn(InstallStatus)>(
     item: std::io::Result<Message>,
     expected: &Regex,
     progress: F,
 ) -> Option<Result<String, InstallError>> {
     match item {
-        Ok(message) => match message.deserialize::<InstallResponse>() {
+        Ok(message) => match message.response::<InstallResponse>() {
             Ok(resp) => {
                 if let Some(details) = resp.details {
                     if let Some(state) = details.state {
@@ -215,7 +502,8 @@ pub(crate) fn map_installer_message<F: Fn(String)>(
                         {
                             return Some(Ok(details.package_id.unwrap_or(String::from(""))));
                         } else {
-                            progress(state);
+                            let percent = install_percent(details.progress, &state);
+                            progress(InstallStatus { state, percent });
                         }
                     }
                 }
@@ -227,6 +515,20 @@ pub(crate) fn map_installer_message<F: Fn(String)>(
     }
 }
 
+/// How far the install is, from the `progress` field or else a percentage
+/// in the state, such as `installing : 45%`.
+fn install_percent(progress: Option<f64>, state: &str) -> Option<u64> {
+    let percent = progress.or_else(|| {
+        let captures = Regex::new(r"(\d+(?:\.\d+)?)\s*%")
+            .unwrap()
+            .captures(state)?;
+        captures[1].parse().ok()
+    })?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let percent = percent.clamp(0.0, 100.0) as u64;
+    Some(percent)
+}
+
 impl From<LunaError> for InstallError {
     fn from(value: LunaError) -> Self {
         Self::Luna(value)
@@ -253,3 +555,20 @@ impl From<JsonError> for InstallError {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::install_percent;
+
+    #[test]
+    fn percent_comes_from_progress_or_the_state() {
+        assert_eq!(install_percent(Some(42.0), "installing"), Some(42));
+        assert_eq!(install_percent(None, "installing : 45%"), Some(45));
+        assert_eq!(
+            install_percent(None, "IPK download current : 7.5 %"),
+            Some(7)
+        );
+        assert_eq!(install_percent(Some(250.0), ""), Some(100));
+        assert_eq!(install_percent(None, "installing"), None);
+    }
+}
@@ -1,19 +1,35 @@
 use std::fmt::Write;
 
-use ares_connection_lib::luna::{Luna, LunaEmptyPayload};
+use ares_connection_lib::luna::{Luna, LunaEmptyPayload, LunaError};
+use ares_device_lib::cli::{print_json, print_table};
+use indicatif::HumanBytes;
 use libssh_rs::Session;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
+use crate::storage::disk_usage;
+
 pub(crate) trait ListApps {
-    fn list_apps(&self, list_full: bool, type_filter: Option<&str>);
+    fn list_apps(&self, format: ListFormat, type_filter: Option<&str>) -> Result<(), LunaError>;
+}
+
+/// How `--list` prints the apps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ListFormat {
+    /// One id per line.
+    Ids,
+    /// Every field, as `--listfull` does.
+    Full,
+    /// A table of id, version, type and size.
+    Table,
+    /// Every field and the size, as a JSON array.
+    Json,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ListAppsResponse {
     pub apps: Vec<App>,
-    pub return_value: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -27,45 +43,84 @@ pub struct App {
     pub vendor: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub visible: Option<bool>,
+    /// Bytes used by the app directory, measured on the device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
     // Keep every remaining field so `--listfull` can render the full app info.
     #[serde(flatten)]
     pub extra: Map<String, Value>,
 }
 
+impl App {
+    /// Where the app is installed, as `listApps` reports it.
+    pub fn folder_path(&self) -> Option<&str> {
+        self.extra.get("folderPath").and_then(Value::as_str)
+    }
+}
+
 impl ListApps for Session {
-    fn list_apps(&self, list_full: bool, type_filter: Option<&str>) {
-        let resp: ListAppsResponse = self
-            .call(
-                "luna://com.webos.applicationManager/dev/listApps",
-                LunaEmptyPayload::default(),
-                true,
-            )
-            .unwrap();
-        for app in resp.apps {
+    fn list_apps(&self, format: ListFormat, type_filter: Option<&str>) -> Result<(), LunaError> {
+        let resp: ListAppsResponse = self.call(
+            "luna://com.webos.applicationManager/dev/listApps",
+            LunaEmptyPayload::default(),
+            true,
+        )?;
+        let mut apps: Vec<App> = resp
+            .apps
+            .into_iter()
             // Mirror the reference CLI: hide non-visible apps (non-signage profile).
-            if !app.visible.unwrap_or(false) {
-                continue;
+            .filter(|app| app.visible.unwrap_or(false))
+            .filter(|app| type_filter.is_none_or(|t| app.r#type == t))
+            .collect();
+        if matches!(format, ListFormat::Table | ListFormat::Json) {
+            let folders: Vec<&str> = apps.iter().filter_map(App::folder_path).collect();
+            let sizes = disk_usage(self, &folders)?;
+            for app in &mut apps {
+                app.size = app.folder_path().and_then(|path| sizes.get(path).copied());
             }
-            if type_filter.is_some_and(|t| app.r#type != t) {
-                continue;
+        }
+        match format {
+            ListFormat::Ids => {
+                for app in &apps {
+                    println!("{}", app.id);
+                }
             }
-            if list_full {
-                println!("id : {}", app.id);
-                if let Ok(Value::Object(mut obj)) = serde_json::to_value(&app) {
-                    obj.remove("id");
-                    print!("{}", convert_json_to_list(&Value::Object(obj), 0));
+            ListFormat::Full => {
+                for app in &apps {
+                    println!("id : {}", app.id);
+                    if let Ok(Value::Object(mut obj)) = serde_json::to_value(app) {
+                        obj.remove("id");
+                        print!("{}", convert_json_to_list(&Value::Object(obj), 0));
+                    }
+                    println!();
                 }
-                println!();
-            } else {
-                println!("{}", app.id);
             }
+            ListFormat::Table => {
+                let rows: Vec<[String; 4]> = apps
+                    .iter()
+                    .map(|app| {
+                        [
+                            app.id.clone(),
+                            app.version.clone(),
+                            app.r#type.clone(),
+                            app.size.map_or_else(
+                                || String::from("-"),
+                                |size| HumanBytes(size).to_string(),
+                            ),
+                        ]
+                    })
+                    .collect();
+                print_table(&["id", "version", "type", "size"], &rows);
+            }
+            ListFormat::Json => print_json(&apps),
         }
+        Ok(())
     }
 }
 
 /// Renders a JSON value into an indented text list, matching the reference CLI's
 /// `convertJsonToList`. Each nesting level is prefixed with one additional `-`.
-fn convert_json_to_list(value: &Value, level: usize) -> String {
+pub(crate) fn convert_json_to_list(value: &Value, level: usize) -> String {
     let prefix = "-".repeat(level);
     let mut out = String::new();
     match value {
@@ -1,30 +1,59 @@
+use std::io::Error as IoError;
 use std::path::PathBuf;
 use std::process::exit;
+use std::thread;
+use std::time::Duration;
 
-use ares_connection_lib::session::NewSession;
-use ares_device_lib::DeviceManager;
-use ares_device_lib::cli::unwrap_or_exit;
+use ares_connection_lib::session::{self, NewSession};
+use ares_device_lib::cli::{
+    CliError, ErrorKind, OutputFormat, for_each_device, json_output, print_json, print_line,
+    print_table, set_output_format, unwrap_or_exit,
+};
+use ares_device_lib::{Device, DeviceManager, prompt};
 use clap::Parser;
-use install::InstallApp;
-use list::ListApps;
+use install::{InstallApp, InstallProgress, LocalPackage, PackageControl};
+use list::{ListApps, ListFormat};
+use serde::Serialize;
 
+use crate::changed::{Action, CheckInstalled};
+use crate::info::AppDetails;
 use crate::remove::RemoveApp;
 
+mod changed;
+mod info;
 mod install;
 mod list;
 mod remove;
+mod storage;
 
 #[derive(Parser, Debug)]
 #[command(about)]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
     #[arg(
         short,
         long,
         value_name = "DEVICE",
         env = "ARES_DEVICE",
-        help = "Specify DEVICE to use"
+        value_delimiter = ',',
+        help = "Specify DEVICE to use, or @GROUP or tag:TAG for many. Separate several with commas"
     )]
-    device: Option<String>,
+    device: Vec<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["group", "list", "list_full", "remove", "info"],
+        requires = "package",
+        help = "Install on every device in the list. Wins over -d"
+    )]
+    all: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["list", "list_full", "remove", "info"],
+        requires = "package",
+        help = "Install on every device in group NAME, as -d @NAME does. Wins over -d"
+    )]
+    group: Option<String>,
     #[arg(short, long, group = "action", help = "List the installed apps")]
     list: bool,
     #[arg(
@@ -41,6 +70,19 @@ struct Cli {
         help = "Filter the listed apps by APP_TYPE"
     )]
     app_type: Option<String>,
+    #[arg(
+        long,
+        requires = "list",
+        help = "List the apps as a table of id, version, type and size"
+    )]
+    table: bool,
+    #[arg(
+        long,
+        group = "action",
+        value_name = "APP_ID",
+        help = "Show where APP_ID is installed, its size and its appinfo.json"
+    )]
+    info: Option<String>,
     #[arg(
         short,
         long,
@@ -55,37 +97,309 @@ struct Cli {
         help = "webOS package with .ipk extension"
     )]
     package: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "package",
+        help = "Skip the install if the device already has this version"
+    )]
+    if_changed: bool,
+    #[arg(
+        long,
+        requires = "if_changed",
+        help = "With --if-changed, install over a newer version on the device"
+    )]
+    allow_downgrade: bool,
+    #[arg(
+        long,
+        value_name = "SECS",
+        requires = "package",
+        help = "Give up on an install after SECS seconds without word from the installer"
+    )]
+    timeout: Option<u64>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
+}
+
+/// What `--output json` prints for each device an install ran on.
+#[derive(Serialize, Debug)]
+struct InstallResult<'a> {
+    device: &'a str,
+    /// From the package with `--if-changed`, or else from the installer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    /// Only known with `--if-changed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<&'a str>,
+    /// `installed`, `skipped` or `failed`.
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a CliError>,
+}
+
+/// What `--output json` prints after a remove.
+#[derive(Serialize, Debug)]
+struct RemoveResult<'a> {
+    id: &'a str,
+    result: &'static str,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    unwrap_or_exit(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
-    let Some(device) = unwrap_or_exit(manager.find_or_default(cli.device.as_ref()), "find device")
-    else {
-        eprintln!("Device not found");
-        exit(1);
+    let devices = unwrap_or_exit(select_devices(&manager, &cli), "find device");
+    if devices.is_empty() {
+        CliError::new(ErrorKind::NotFound, "The device list is empty").exit();
+    }
+    if let Some(package) = &cli.package {
+        let package = unwrap_or_exit(
+            LocalPackage::open(package.clone(), cli.if_changed),
+            "read the package",
+        );
+        install_all(&cli, &devices, &package);
+        return;
+    }
+    if let Some(id) = &cli.remove {
+        for_each_device(&devices, |device| remove(device, id));
+        return;
+    }
+    let [device] = devices.as_slice() else {
+        CliError::new(
+            ErrorKind::InvalidInput,
+            "--list and --info work on one device at a time",
+        )
+        .exit();
     };
     let session = unwrap_or_exit(device.new_session(), &format!("connect to {}", device.name));
     if cli.list || cli.list_full {
-        session.list_apps(cli.list_full, cli.app_type.as_deref());
-    } else if let Some(id) = cli.remove {
-        println!("Removing {id}...");
-        match session.remove_app(&id) {
-            Ok(_) => println!("{id} removed."),
-            Err(e) => {
-                eprintln!("Failed to remove {id}: {e}");
-                exit(1);
-            }
-        }
-    } else if let Some(package) = cli.package {
-        match session.install_app(package) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!("Failed to install: {e}");
-                exit(1);
-            }
+        let format = if json_output() {
+            ListFormat::Json
+        } else if cli.list_full {
+            ListFormat::Full
+        } else if cli.table {
+            ListFormat::Table
+        } else {
+            ListFormat::Ids
+        };
+        unwrap_or_exit(
+            session.list_apps(format, cli.app_type.as_deref()),
+            "list the apps",
+        );
+    } else if let Some(id) = &cli.info {
+        let report = unwrap_or_exit(session.app_details(id), &format!("read {id}"));
+        if json_output() {
+            print_json(&report);
+        } else {
+            report.print();
         }
     } else {
         Cli::parse_from(vec!["", "--help"]);
     }
 }
+
+/// The devices picked by `--all`, `--group` or `-d`, or the default device.
+/// `--all` and `--group` win over `-d`, which may only come from
+/// `ARES_DEVICE`. `--group NAME` is the same as `-d @NAME`.
+fn select_devices(manager: &DeviceManager, cli: &Cli) -> Result<Vec<Device>, IoError> {
+    if cli.all {
+        manager.list()
+    } else if let Some(group) = &cli.group {
+        manager.resolve(&[format!("@{group}")])
+    } else {
+        manager.resolve(&cli.device)
+    }
+}
+
+/// Remove the app `id` from one device.
+fn remove<'a>(device: &Device, id: &'a str) -> Result<RemoveResult<'a>, CliError> {
+    let session = device
+        .new_session()
+        .map_err(|e| CliError::failed_to(&format!("connect to {}", device.name), &e))?;
+    if !json_output() {
+        print_line(format!("Removing {id}..."));
+    }
+    session
+        .remove_app(id)
+        .map_err(|e| CliError::failed_to(&format!("remove {id}"), &e))?;
+    // Most apps have no record, so a failure here means nothing.
+    let _ = session.forget_install(id);
+    if !json_output() {
+        print_line(format!("{id} removed."));
+    }
+    Ok(RemoveResult {
+        id,
+        result: "removed",
+    })
+}
+
+/// Install `package` on every device at once. With more than one device, a
+/// table or a JSON array of the results follows. Any failure makes the exit
+/// code non-zero.
+fn install_all(cli: &Cli, devices: &[Device], package: &LocalPackage) {
+    let names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+    let progress = InstallProgress::new(&names);
+    // The first Ctrl+C stops the installs and deletes the uploads. The second
+    // quits at once.
+    let _ = ctrlc::set_handler(|| {
+        if install::cancel() {
+            exit(130);
+        }
+    });
+    let results: Vec<Result<Option<String>, CliError>> = thread::scope(|scope| {
+        let installs: Vec<_> = devices
+            .iter()
+            .map(|device| scope.spawn(|| install_on(cli, device, package, &progress)))
+            .collect();
+        installs
+            .into_iter()
+            .map(|install| {
+                install.join().unwrap_or_else(|_| {
+                    Err(CliError::new(
+                        ErrorKind::Other,
+                        "Failed to install: the install thread crashed",
+                    ))
+                })
+            })
+            .collect()
+    });
+
+    let summary: Vec<InstallResult> = names
+        .iter()
+        .zip(&results)
+        .map(|(name, result)| InstallResult {
+            device: name,
+            id: package.control.as_ref().map(|c| c.id.as_str()).or_else(|| {
+                result
+                    .as_ref()
+                    .ok()
+                    .and_then(Option::as_deref)
+                    .filter(|id| !id.is_empty())
+            }),
+            version: package.control.as_ref().map(|c| c.version.as_str()),
+            result: match result {
+                Ok(Some(_)) => "installed",
+                Ok(None) => "skipped",
+                Err(_) => "failed",
+            },
+            error: result.as_ref().err(),
+        })
+        .collect();
+    if let [result] = summary.as_slice() {
+        if let Some(error) = result.error {
+            error.exit();
+        }
+        if json_output() {
+            print_json(result);
+        }
+        return;
+    }
+    if json_output() {
+        print_json(&summary);
+    } else {
+        println!();
+        let rows: Vec<[String; 2]> = summary
+            .iter()
+            .map(|result| {
+                let status = match result.error {
+                    Some(error) => error.message.clone(),
+                    None => result.result.to_string(),
+                };
+                [result.device.to_string(), status]
+            })
+            .collect();
+        print_table(&["name", "result"], &rows);
+    }
+    let mut codes = results
+        .iter()
+        .filter_map(|r| r.as_ref().err())
+        .map(|e| e.exit_code);
+    if let Some(code) = codes.next() {
+        // Keep the code when every device failed the same way.
+        exit(if codes.all(|c| c == code) { code } else { 1 });
+    }
+}
+
+/// Install `package` on `device`. Returns the package id the installer
+/// reports, `None` when `--if-changed` skipped it, or what failed.
+fn install_on(
+    cli: &Cli,
+    device: &Device,
+    package: &LocalPackage,
+    progress: &InstallProgress,
+) -> Result<Option<String>, CliError> {
+    let session = device
+        .new_session()
+        .map_err(|e| CliError::failed_to(&format!("connect to {}", device.name), &e))?;
+    if let Some(control) = &package.control {
+        let action = session
+            .check_installed(control, &package.sha256, cli.allow_downgrade)
+            .map_err(|e| CliError::failed_to("check the installed version", &e))?;
+        if !report_action(&device.name, control, &action, progress) {
+            return Ok(None);
+        }
+    }
+    let timeout = cli.timeout.map(Duration::from_secs);
+    session
+        .install_app(package, progress, timeout)
+        .map(Some)
+        .map_err(|e| CliError::failed_to("install", &e))
+}
+
+/// Say what `--if-changed` decided, and whether to go on with the install.
+fn report_action(
+    device: &str,
+    control: &PackageControl,
+    action: &Action,
+    progress: &InstallProgress,
+) -> bool {
+    let (id, version) = (&control.id, &control.version);
+    let line = match action {
+        Action::Install => format!("{id} is not installed."),
+        Action::Upgrade { installed } => format!("Upgrading {id} from {installed} to {version}."),
+        Action::Reinstall => format!("{id} {version} was installed from a different package."),
+        Action::Downgrade { installed } => {
+            format!("Downgrading {id} from {installed} to {version}.")
+        }
+        Action::Unchanged => {
+            progress.println(
+                device,
+                &format!("{id} {version} is already installed, skipping."),
+            );
+            return false;
+        }
+        Action::Newer { installed } => {
+            progress.eprintln(
+                device,
+                &format!(
+                    "{id} {installed} on the device is newer than {version}, skipping. \
+                    Use --allow-downgrade to install it anyway."
+                ),
+            );
+            return false;
+        }
+    };
+    progress.println(device, &line);
+    true
+}
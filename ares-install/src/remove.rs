@@ -1,5 +1,6 @@
 use ares_connection_lib::luna::Luna;
 use ares_connection_lib::session::DeviceSession;
+use ares_device_lib::cli::{eprint_line, json_output, print_line};
 use regex::Regex;
 use serde::Serialize;
 
@@ -28,8 +29,12 @@ impl RemoveApp for DeviceSession {
         ) {
             Ok(subscription) => subscription
                 .filter_map(|item| {
-                    map_installer_message(item, &Regex::new(r"(?i)removed").unwrap(), |progress| {
-                        println!("{}", progress);
+                    map_installer_message(item, &Regex::new(r"(?i)removed").unwrap(), |status| {
+                        if json_output() {
+                            eprint_line(&status.state);
+                        } else {
+                            print_line(&status.state);
+                        }
                     })
                 })
                 .next(),
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::io::Error as IoError;
+
+use ares_connection_lib::transfer::exec;
+use libssh_rs::Session;
+
+/// Disk use of `paths` on the device in bytes, measured with `du`. Paths
+/// that don't exist are left out.
+pub(crate) fn disk_usage<S: AsRef<str>>(
+    session: &Session,
+    paths: &[S],
+) -> Result<HashMap<String, u64>, IoError> {
+    if paths.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let quoted: Vec<String> = paths
+        .iter()
+        .map(|path| snailquote::escape(path.as_ref()).into_owned())
+        .collect();
+    // du fails for a missing path, but still measures the others.
+    let (output, _) = exec(session, &format!("du -sk {} 2>/dev/null", quoted.join(" ")))
+        .map_err(IoError::other)?;
+    Ok(parse_du(&output))
+}
+
+/// Directories named `name` at most `depth` levels below those `pattern`
+/// matches on the device. `pattern` is a shell glob and goes to the shell as
+/// it is.
+pub(crate) fn find_dirs(
+    session: &Session,
+    pattern: &str,
+    name: &str,
+    depth: u32,
+) -> Result<Vec<String>, IoError> {
+    // find fails when the glob matches nothing, which means no directories.
+    let (output, _) = exec(
+        session,
+        &format!(
+            "find {pattern} -maxdepth {depth} -type d -name {} 2>/dev/null",
+            snailquote::escape(name)
+        ),
+    )
+    .map_err(IoError::other)?;
+    Ok(output.lines().map(String::from).collect())
+}
+
+/// Read `du -sk` output, one `KIB<tab>PATH` line per path.
+fn parse_du(output: &str) -> HashMap<String, u64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (size, path) = line.split_once('\t')?;
+            Some((path.to_string(), size.trim().parse::<u64>().ok()? * 1024))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_du;
+
+    #[test]
+    fn du_lines_become_bytes() {
+        let sizes = parse_du("12\t/media/developer/apps/a\n0\t/var/palm/jail/a b\nbad line\n");
+        assert_eq!(sizes["/media/developer/apps/a"], 12 * 1024);
+        assert_eq!(sizes["/var/palm/jail/a b"], 0);
+        assert_eq!(sizes.len(), 2);
+    }
+}
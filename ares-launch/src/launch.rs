@@ -1,37 +1,27 @@
-use std::process::exit;
-
 use ares_connection_lib::luna::Luna;
+use ares_device_lib::cli::CliError;
 use libssh_rs::Session;
 use serde_json::Value;
 
-use crate::{LaunchParams, LaunchResponse};
+use crate::LaunchParams;
 
 pub(crate) trait LaunchApp {
-    fn launch_app(&self, app_id: String, params: Value);
+    fn launch_app(&self, app_id: &str, params: Value) -> Result<(), CliError>;
 }
 
 impl LaunchApp for Session {
-    fn launch_app(&self, app_id: String, params: Value) {
-        let response: LaunchResponse = self
+    fn launch_app(&self, app_id: &str, params: Value) -> Result<(), CliError> {
+        let _: Value = self
             .call(
                 "luna://com.webos.applicationManager/launch",
                 &LaunchParams {
-                    id: app_id.clone(),
+                    id: app_id.to_string(),
                     subscribe: false,
                     params,
                 },
                 true,
             )
-            .unwrap();
-        if response.return_value {
-            println!("Launched application {app_id}");
-        } else {
-            eprintln!(
-                "Failed to launch {app_id}: {} ({})",
-                response.error_text.unwrap_or(String::from("unknown error")),
-                response.error_code.unwrap_or(-1)
-            );
-            exit(1);
-        }
+            .map_err(|e| CliError::failed_to(&format!("launch {app_id}"), &e))?;
+        Ok(())
     }
 }
@@ -1,10 +1,11 @@
-use std::process::exit;
-
-use ares_connection_lib::session::NewSession;
-use ares_device_lib::DeviceManager;
-use ares_device_lib::cli::unwrap_or_exit;
+use ares_connection_lib::session::{self, DeviceSession, NewSession};
+use ares_device_lib::cli::{
+    CliError, OutputFormat, for_each_device, json_output, print_line, set_output_format,
+    unwrap_or_exit,
+};
+use ares_device_lib::{Device, DeviceManager, prompt};
 use clap::Parser;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json::{Map, Value, json};
 
 use crate::close::CloseApp;
@@ -23,9 +24,10 @@ struct Cli {
         long,
         value_name = "DEVICE",
         env = "ARES_DEVICE",
-        help = "Specify DEVICE to use"
+        value_delimiter = ',',
+        help = "Specify DEVICE to use, or @GROUP or tag:TAG for many. Separate several with commas"
     )]
-    device: Option<String>,
+    device: Vec<String>,
     #[arg(short, long, group = "action", help = "Close a running app")]
     close: bool,
     #[arg(short, long, group = "action", help = "List running apps")]
@@ -39,6 +41,25 @@ struct Cli {
     params: Vec<String>,
     #[arg(value_name = "APP_ID", help = "An app id described in appinfo.json")]
     app_id: Option<String>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -49,32 +70,40 @@ struct LaunchParams {
     params: Value,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct LaunchResponse {
-    return_value: bool,
-    error_code: Option<i32>,
-    error_text: Option<String>,
+/// What `--output json` prints after a launch or a close.
+#[derive(Serialize, Debug)]
+struct AppResult<'a> {
+    id: &'a str,
+    result: &'static str,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    unwrap_or_exit(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
-    let Some(device) = unwrap_or_exit(manager.find_or_default(cli.device.as_ref()), "find device")
-    else {
-        eprintln!("Device not found");
-        exit(1);
-    };
-    let session = unwrap_or_exit(device.new_session(), &format!("connect to {}", device.name));
+    let devices = unwrap_or_exit(manager.resolve(&cli.device), "find device");
 
     if cli.running {
-        session.list_running();
+        for_each_device(&devices, |device| {
+            let running = connect(device)?.list_running()?;
+            if !json_output() {
+                for proc in &running {
+                    print_line(&proc.id);
+                }
+            }
+            Ok(running)
+        });
         return;
     }
-    if cli.app_id.is_none() {
+    let Some(app_id) = cli.app_id else {
         Cli::parse_from(vec!["", "--help"]);
         return;
-    }
+    };
 
     let mut params: Value = Value::Null;
     if !cli.params.is_empty() {
@@ -96,9 +125,28 @@ fn main() {
         }
         params = Value::Object(map);
     }
-    if cli.close {
-        session.close_app(cli.app_id.unwrap(), params);
-    } else {
-        session.launch_app(cli.app_id.unwrap(), params);
-    }
+    for_each_device(&devices, |device| {
+        let session = connect(device)?;
+        if cli.close {
+            session.close_app(&app_id, params.clone())?;
+            if !json_output() {
+                print_line(format!("Closed application {app_id}"));
+            }
+        } else {
+            session.launch_app(&app_id, params.clone())?;
+            if !json_output() {
+                print_line(format!("Launched application {app_id}"));
+            }
+        }
+        Ok(AppResult {
+            id: &app_id,
+            result: if cli.close { "closed" } else { "launched" },
+        })
+    });
+}
+
+fn connect(device: &Device) -> Result<DeviceSession, CliError> {
+    device
+        .new_session()
+        .map_err(|e| CliError::failed_to(&format!("connect to {}", device.name), &e))
 }
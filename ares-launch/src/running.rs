@@ -1,51 +1,34 @@
-use std::process::exit;
-
 use ares_connection_lib::luna::Luna;
+use ares_device_lib::cli::CliError;
 use libssh_rs::Session;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 pub(crate) trait ListRunning {
-    fn list_running(&self);
+    fn list_running(&self) -> Result<Vec<RunningProcess>, CliError>;
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct ListRunningResponse {
-    return_value: bool,
-    error_code: Option<i32>,
-    error_text: Option<String>,
     running: Option<Vec<RunningProcess>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct RunningProcess {
-    id: String,
+pub(crate) struct RunningProcess {
+    pub id: String,
 }
 
 impl ListRunning for Session {
-    fn list_running(&self) {
+    fn list_running(&self) -> Result<Vec<RunningProcess>, CliError> {
         let response: ListRunningResponse = self
             .call(
                 "luna://com.webos.applicationManager/dev/running",
                 json!({"subscribe":false}),
                 true,
             )
-            .unwrap();
-        if response.return_value {
-            if let Some(running) = response.running {
-                for proc in running {
-                    println!("{}", proc.id);
-                }
-            }
-        } else {
-            eprintln!(
-                "Failed to list running apps: {} ({})",
-                response.error_text.unwrap_or(String::from("unknown error")),
-                response.error_code.unwrap_or(-1)
-            );
-            exit(1);
-        }
+            .map_err(|e| CliError::failed_to("list running apps", &e))?;
+        Ok(response.running.unwrap_or_default())
     }
 }
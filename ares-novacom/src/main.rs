@@ -1,17 +1,21 @@
 use std::io::{Error as IoError, ErrorKind, Read, Write};
 use std::net::{TcpListener, TcpStream};
 use std::path::Path;
-use std::process::exit;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
 use ares_connection_lib::DeviceSetupManager;
-use ares_connection_lib::session::{DeviceSession, NewSession};
-use ares_device_lib::cli::unwrap_or_exit;
-use ares_device_lib::{DeviceManager, PrivateKey};
+use ares_connection_lib::keepalive::ReconnectingSession;
+use ares_connection_lib::session::{self, DeviceSession};
+use ares_device_lib::cli::{
+    CliError, ErrorKind as FailureKind, OutputFormat, json_output, print_json, set_output_format,
+    unwrap_or_exit,
+};
+use ares_device_lib::{DeviceManager, PrivateKey, prompt};
 use clap::Parser;
-use libssh_rs::Error as SshError;
+use libssh_rs::{Channel, Error as SshError};
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 #[command(about)]
@@ -52,10 +56,52 @@ struct Cli {
         help = "Port to forward: the device port, optionally mapped to a host port"
     )]
     port: Option<String>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
+}
+
+/// What `--output json` prints once a forward is listening.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ForwardResult<'a> {
+    device: &'a str,
+    host_port: u16,
+    device_port: u16,
+}
+
+/// What `--output json` prints after `--getkey`.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct KeyResult<'a> {
+    device: &'a str,
+    key_path: &'a Path,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    unwrap_or_exit(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
 
     if cli.getkey {
@@ -75,34 +121,42 @@ fn main() {
 /// through the device's SSH session to `localhost:<device_port>` on the device.
 fn forward(manager: &DeviceManager, device: Option<&str>, port_spec: Option<&str>) {
     let Some(port_spec) = port_spec else {
-        eprintln!("--port is required with --forward (DEVICE_PORT[:HOST_PORT])");
-        exit(1);
-    };
-    let (device_port, host_port) = match parse_port(port_spec) {
-        Ok(ports) => ports,
-        Err(e) => {
-            eprintln!("{e}");
-            exit(1);
-        }
+        CliError::new(
+            FailureKind::InvalidInput,
+            "--port is required with --forward (DEVICE_PORT[:HOST_PORT])",
+        )
+        .exit();
     };
+    let (device_port, host_port) = parse_port(port_spec)
+        .unwrap_or_else(|e| CliError::new(FailureKind::InvalidInput, e).exit());
 
     let Some(device) = unwrap_or_exit(manager.find_or_default(device.as_ref()), "find device")
     else {
-        eprintln!("Device not found");
-        exit(1);
+        CliError::new(FailureKind::NotFound, "Device not found").exit();
     };
 
-    let session = unwrap_or_exit(device.new_session(), &format!("connect to {}", device.host));
-    let session = Arc::new(session);
+    // A forward runs until Ctrl+C, so it outlives naps and Wi-Fi drops.
+    let session = unwrap_or_exit(
+        ReconnectingSession::open(&device),
+        &format!("connect to {}", device.host),
+    );
 
     let listener = unwrap_or_exit(
         TcpListener::bind(("127.0.0.1", host_port)),
         "bind the host port",
     );
-    println!(
-        "Forwarding 127.0.0.1:{host_port} -> localhost:{device_port} on {}. Press Ctrl+C to stop.",
-        device.name
-    );
+    if json_output() {
+        print_json(&ForwardResult {
+            device: &device.name,
+            host_port,
+            device_port,
+        });
+    } else {
+        println!(
+            "Forwarding 127.0.0.1:{host_port} -> localhost:{device_port} on {}. Press Ctrl+C to stop.",
+            device.name
+        );
+    }
 
     for stream in listener.incoming() {
         match stream {
@@ -140,11 +194,12 @@ fn parse_port(spec: &str) -> Result<(u16, u16), String> {
 /// Pumps bytes both ways between a local TCP connection and an SSH forwarding
 /// channel until either side closes. Uses short polling timeouts so a single
 /// SSH session can service several connections without one blocking the others.
-fn bridge(session: &DeviceSession, mut tcp: TcpStream, device_port: u16) -> Result<(), IoError> {
-    let channel = session.new_channel().map_err(to_io)?;
-    channel
-        .open_forward("localhost", device_port, "127.0.0.1", 0)
-        .map_err(to_io)?;
+fn bridge(
+    session: &ReconnectingSession,
+    mut tcp: TcpStream,
+    device_port: u16,
+) -> Result<(), IoError> {
+    let channel = open_forward(session, device_port)?;
     tcp.set_read_timeout(Some(Duration::from_millis(10)))?;
 
     let mut buf = [0u8; 16 * 1024];
@@ -180,6 +235,29 @@ fn bridge(session: &DeviceSession, mut tcp: TcpStream, device_port: u16) -> Resu
     Ok(())
 }
 
+/// Open a forwarding channel on the live session. When that fails because the
+/// session has dropped, reconnect and try once more.
+fn open_forward(session: &ReconnectingSession, device_port: u16) -> Result<Channel, IoError> {
+    let live = session.session();
+    match forward_channel(&live, device_port) {
+        Err(_) if !live.is_connected() => {
+            let live = session
+                .reconnect(&live)
+                .map_err(|e| IoError::other(e.to_string()))?;
+            forward_channel(&live, device_port)
+        }
+        result => result,
+    }
+}
+
+fn forward_channel(session: &DeviceSession, device_port: u16) -> Result<Channel, IoError> {
+    let channel = session.new_channel().map_err(to_io)?;
+    channel
+        .open_forward("localhost", device_port, "127.0.0.1", 0)
+        .map_err(to_io)?;
+    Ok(channel)
+}
+
 fn would_block(e: &IoError) -> bool {
     matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
 }
@@ -191,11 +269,12 @@ fn to_io(e: SshError) -> IoError {
 fn get_key(manager: &DeviceManager, device: Option<&str>, passphrase: &str) {
     let Some(device) = unwrap_or_exit(manager.find_or_default(device.as_ref()), "find device")
     else {
-        eprintln!("Device not found");
-        exit(1);
+        CliError::new(FailureKind::NotFound, "Device not found").exit();
     };
 
-    println!("Fetching key from {}...", device.host);
+    if !json_output() {
+        println!("Fetching key from {}...", device.host);
+    }
     let content = unwrap_or_exit(
         manager.novacom_getkey(&device.host, passphrase),
         "fetch key",
@@ -216,13 +295,26 @@ fn get_key(manager: &DeviceManager, device: Option<&str>, passphrase: &str) {
     updated.password = None;
     updated.username = String::from("prisoner");
     updated.port = 9922;
+    if let Some(secret_ref) = &device.secret_ref {
+        unwrap_or_exit(
+            manager.store_secrets(&mut updated, secret_ref.store),
+            "store the passphrase",
+        );
+    }
     unwrap_or_exit(manager.modify(&device.name, &updated), "update device");
 
-    println!(
-        "Saved key to {} and updated device {}.",
-        key_path.display(),
-        device.name
-    );
+    if json_output() {
+        print_json(&KeyResult {
+            device: &device.name,
+            key_path: &key_path,
+        });
+    } else {
+        println!(
+            "Saved key to {} and updated device {}.",
+            key_path.display(),
+            device.name
+        );
+    }
 }
 
 /// Builds the local key filename from the key itself, as `webos_` plus the
@@ -1,22 +1,25 @@
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
 
-use ar::Builder;
+use ares_device_lib::cli::{
+    Classify, CliError, ErrorKind, OutputFormat, json_output, print_json, set_output_format,
+    unwrap_or_exit,
+};
+use ares_package_lib::ParseFrom;
+use ares_package_lib::builder::PackageBuilder;
+use ares_package_lib::diff::IpkDiff;
+use ares_package_lib::error::PackageError;
+use ares_package_lib::input::control::ControlFile;
+use ares_package_lib::input::project::ProjectInfo;
+use ares_package_lib::input::validation::PackageArch;
+use ares_package_lib::manifest::{ManifestLinks, RepoManifest};
+use ares_package_lib::packaging::compression::{Codec, Compression};
+use ares_package_lib::reader::IpkContents;
 use clap::Parser;
 use serde::Serialize;
 
-use crate::input::data::DataInfo;
-use crate::input::validation::{PackageArch, Validation};
-use crate::packaging::control::{AppendControl, ControlInfo};
-use crate::packaging::data::AppendData;
-use crate::packaging::header::AppendHeader;
-
-mod input;
-mod packaging;
-
 #[derive(Parser, Debug)]
 #[command(about)]
 struct Cli {
@@ -34,6 +37,13 @@ struct Cli {
         help = "Exclude files, given as a PATTERN"
     )]
     app_exclude: Vec<String>,
+    #[arg(
+        short = 'x',
+        long,
+        value_name = "PATTERN",
+        help = "Mark files matching PATTERN as executable (repeatable)"
+    )]
+    executable: Vec<String>,
     #[arg(
         short = 'A',
         long,
@@ -41,72 +51,277 @@ struct Cli {
         help = "Explicitly specify the architecture"
     )]
     force_arch: Option<PackageArch>,
-    #[arg(help = "App directory containing a valid appinfo.json file.")]
-    app_dir: PathBuf,
+    #[arg(
+        long,
+        value_name = "CODEC",
+        default_value_t = Codec::Gzip,
+        help = "Compress the package contents with CODEC"
+    )]
+    compression: Codec,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Compression level, from 0 (fastest) to 9 (smallest)"
+    )]
+    level: Option<u32>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read control fields from a package.json-style FILE"
+    )]
+    control_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "DEPENDS",
+        help = "Packages this one depends on, for the Depends field"
+    )]
+    depends: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Where the source lives, for the Source field"
+    )]
+    source: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Project home page, for the Homepage field"
+    )]
+    homepage: Option<String>,
+    #[arg(
+        short = 'C',
+        long = "control",
+        value_name = "FIELD=VALUE",
+        help = "Add FIELD to the control file (repeatable)"
+    )]
+    control: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Also write a webosbrew repository manifest to FILE"
+    )]
+    manifest: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "manifest",
+        help = "Icon URL for the manifest"
+    )]
+    icon_uri: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "manifest",
+        help = "Download URL for the manifest [default: the package file name]"
+    )]
+    ipk_url: Option<String>,
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with_all = ["app_dir", "project", "manifest"],
+        help = "Compare two packages instead of building one"
+    )]
+    diff: Option<Vec<PathBuf>>,
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["app_dir", "service_dir"],
+        help = "Read the package id, contents and options from a project FILE"
+    )]
+    project: Option<PathBuf>,
+    #[arg(
+        required_unless_present_any = ["project", "diff"],
+        help = "App directory containing a valid appinfo.json file."
+    )]
+    app_dir: Option<PathBuf>,
     #[arg(help = "Directory containing a valid services.json file")]
     service_dir: Vec<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
 }
 
-#[derive(Debug, Serialize)]
-pub struct PackageInfo {
-    id: String,
-    version: String,
-    app: String,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    services: Vec<String>,
-}
-
-pub trait ParseFrom: Sized {
-    fn parse_from<R: Read>(reader: R) -> std::io::Result<Self>;
+/// What `--output json` prints once the package is written.
+#[derive(Serialize, Debug)]
+struct PackageResult<'a> {
+    path: &'a Path,
+    id: &'a str,
+    version: &'a str,
+    arch: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest: Option<&'a Path>,
 }
 
 fn main() {
     let cli = Cli::parse();
-    let app_dir = cli.app_dir;
+    set_output_format(cli.output);
+    if let Some(packages) = &cli.diff {
+        let [old, new] = [&packages[0], &packages[1]].map(|path| {
+            unwrap_or_exit(
+                File::open(path).and_then(|file| IpkContents::read(BufReader::new(file))),
+                &format!("read {}", path.to_string_lossy()),
+            )
+        });
+        let diff = IpkDiff::new(&old, &new);
+        if json_output() {
+            print_json(&diff);
+        } else {
+            print!("{diff}");
+        }
+        return;
+    }
     let outdir = cli
         .outdir
+        .clone()
         .or_else(|| std::env::current_dir().ok())
         .expect("Invalid output directory");
 
-    let data = DataInfo::from_input(&app_dir, &cli.service_dir, &cli.app_exclude).unwrap();
-    let package_info = &data.package;
-    let validation = data.validate(cli.force_arch.is_some()).unwrap();
-    let forced = cli.force_arch.clone();
-    let arch = forced
-        .clone()
-        .or_else(|| validation.arch.clone())
-        .unwrap_or(PackageArch::ALL);
-    if let Some(validation_arch) = &validation.arch
-        && std::mem::discriminant(&arch) != std::mem::discriminant(validation_arch)
-    {
-        eprintln!("Incompatible architecture: {arch} != {validation_arch}");
-        return;
+    let compression = unwrap_or_exit(
+        Compression::new(cli.compression, cli.level),
+        "set up compression",
+    );
+    let project = unwrap_or_exit(project_info(&cli), "read the project");
+
+    // Check the inputs and control fields before the slow part, so a typo
+    // fails fast.
+    let mut builder = PackageBuilder::from_project(project)
+        .compression(compression)
+        .temp_dir(&outdir)
+        .progress(|entry| match entry.link {
+            _ if json_output() => {}
+            Some(target) => println!("Adding {} -> {}", entry.path, target.to_string_lossy()),
+            None => println!("Adding {}", entry.path),
+        });
+    if let Some(arch) = &cli.force_arch {
+        builder = builder.force_arch(arch.clone());
     }
-    if forced.is_some() {
-        eprintln!("Warning: architecture {} was explicitly forced via -A", arch);
+    builder = unwrap_or_exit(control_fields(&cli, builder), "read the control fields");
+    let mut package = builder
+        .build()
+        .unwrap_or_else(|e| package_failed("prepare the package", &e).exit());
+    if cli.force_arch.is_some() {
+        eprintln!(
+            "Warning: architecture {} was explicitly forced via -A",
+            package.arch()
+        );
     }
 
-    let path = outdir.join(format!(
-        "{}_{}_{}.ipk",
-        package_info.id, package_info.version, arch
-    ));
-    println!("Packaging {}...", path.to_string_lossy());
-    let ipk_file = File::create(path).unwrap();
-    let mut ar = Builder::new(ipk_file);
+    let file_name = package.file_name().to_string();
+    let path = outdir.join(&file_name);
+    let links = cli.manifest.as_ref().map(|_| {
+        let ipk_url = cli.ipk_url.clone().unwrap_or(file_name);
+        unwrap_or_exit(
+            ManifestLinks::new(
+                package.data(),
+                cli.icon_uri.as_deref(),
+                ipk_url,
+                package.control(),
+            ),
+            "prepare the manifest",
+        )
+    });
+    if !json_output() {
+        println!("Packaging {}...", path.to_string_lossy());
+    }
 
-    let mtime = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+    let written = File::create(&path)
+        .map_err(Into::into)
+        .and_then(|file| package.write_to(file))
+        .and_then(|mut file| Ok(file.flush()?));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&path);
+        package_failed(&format!("package {}", package.data().package.id), &e).exit();
+    }
+
+    if let (Some(manifest_path), Some(links)) = (&cli.manifest, links) {
+        unwrap_or_exit(
+            RepoManifest::new(package.data(), package.control(), links, &path)
+                .and_then(|manifest| manifest.write_to(manifest_path)),
+            &format!("write manifest {}", manifest_path.to_string_lossy()),
+        );
+        if !json_output() {
+            println!("Wrote manifest {}", manifest_path.to_string_lossy());
+        }
+    }
+    if json_output() {
+        let info = &package.data().package;
+        print_json(&PackageResult {
+            path: &path,
+            id: &info.id,
+            version: &info.version,
+            arch: package.arch().to_string(),
+            manifest: cli.manifest.as_deref(),
+        });
+    } else {
+        println!("Done.");
+    }
+}
 
-    ar.append_header(mtime).unwrap();
-    let control = ControlInfo {
-        package: package_info.id.clone(),
-        version: package_info.version.clone(),
-        installed_size: validation.size,
-        architecture: arch.to_string(),
+/// `Failed to <action>: <error>`. Anything short of a file that can't be read
+/// or written is a problem with the input.
+fn package_failed(action: &str, e: &PackageError) -> CliError {
+    let kind = match e {
+        PackageError::Io(e) => e.error_kind(),
+        _ => ErrorKind::InvalidInput,
     };
-    ar.append_control(&control, mtime).unwrap();
-    ar.append_data(&data, mtime).unwrap();
-    println!("Done.");
+    CliError::new(kind, format!("Failed to {action}: {e}"))
+}
+
+/// What to package: the project file, with the exclude and executable flags
+/// added, or else the directories on the command line.
+fn project_info(cli: &Cli) -> std::io::Result<ProjectInfo> {
+    let mut project = match &cli.project {
+        Some(path) => {
+            let mut project = ProjectInfo::parse_from(File::open(path)?)?;
+            if let Some(base) = path.parent() {
+                project.resolve_paths(base);
+            }
+            project
+        }
+        None => ProjectInfo {
+            app: cli.app_dir.clone(),
+            services: cli.service_dir.clone(),
+            ..ProjectInfo::default()
+        },
+    };
+    project.exclude.extend(cli.app_exclude.iter().cloned());
+    project.executable.extend(cli.executable.iter().cloned());
+    Ok(project)
+}
+
+/// Hand the control fields to `builder`. `--control-file` comes first, then
+/// the field flags, then `--control`, each overriding the one before.
+fn control_fields<'a>(
+    cli: &Cli,
+    mut builder: PackageBuilder<'a>,
+) -> std::io::Result<PackageBuilder<'a>> {
+    if let Some(path) = &cli.control_file {
+        builder = builder.control_file(ControlFile::parse_from(File::open(path)?)?);
+    }
+    let flags = [
+        ("Depends", &cli.depends),
+        ("Source", &cli.source),
+        ("Homepage", &cli.homepage),
+    ];
+    for (name, value) in flags {
+        if let Some(value) = value {
+            builder = builder.control_field(name, value);
+        }
+    }
+    for field in &cli.control {
+        let (name, value) = field.split_once('=').ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid --control '{field}', expected FIELD=VALUE"),
+            )
+        })?;
+        builder = builder.control_field(name, value);
+    }
+    Ok(builder)
 }
@@ -3,11 +3,15 @@ use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use ares_connection_lib::session::NewSession;
+use ares_connection_lib::session::{self, NewSession};
 use ares_connection_lib::transfer::{PathKind, Transfer, TransferError};
-use ares_device_lib::DeviceManager;
-use ares_device_lib::cli::unwrap_or_exit;
+use ares_device_lib::cli::{
+    CliError, OutputFormat, eprint_line, for_each_device, json_output, print_line,
+    set_output_format, unwrap_or_exit,
+};
+use ares_device_lib::{Device, DeviceManager, prompt};
 use clap::Parser;
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 #[command(about)]
@@ -17,9 +21,10 @@ struct Cli {
         long,
         value_name = "DEVICE",
         env = "ARES_DEVICE",
-        help = "Specify DEVICE to use"
+        value_delimiter = ',',
+        help = "Specify DEVICE to use, or @GROUP or tag:TAG for many. Separate several with commas"
     )]
-    device: Option<String>,
+    device: Vec<String>,
     #[arg(short, long, help = "Hide the detailed copy messages")]
     ignore: bool,
     #[arg(
@@ -37,9 +42,42 @@ struct Cli {
     #[arg(
         value_name = "DESTINATION",
         default_value = ".",
-        help = "Path on the host machine, where files are copied to"
+        help = "Path on the host machine, where files are copied to. With many devices, each one gets a directory in it"
     )]
     destination: String,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
+}
+
+/// What `--output json` prints: the files and directories copied, and what
+/// `--keep-going` skipped.
+#[derive(Serialize, Debug, Default)]
+struct PullResult {
+    copied: Vec<Copied>,
+    skipped: Vec<CliError>,
+}
+
+#[derive(Serialize, Debug)]
+struct Copied {
+    source: String,
+    target: String,
 }
 
 /// Directory nesting we refuse to go past. Symlinks are followed, so a link
@@ -48,13 +86,36 @@ const MAX_DEPTH: usize = 64;
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    unwrap_or_exit(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
-    let Some(device) = unwrap_or_exit(manager.find_or_default(cli.device.as_ref()), "find device")
-    else {
-        eprintln!("Device not found");
+    let devices = unwrap_or_exit(manager.resolve(&cli.device), "find device");
+    let many = devices.len() > 1;
+    let results = for_each_device(&devices, |device| {
+        let destination = if many {
+            // Made up front, so a lone file lands in it rather than as it.
+            let dir = device_dir(&cli.destination, &device.name);
+            create_dir_all(&dir).map_err(|e| CliError::failed_to(&format!("create {dir}"), &e))?;
+            dir
+        } else {
+            cli.destination.clone()
+        };
+        pull_from(&cli, device, &destination)
+    });
+    if results.iter().any(|result| !result.skipped.is_empty()) {
         exit(1);
-    };
-    let session = unwrap_or_exit(device.new_session(), &format!("connect to {}", device.name));
+    }
+}
+
+/// Copy SOURCE from one device to `destination`.
+fn pull_from(cli: &Cli, device: &Device, destination: &str) -> Result<PullResult, CliError> {
+    let session = device
+        .new_session()
+        .map_err(|e| CliError::failed_to(&format!("connect to {}", device.name), &e))?;
     // Open the transport once. It is SFTP unless the device is set to stream,
     // and a copy of many files would otherwise pay a handshake per file.
     let transfer = Transfer::open(&session);
@@ -63,23 +124,30 @@ fn main() {
         transfer: &transfer,
         quiet: cli.ignore,
         keep_going: cli.keep_going,
-        failed: false,
+        result: PullResult::default(),
     };
-    if let Err(e) = pull.run(&cli.source, &cli.destination) {
-        eprintln!("Failed to pull: {e}");
-        exit(1);
-    }
-    if pull.failed {
-        exit(1);
-    }
+    pull.run(&cli.source, destination)
+        .map_err(|e| CliError::failed_to("pull", &e))?;
+    Ok(pull.result)
+}
+
+/// The directory under DESTINATION that gets the copy from one of many devices.
+/// A device given as a URI is named `user@host:port`, and `:` can't be in a
+/// file name on Windows.
+fn device_dir(destination: &str, name: &str) -> String {
+    Path::new(destination)
+        .join(name.replace([':', '/', '\\'], "_"))
+        .to_string_lossy()
+        .into_owned()
 }
 
 struct Pull<'a> {
     transfer: &'a Transfer<'a>,
     quiet: bool,
     keep_going: bool,
-    /// Set when --keep-going swallowed a failure, so the exit code still says so.
-    failed: bool,
+    /// What was copied, and the failures --keep-going swallowed, so the exit
+    /// code still says so.
+    result: PullResult,
 }
 
 impl Pull<'_> {
@@ -128,7 +196,9 @@ impl Pull<'_> {
             // ares-cli walks with `find -follow`, which lists a device node or a
             // broken symlink as neither a file nor a directory and skips it.
             PathKind::Other | PathKind::Missing => {
-                eprintln!("Skipping {remote}: it is not a file or a directory");
+                eprint_line(format!(
+                    "Skipping {remote}: it is not a file or a directory"
+                ));
                 Ok(())
             }
         }
@@ -168,9 +238,14 @@ impl Pull<'_> {
             .map_err(|e| transfer_error(remote, &e))
     }
 
-    fn report(&self, remote: &str, local: &Path) {
-        if !self.quiet {
-            println!("{remote} => {}", local.display());
+    fn report(&mut self, remote: &str, local: &Path) {
+        if json_output() {
+            self.result.copied.push(Copied {
+                source: remote.to_string(),
+                target: local.to_string_lossy().into_owned(),
+            });
+        } else if !self.quiet {
+            print_line(format!("{remote} => {}", local.display()));
         }
     }
 
@@ -180,8 +255,12 @@ impl Pull<'_> {
         if !self.keep_going {
             return Err(e);
         }
-        eprintln!("Skipping {what}: {e}");
-        self.failed = true;
+        if !json_output() {
+            eprint_line(format!("Skipping {what}: {e}"));
+        }
+        self.result
+            .skipped
+            .push(CliError::failed_to(&format!("pull {what}"), &e));
         Ok(())
     }
 }
@@ -225,7 +304,7 @@ fn transfer_error(path: &str, e: &TransferError) -> Error {
 
 #[cfg(test)]
 mod tests {
-    use super::{remote_name, resolve_target};
+    use super::{device_dir, remote_name, resolve_target};
 
     fn target(source: &str, destination: &str, source_is_dir: bool, dest_is_dir: bool) -> String {
         resolve_target(source, destination, source_is_dir, dest_is_dir)
@@ -266,6 +345,15 @@ mod tests {
         assert_eq!(target("/", "out", true, true), "out");
     }
 
+    #[test]
+    fn each_device_gets_a_directory() {
+        assert_eq!(
+            device_dir("out", "root@10.0.0.5:22").replace('\\', "/"),
+            "out/root@10.0.0.5_22"
+        );
+        assert_eq!(device_dir(".", "tv1").replace('\\', "/"), "./tv1");
+    }
+
     #[test]
     fn names_come_from_the_last_component() {
         assert_eq!(remote_name("/var/log/messages"), Some("messages"));
@@ -5,12 +5,16 @@ use std::io::{Error, ErrorKind};
 use std::path::{Component, Path, PathBuf};
 use std::process::exit;
 
-use ares_connection_lib::session::NewSession;
+use ares_connection_lib::session::{self, NewSession};
 use ares_connection_lib::transfer::{PathKind, Transfer, TransferError};
-use ares_device_lib::DeviceManager;
-use ares_device_lib::cli::unwrap_or_exit;
+use ares_device_lib::cli::{
+    CliError, ErrorKind as FailureKind, OutputFormat, eprint_line, for_each_device, json_output,
+    print_line, set_output_format, unwrap_or_exit,
+};
+use ares_device_lib::{Device, DeviceManager, prompt};
 use clap::Parser;
 use path_slash::PathExt;
+use serde::Serialize;
 use walkdir::WalkDir;
 
 #[derive(Parser, Debug)]
@@ -21,9 +25,10 @@ struct Cli {
         long,
         value_name = "DEVICE",
         env = "ARES_DEVICE",
-        help = "Specify DEVICE to use"
+        value_delimiter = ',',
+        help = "Specify DEVICE to use, or @GROUP or tag:TAG for many. Separate several with commas"
     )]
-    device: Option<String>,
+    device: Vec<String>,
     #[arg(short, long, help = "Hide the detailed copy messages")]
     ignore: bool,
     #[arg(
@@ -44,35 +49,78 @@ struct Cli {
         required = true
     )]
     destination: String,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
+}
+
+/// What `--output json` prints: the files and directories copied, and what
+/// `--keep-going` skipped.
+#[derive(Serialize, Debug, Default)]
+struct PushResult {
+    copied: Vec<Copied>,
+    skipped: Vec<CliError>,
+}
+
+#[derive(Serialize, Debug)]
+struct Copied {
+    source: String,
+    target: String,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    unwrap_or_exit(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
-    let Some(device) = unwrap_or_exit(manager.find_or_default(cli.device.as_ref()), "find device")
-    else {
-        eprintln!("Device not found");
+    let devices = unwrap_or_exit(manager.resolve(&cli.device), "find device");
+    let results = for_each_device(&devices, |device| push_to(&cli, device));
+    if results.iter().any(|result| !result.skipped.is_empty()) {
         exit(1);
-    };
-    let session = unwrap_or_exit(device.new_session(), &format!("connect to {}", device.name));
+    }
+}
+
+/// Copy every SOURCE to one device.
+fn push_to(cli: &Cli, device: &Device) -> Result<PushResult, CliError> {
+    let session = device
+        .new_session()
+        .map_err(|e| CliError::failed_to(&format!("connect to {}", device.name), &e))?;
     // Open the transport once. It is SFTP unless the device is set to stream,
     // and a copy of many files would otherwise pay a handshake per file.
     let transfer = Transfer::open(&session);
 
-    let dest_kind = match transfer.stat(&cli.destination) {
-        Ok(kind) => kind,
-        Err(e) => {
-            eprintln!("Failed to read {}: {e}", cli.destination);
-            exit(1);
-        }
-    };
+    let dest_kind = transfer
+        .stat(&cli.destination)
+        .map_err(|e| CliError::failed_to(&format!("read {}", cli.destination), &e))?;
     let single = cli.source.len() == 1;
     if dest_kind == PathKind::File && !single {
-        eprintln!(
-            "Failed to push: {} is a file, so it can hold only one SOURCE",
-            cli.destination
-        );
-        exit(1);
+        return Err(CliError::new(
+            FailureKind::InvalidInput,
+            format!(
+                "Failed to push: {} is a file, so it can hold only one SOURCE",
+                cli.destination
+            ),
+        ));
     }
 
     let mut push = Push {
@@ -80,20 +128,21 @@ fn main() {
         quiet: cli.ignore,
         keep_going: cli.keep_going,
         made_dirs: HashSet::new(),
-        failed: false,
+        result: PushResult::default(),
     };
     for source in &cli.source {
         if let Err(e) = push.source(source, &cli.destination, dest_kind, single) {
-            eprintln!("Failed to push {}: {e}", source.display());
+            let error = CliError::failed_to(&format!("push {}", source.display()), &e);
             if !push.keep_going {
-                exit(1);
+                return Err(error);
+            }
+            if !json_output() {
+                eprint_line(&error);
             }
-            push.failed = true;
+            push.result.skipped.push(error);
         }
     }
-    if push.failed {
-        exit(1);
-    }
+    Ok(push.result)
 }
 
 struct Push<'a> {
@@ -103,8 +152,9 @@ struct Push<'a> {
     /// Device paths we already made, so a run does not stat the same directory
     /// once per file.
     made_dirs: HashSet<String>,
-    /// Set when --keep-going swallowed a failure, so the exit code still says so.
-    failed: bool,
+    /// What was copied, and the failures --keep-going swallowed, so the exit
+    /// code still says so.
+    result: PushResult,
 }
 
 impl Push<'_> {
@@ -148,10 +198,10 @@ impl Push<'_> {
                 // content. A symlink to a directory makes it fail, so skip that.
                 match std::fs::metadata(entry.path()) {
                     Ok(metadata) if metadata.is_dir() => {
-                        eprintln!(
+                        eprint_line(format!(
                             "Skipping {}: it is a symlink to a directory",
                             entry.path().display()
-                        );
+                        ));
                         Ok(())
                     }
                     Ok(_) => self.put_file(entry.path(), &target),
@@ -190,9 +240,14 @@ impl Push<'_> {
         Ok(())
     }
 
-    fn report(&self, local: &Path, target: &str) {
-        if !self.quiet {
-            println!("{} => {target}", local.display());
+    fn report(&mut self, local: &Path, target: &str) {
+        if json_output() {
+            self.result.copied.push(Copied {
+                source: local.to_string_lossy().into_owned(),
+                target: target.to_string(),
+            });
+        } else if !self.quiet {
+            print_line(format!("{} => {target}", local.display()));
         }
     }
 
@@ -202,8 +257,12 @@ impl Push<'_> {
         if !self.keep_going {
             return Err(e);
         }
-        eprintln!("Skipping {what}: {e}");
-        self.failed = true;
+        if !json_output() {
+            eprint_line(format!("Skipping {what}: {e}"));
+        }
+        self.result
+            .skipped
+            .push(CliError::failed_to(&format!("push {what}"), &e));
         Ok(())
     }
 }
@@ -0,0 +1,202 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use ares_connection_lib::session::NewSession;
+use ares_connection_lib::setup::{KeyPair, authorize_key, generate_key, revoke_key};
+use ares_connection_lib::transfer::Transfer;
+use ares_device_lib::cli::{CliError, ErrorKind, json_output, print_json, unwrap_or_exit};
+use ares_device_lib::{AuthMethod, Device, DeviceManager, PrivateKey};
+use serde_json::json;
+
+/// Make a key for the device called `name`, save it, add it to
+/// `authorized_keys` over the login the device has now, check that the key
+/// logs in, then switch the device to the key and drop its password. A
+/// failure on the way undoes the steps before it.
+///
+/// The key is kept in the SSH key directory as `webos_<name>_ed25519`, so
+/// `--remove` deletes it with the device.
+pub(crate) fn deploy_key(manager: &DeviceManager, name: &str) {
+    let Some(existing) = unwrap_or_exit(manager.find_or_default(Some(&name)), "find device") else {
+        CliError::new(ErrorKind::NotFound, format!("Device {name} not found")).exit();
+    };
+    let device = unwrap_or_exit(manager.unlock(&existing), "read the secrets");
+    let key_name = key_file_name(&device.name);
+    let key_dir = unwrap_or_exit(manager.ssh_key_dir(), "resolve ssh directory");
+    let key_path = key_dir.join(&key_name);
+    if key_path.exists() {
+        CliError::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} exists already. Remove it to deploy a new key",
+                key_path.display()
+            ),
+        )
+        .exit();
+    }
+
+    let pair = unwrap_or_exit(
+        generate_key(&format!("ares-cli-rs@{}", device.name)),
+        "generate key",
+    );
+    // Saved first, so that the device never trusts a key nobody holds.
+    unwrap_or_exit(write_key(&key_path, &pair.private_key), "save key");
+    let added = match authorize(manager, &existing, &device, &key_name, &pair) {
+        Ok(added) => added,
+        Err(e) => {
+            if let Err(e) = fs::remove_file(&key_path) {
+                eprintln!("Failed to delete {}: {e}", key_path.display());
+            }
+            e.exit();
+        }
+    };
+    if let Err(e) = manager.forget_secrets(&existing) {
+        eprintln!("Failed to delete the old secrets of {name}: {e}");
+    }
+
+    if json_output() {
+        print_json(&json!({
+            "device": existing.name,
+            "keyPath": key_path,
+            "publicKey": pair.public_key,
+            "added": added,
+        }));
+    } else {
+        println!(
+            "Saved key to {} and switched device {} to it.",
+            key_path.display(),
+            existing.name
+        );
+    }
+}
+
+/// Add the public key of `pair` to `authorized_keys` on `device`, then switch
+/// to the key. The key comes out of `authorized_keys` again if that fails.
+/// Returns `true` when the key was added.
+fn authorize(
+    manager: &DeviceManager,
+    existing: &Device,
+    device: &Device,
+    key_name: &str,
+    pair: &KeyPair,
+) -> Result<bool, CliError> {
+    let session = device
+        .new_session()
+        .map_err(|e| CliError::failed_to(&format!("connect to {}", device.name), &e))?;
+    let transfer = Transfer::open(&session);
+    let added = authorize_key(&transfer, &pair.public_key)
+        .map_err(|e| CliError::failed_to("add the key to authorized_keys", &e))?;
+    let switched = switch_to_key(manager, existing, device, key_name, &pair.private_key);
+    if switched.is_err()
+        && added
+        && let Err(e) = revoke_key(&transfer, &pair.public_key)
+    {
+        eprintln!("Failed to remove the new key from authorized_keys: {e}");
+    }
+    switched.map(|()| added)
+}
+
+/// Check that `private_key` logs in to `device`, then make it the key of the
+/// device in the list and drop its password.
+fn switch_to_key(
+    manager: &DeviceManager,
+    existing: &Device,
+    device: &Device,
+    key_name: &str,
+    private_key: &str,
+) -> Result<(), CliError> {
+    let mut keyed = device.clone();
+    keyed.private_key = Some(PrivateKey::Data {
+        data: private_key.to_string(),
+    });
+    keyed.passphrase = None;
+    keyed.password = None;
+    keyed.auth_methods = Some(vec![AuthMethod::PublicKey]);
+    keyed
+        .new_session()
+        .map_err(|e| CliError::failed_to("log in with the new key", &e))?;
+
+    let mut updated = existing.clone();
+    updated.private_key = Some(PrivateKey::Name {
+        name: key_name.to_string(),
+    });
+    updated.passphrase = None;
+    updated.password = None;
+    updated.secret_ref = None;
+    updated.auth_methods = updated.auth_methods.map(key_methods);
+    manager
+        .modify(&existing.name, &updated)
+        .map_err(|e| CliError::failed_to("update device", &e))?;
+    Ok(())
+}
+
+/// `webos_<name>_ed25519`, with anything but letters, digits, `-` and `.` in
+/// the device name turned into `_`. The `webos_` prefix is what `--remove`
+/// looks for before it deletes a key.
+fn key_file_name(device: &str) -> String {
+    let name: String = device
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("webos_{name}_ed25519")
+}
+
+/// The login order once the device has a key: no password, and the key first
+/// unless the order already has it.
+fn key_methods(methods: Vec<AuthMethod>) -> Vec<AuthMethod> {
+    let mut methods: Vec<AuthMethod> = methods
+        .into_iter()
+        .filter(|m| *m != AuthMethod::Password)
+        .collect();
+    if !methods.contains(&AuthMethod::PublicKey) {
+        methods.insert(0, AuthMethod::PublicKey);
+    }
+    methods
+}
+
+/// Write the key readable by its owner alone from the start. A file that
+/// turned up since the check in [`deploy_key`] is left alone.
+fn write_key(path: &Path, content: &str) -> Result<(), std::io::Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use ares_device_lib::AuthMethod;
+
+    use super::{key_file_name, key_methods};
+
+    #[test]
+    fn key_names_are_safe_file_names() {
+        assert_eq!(key_file_name("tv"), "webos_tv_ed25519");
+        assert_eq!(
+            key_file_name("living room/tv"),
+            "webos_living_room_tv_ed25519"
+        );
+    }
+
+    #[test]
+    fn the_password_gives_way_to_the_key() {
+        assert_eq!(
+            key_methods(vec![AuthMethod::Password, AuthMethod::None]),
+            vec![AuthMethod::PublicKey, AuthMethod::None]
+        );
+        assert_eq!(
+            key_methods(vec![AuthMethod::Agent, AuthMethod::PublicKey]),
+            vec![AuthMethod::Agent, AuthMethod::PublicKey]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use ares_device_lib::cli::unwrap_or_exit;
+use ares_device_lib::{Device, DeviceManager, PrivateKey};
+
+#[cfg(windows)]
+const NULL_DEVICE: &str = "NUL";
+#[cfg(not(windows))]
+const NULL_DEVICE: &str = "/dev/null";
+
+/// Print the device list as `Host` blocks for `~/.ssh/config`, so that `ssh`,
+/// `scp` and editors that connect over SSH reach the devices by name.
+pub(crate) fn export_ssh_config(manager: &DeviceManager) {
+    let devices = unwrap_or_exit(manager.list(), "list devices");
+    let key_dir = unwrap_or_exit(manager.ssh_key_dir(), "resolve ssh directory");
+    print!("{}", ssh_config(&devices, &key_dir));
+}
+
+fn ssh_config(devices: &[Device], key_dir: &Path) -> String {
+    let mut config = String::from("# Written by ares-setup-device --export-ssh-config\n");
+    for device in devices {
+        config.push('\n');
+        host_block(&mut config, device, devices, key_dir);
+    }
+    config
+}
+
+/// One `Host` block. The host key options match what the tools do: a device
+/// makes a new host key when Developer Mode is installed again, so it isn't
+/// checked, and old devices need `ssh-rsa`, which OpenSSH turns off by default.
+fn host_block(config: &mut String, device: &Device, devices: &[Device], key_dir: &Path) {
+    let mut line = |text: String| {
+        config.push_str(&text);
+        config.push('\n');
+    };
+    if let Some(description) = device.description.as_deref().filter(|d| !d.is_empty()) {
+        line(format!("# {description}"));
+    }
+    line(format!("Host {}", quote(&device.name)));
+    line(format!("    HostName {}", device.host));
+    line(format!("    Port {}", device.port));
+    line(format!("    User {}", device.username));
+    match &device.private_key {
+        Some(PrivateKey::Data { .. }) => {
+            line(String::from(
+                "    # The key is kept in the device list itself, which ssh can't read.",
+            ));
+        }
+        Some(key) => {
+            if let Some(path) = key.file_in(Some(key_dir)) {
+                line(format!(
+                    "    IdentityFile {}",
+                    quote(&path.to_string_lossy())
+                ));
+                line(String::from("    IdentitiesOnly yes"));
+            }
+        }
+        None => {}
+    }
+    if let Some(jump) = &device.proxy_jump {
+        // A device in the list has a Host block of its own. An address is
+        // written out in full, because ssh takes port 22 where we take 9922.
+        let jump = if devices.iter().any(|d| &d.name == jump) {
+            quote(jump)
+        } else {
+            Device::from_uri(jump).map_or_else(|_| jump.clone(), |hop| hop.name)
+        };
+        line(format!("    ProxyJump {jump}"));
+    }
+    if let Some(timeout) = device.connection.connect_timeout {
+        line(format!("    ConnectTimeout {timeout}"));
+    }
+    if let Some(interval) = device.connection.server_alive_interval {
+        line(format!("    ServerAliveInterval {interval}"));
+    }
+    if let Some(count) = device.connection.server_alive_count_max {
+        line(format!("    ServerAliveCountMax {count}"));
+    }
+    if let Some(attempts) = device.connection.connection_attempts {
+        line(format!("    ConnectionAttempts {attempts}"));
+    }
+    line(String::from("    HostKeyAlgorithms +ssh-rsa"));
+    line(String::from("    PubkeyAcceptedAlgorithms +ssh-rsa"));
+    line(String::from("    StrictHostKeyChecking no"));
+    line(format!("    UserKnownHostsFile {NULL_DEVICE}"));
+}
+
+/// Quote a name or path that has spaces in it, as ssh reads them.
+fn quote(text: &str) -> String {
+    if text.contains(char::is_whitespace) {
+        format!("\"{text}\"")
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ares_device_lib::Device;
+
+    use super::ssh_config;
+
+    fn device(json: &str) -> Device {
+        serde_json::from_str(&format!(
+            r#"{{"profile":"ose","username":"prisoner",{json}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn devices_become_host_blocks() {
+        let devices = [
+            device(
+                r#""name":"tv","host":"10.0.0.5","port":9922,"privateKey":{"openSsh":"webos_tv"},
+                   "proxyJump":"bastion","connectTimeout":30"#,
+            ),
+            device(r#""name":"bastion","host":"gw.lab","port":22"#),
+            device(r#""name":"living room","host":"10.0.0.6","port":9922,"proxyJump":"me@gw.lab""#),
+        ];
+        let config = ssh_config(&devices, Path::new("/keys"));
+
+        assert!(config.contains(
+            "Host tv\n    HostName 10.0.0.5\n    Port 9922\n    User prisoner\n    \
+             IdentityFile /keys/webos_tv\n    IdentitiesOnly yes\n    ProxyJump bastion\n    \
+             ConnectTimeout 30\n"
+        ));
+        assert!(
+            config.contains("Host bastion\n    HostName gw.lab\n    Port 22\n    User prisoner\n")
+        );
+        assert!(config.contains("Host \"living room\"\n"));
+        assert!(config.contains("    ProxyJump me@gw.lab:9922\n"));
+    }
+}
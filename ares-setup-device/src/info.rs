@@ -4,13 +4,14 @@ use ares_device_lib::Device;
 use serde_json::{Map, Value, json};
 
 /// Builds a brand-new device from the `--add` name and parsed `--info` fields,
-/// filling in sensible defaults for anything the user did not provide.
+/// filling in the defaults of [`Device::with_defaults`] for anything the user
+/// did not provide.
 pub(crate) fn build_device(name: &str, info: &Map<String, Value>) -> Result<Device, String> {
-    let mut map = Map::new();
-    map.insert(String::from("profile"), json!("ose"));
-    map.insert(String::from("port"), json!(9922));
-    map.insert(String::from("username"), json!("root"));
-    map.insert(String::from("files"), json!("sftp"));
+    let Ok(Value::Object(mut map)) = serde_json::to_value(Device::with_defaults(name, "")) else {
+        return Err(String::from("Failed to fill in the defaults"));
+    };
+    // The host has no default.
+    map.remove("host");
     for (key, value) in info {
         map.insert(key.clone(), value.clone());
     }
@@ -80,14 +81,18 @@ fn insert_field(map: &mut Map<String, Value>, key: &str, value: &Value) -> Resul
         "host" | "ipAddress" => {
             map.insert(String::from("host"), json!(text()));
         }
-        "port" => {
-            let port = match value {
+        "port"
+        | "connectTimeout"
+        | "serverAliveInterval"
+        | "serverAliveCountMax"
+        | "connectionAttempts" => {
+            let number = match value {
                 Value::Number(n) => n.as_u64(),
                 Value::String(s) => s.parse::<u64>().ok(),
                 _ => None,
             }
-            .ok_or_else(|| String::from("port must be a number"))?;
-            map.insert(String::from("port"), json!(port));
+            .ok_or_else(|| format!("{key} must be a number"))?;
+            map.insert(String::from(key), json!(number));
         }
         "username" | "user" => {
             map.insert(String::from("username"), json!(text()));
@@ -98,6 +103,11 @@ fn insert_field(map: &mut Map<String, Value>, key: &str, value: &Value) -> Resul
         "description" => {
             map.insert(String::from("description"), json!(text()));
         }
+        "proxyJump" => {
+            // An empty value connects directly again.
+            let jump = Some(text()).filter(|jump| !jump.is_empty());
+            map.insert(String::from("proxyJump"), json!(jump));
+        }
         "password" => {
             map.insert(String::from("password"), json!(text()));
         }
@@ -110,21 +120,49 @@ fn insert_field(map: &mut Map<String, Value>, key: &str, value: &Value) -> Resul
         "openSshPath" | "keyPath" => {
             map.insert(String::from("privateKey"), json!({ "openSshPath": text() }));
         }
+        "agent" => {
+            if matches!(value, Value::Bool(true)) || text().eq_ignore_ascii_case("true") {
+                map.insert(String::from("privateKey"), json!({ "agent": true }));
+            }
+        }
         "files" => {
             map.insert(String::from("files"), json!(text()));
         }
-        "default" => {
+        "default" | "sshConfig" => {
             let flag = matches!(value, Value::Bool(true))
                 || value
                     .as_str()
                     .is_some_and(|s| s.eq_ignore_ascii_case("true"));
-            map.insert(String::from("default"), json!(flag));
+            map.insert(String::from(key), json!(flag));
+        }
+        "groups" | "tags" | "authMethods" => {
+            map.insert(String::from(key), labels(key, value)?);
         }
         other => return Err(format!("Unknown --info field: {other}")),
     }
     Ok(())
 }
 
+/// Reads `groups`, `tags` or `authMethods` as a comma-separated list or a JSON array. An
+/// empty list removes the field.
+fn labels(key: &str, value: &Value) -> Result<Value, String> {
+    let labels: Vec<String> = match value {
+        Value::String(s) => s.split(',').map(|l| l.trim().to_string()).collect(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("{key} must be a list of names"))?,
+        _ => return Err(format!("{key} must be a list of names")),
+    };
+    let labels: Vec<String> = labels.into_iter().filter(|l| !l.is_empty()).collect();
+    if labels.is_empty() {
+        Ok(Value::Null)
+    } else {
+        Ok(json!(labels))
+    }
+}
+
 /// Ensures only one authentication method is stored, based on what the user
 /// explicitly provided: a password clears any key, and a key clears a password.
 fn apply_auth(map: &mut Map<String, Value>, info: &Map<String, Value>) {
@@ -224,6 +262,9 @@ mod tests {
         assert_eq!(device.profile, "ose");
 
         assert!(build_device("tv", &info(&["username=root"])).is_err());
+
+        let patient = build_device("tv", &info(&["host=1.2.3.4", "connectTimeout=30"])).unwrap();
+        assert_eq!(patient.connection.connect_timeout, Some(30));
     }
 
     #[test]
@@ -232,6 +273,44 @@ mod tests {
         assert!(build_device("tv", &info(&["host=1.2.3.4", "port=70000"])).is_err());
     }
 
+    #[test]
+    fn groups_and_tags_take_lists() {
+        let device = build_device(
+            "tv",
+            &info(&["host=1.2.3.4", "groups=lab, office", "tags=webos6"]),
+        )
+        .unwrap();
+        assert_eq!(
+            device.groups.as_deref(),
+            Some(&[String::from("lab"), String::from("office")][..])
+        );
+        assert_eq!(device.tags.as_deref(), Some(&[String::from("webos6")][..]));
+
+        let cleared = super::modified_device(&device, &info(&["groups="])).unwrap();
+        assert!(cleared.groups.is_none());
+        assert!(cleared.tags.is_some());
+
+        let from_json =
+            build_device("tv", &info(&[r#"{"host":"1.2.3.4","tags":["a","b"]}"#])).unwrap();
+        assert_eq!(from_json.tags.map(|t| t.len()), Some(2));
+    }
+
+    #[test]
+    fn agent_and_auth_methods() {
+        let device = build_device(
+            "tv",
+            &info(&["host=1.2.3.4", "agent=true", "authMethods=agent,password"]),
+        )
+        .unwrap();
+        assert!(matches!(
+            device.private_key,
+            Some(ares_device_lib::PrivateKey::Agent { agent: true })
+        ));
+        assert_eq!(device.auth_methods.map(|m| m.len()), Some(2));
+
+        assert!(build_device("tv", &info(&["host=1.2.3.4", "authMethods=kerberos"])).is_err());
+    }
+
     #[test]
     fn modifying_to_password_clears_existing_key() {
         let with_key = build_device("tv", &info(&["host=1.2.3.4", "openSsh=k"])).unwrap();
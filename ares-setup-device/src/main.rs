@@ -1,16 +1,21 @@
-use std::process::exit;
-
-use ares_device_lib::DeviceManager;
+use ares_connection_lib::session;
+use ares_device_lib::cli::{
+    CliError, ErrorKind, OutputFormat, print_device_list, set_output_format, unwrap_or_exit,
+};
+use ares_device_lib::{Device, DeviceManager, SecretStore, prompt};
 use clap::Parser;
 
+mod deploy;
+mod export;
 mod info;
 mod output;
 
 use info::{build_device, modified_device, parse_info};
-use output::{print_list, print_list_full};
+use output::print_list_full;
 
 #[derive(Parser, Debug)]
 #[command(about)]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
     #[arg(short = 'l', long, group = "action", help = "List the devices")]
     list: bool,
@@ -53,6 +58,19 @@ struct Cli {
         help = "Set the device with NAME as default"
     )]
     default: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        group = "action",
+        help = "Make an SSH key for the device with NAME, authorize it there, and switch to it"
+    )]
+    deploy_key: Option<String>,
+    #[arg(
+        long,
+        group = "action",
+        help = "Print the devices as Host blocks for ~/.ssh/config"
+    )]
+    export_ssh_config: bool,
     #[arg(
         short = 'R',
         long,
@@ -67,74 +85,150 @@ struct Cli {
         help = "Device details as JSON or key=value (repeatable) for --add/--modify"
     )]
     info: Vec<String>,
+    #[arg(
+        long,
+        value_name = "STORE",
+        conflicts_with_all = ["list", "list_full", "remove", "default", "deploy_key", "export_ssh_config", "reset"],
+        help = "Keep the passphrase and password of the device in STORE: vault or keyring"
+    )]
+    secret_store: Option<SecretStore>,
+    #[arg(long, help = "Show passphrases and passwords in the device list")]
+    show_secrets: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print results and errors as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    unwrap_or_exit(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
 
     if cli.list {
-        print_devices(&manager, false);
+        print_devices(&manager, false, cli.show_secrets);
     } else if cli.list_full {
-        print_devices(&manager, true);
+        print_devices(&manager, true, cli.show_secrets);
     } else if let Some(name) = &cli.add {
-        run_add(&manager, name, &cli.info);
+        run_add(&manager, name, &cli.info, cli.secret_store);
     } else if let Some(name) = &cli.modify {
-        run_modify(&manager, name, &cli.info);
+        run_modify(&manager, name, &cli.info, cli.secret_store);
     } else if let Some(name) = &cli.remove {
+        let existing = unwrap_or_exit(manager.find_or_default(Some(name)), "find device");
         unwrap_or_exit(manager.remove(name, true, false), "remove device");
-        print_devices(&manager, false);
+        if let Some(device) = existing
+            && let Err(e) = manager.forget_secrets(&device)
+        {
+            eprintln!("Failed to delete the secrets of {name}: {e}");
+        }
+        print_devices(&manager, false, false);
     } else if let Some(name) = &cli.default {
         unwrap_or_exit(manager.set_default(name), "set default device");
-        print_devices(&manager, false);
+        print_devices(&manager, false, false);
+    } else if let Some(name) = &cli.deploy_key {
+        deploy::deploy_key(&manager, name);
+    } else if cli.export_ssh_config {
+        export::export_ssh_config(&manager);
     } else if cli.reset {
         unwrap_or_exit(manager.reset(), "reset devices");
-        print_devices(&manager, false);
+        print_devices(&manager, false, false);
     } else {
         Cli::parse_from(["", "--help"]);
     }
 }
 
-fn run_add(manager: &DeviceManager, name: &str, info: &[String]) {
+fn run_add(manager: &DeviceManager, name: &str, info: &[String], store: Option<SecretStore>) {
     let info = unwrap_or_exit(parse_info(info).map_err(into_error), "parse --info");
-    let device = unwrap_or_exit(
+    let mut device = unwrap_or_exit(
         build_device(name, &info).map_err(into_error),
         "build device",
     );
+    if let Some(store) = store {
+        unwrap_or_exit(
+            manager.store_secrets(&mut device, store),
+            "store the secrets",
+        );
+    }
     unwrap_or_exit(manager.add(&device), "add device");
-    print_devices(manager, false);
+    print_devices(manager, false, false);
 }
 
-fn run_modify(manager: &DeviceManager, name: &str, info: &[String]) {
+fn run_modify(manager: &DeviceManager, name: &str, info: &[String], store: Option<SecretStore>) {
     let info = unwrap_or_exit(parse_info(info).map_err(into_error), "parse --info");
     let Some(existing) = unwrap_or_exit(manager.find_or_default(Some(&name)), "find device") else {
-        eprintln!("Device {name} not found");
-        exit(1);
+        CliError::new(ErrorKind::NotFound, format!("Device {name} not found")).exit();
     };
-    let device = unwrap_or_exit(
+    let mut device = unwrap_or_exit(
         modified_device(&existing, &info).map_err(into_error),
         "build device",
     );
+    let moved = unwrap_or_exit(
+        keep_secrets(manager, &existing, &mut device, store),
+        "store the secrets",
+    );
     unwrap_or_exit(manager.modify(name, &device), "modify device");
-    print_devices(manager, false);
+    if moved && let Err(e) = manager.forget_secrets(&existing) {
+        eprintln!("Failed to delete the old secrets of {name}: {e}");
+    }
+    print_devices(manager, false, false);
+}
+
+/// Put new secrets in the store the device already uses, or move them all to
+/// `store`. Returns `true` when they left another store, which then still
+/// holds a copy.
+fn keep_secrets(
+    manager: &DeviceManager,
+    existing: &Device,
+    device: &mut Device,
+    store: Option<SecretStore>,
+) -> Result<bool, std::io::Error> {
+    let current = existing.secret_ref.as_ref().map(|r| r.store);
+    let Some(store) = store.or(current) else {
+        return Ok(false);
+    };
+    let moved = current.is_some_and(|current| current != store);
+    if moved {
+        // What --info didn't replace comes from the old store.
+        let old = manager.unlock(existing)?;
+        device.passphrase = device.passphrase.take().or(old.passphrase);
+        device.password = device.password.take().or(old.password);
+        device.secret_ref = None;
+    }
+    if device.passphrase.is_some() || device.password.is_some() {
+        manager.store_secrets(device, store)?;
+    }
+    Ok(moved)
 }
 
-fn print_devices(manager: &DeviceManager, full: bool) {
+fn print_devices(manager: &DeviceManager, full: bool, show_secrets: bool) {
     let devices = unwrap_or_exit(manager.list(), "list devices");
     if full {
-        print_list_full(&devices);
+        print_list_full(&devices, show_secrets);
     } else {
-        print_list(&devices);
+        print_device_list(&devices, show_secrets);
     }
 }
 
 fn into_error(message: String) -> std::io::Error {
     std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
 }
-
-fn unwrap_or_exit<T>(result: Result<T, std::io::Error>, action: &str) -> T {
-    result.unwrap_or_else(|e| {
-        eprintln!("Failed to {action}: {e}");
-        exit(1);
-    })
-}
@@ -1,67 +1,45 @@
 use std::fmt::Write;
 
 use ares_device_lib::Device;
-use serde_json::Value;
+use ares_device_lib::cli::{json_output, print_json};
+use serde_json::{Map, Value};
 
-/// Prints the device list as a padded table, mirroring the reference CLI:
-/// name (with a `(default)` marker), deviceinfo, connection, profile, passphrase.
-pub(crate) fn print_list(devices: &[Device]) {
-    let headers = ["name", "deviceinfo", "connection", "profile", "passphrase"];
-    let rows: Vec<[String; 5]> = devices.iter().map(device_row).collect();
-    print_table(&headers, &rows);
-}
-
-/// Prints every field of every device, like the reference CLI's `--listfull`.
-pub(crate) fn print_list_full(devices: &[Device]) {
-    for device in devices {
-        println!("name : {}", device.name);
-        if let Ok(Value::Object(mut obj)) = serde_json::to_value(device) {
-            obj.remove("name");
-            print!("{}", convert_json_to_list(&Value::Object(obj), 0));
-        }
+/// Prints every field of every device, like the reference CLI's `--listfull`,
+/// or the devices as they are stored with `--output json`. Passphrases and
+/// passwords show as `****` unless `show_secrets` is set.
+pub(crate) fn print_list_full(devices: &[Device], show_secrets: bool) {
+    let entries: Vec<Map<String, Value>> = devices
+        .iter()
+        .filter_map(|device| match serde_json::to_value(device) {
+            Ok(Value::Object(mut obj)) => {
+                if !show_secrets {
+                    mask_secrets(&mut obj);
+                }
+                Some(obj)
+            }
+            _ => None,
+        })
+        .collect();
+    if json_output() {
+        print_json(&entries);
+        return;
+    }
+    for mut obj in entries {
+        let name = obj.remove("name").unwrap_or_default();
+        println!("name : {}", scalar_to_string(&name));
+        print!("{}", convert_json_to_list(&Value::Object(obj), 0));
         println!();
     }
 }
 
-fn device_row(device: &Device) -> [String; 5] {
-    let name = if device.default == Some(true) {
-        format!("{} (default)", device.name)
-    } else {
-        device.name.clone()
-    };
-    [
-        name,
-        format!("{}@{}:{}", device.username, device.host, device.port),
-        String::from("ssh"),
-        device.profile.clone(),
-        device.passphrase.clone().unwrap_or_default(),
-    ]
-}
-
-fn print_table<const N: usize>(headers: &[&str; N], rows: &[[String; N]]) {
-    let mut widths = headers.map(str::len);
-    for row in rows {
-        for (i, cell) in row.iter().enumerate() {
-            widths[i] = widths[i].max(cell.len());
+fn mask_secrets(obj: &mut Map<String, Value>) {
+    for key in ["passphrase", "password"] {
+        if let Some(value) = obj.get_mut(key)
+            && value.as_str().is_some_and(|s| !s.is_empty())
+        {
+            *value = Value::from("****");
         }
     }
-
-    let print_row = |cells: &[String; N]| {
-        let line: Vec<String> = cells
-            .iter()
-            .enumerate()
-            .map(|(i, cell)| format!("{cell:<width$}", width = widths[i]))
-            .collect();
-        println!("{}", line.join("  ").trim_end());
-    };
-
-    let header_row: [String; N] = std::array::from_fn(|i| headers[i].to_string());
-    let separator_row: [String; N] = std::array::from_fn(|i| "-".repeat(widths[i]));
-    print_row(&header_row);
-    print_row(&separator_row);
-    for row in rows {
-        print_row(row);
-    }
 }
 
 /// Renders a JSON value into an indented text list (one extra `-` per level),
@@ -1,8 +1,13 @@
 use std::io::{stdin, stdout};
 use std::process::exit;
+use std::sync::Arc;
 
-use ares_connection_lib::session::NewSession;
-use ares_device_lib::DeviceManager;
+use ares_connection_lib::keepalive;
+use ares_connection_lib::session::{self, NewSession, SessionError};
+use ares_device_lib::cli::{
+    Classify, CliError, ErrorKind, OutputFormat, for_each_device, set_output_format,
+};
+use ares_device_lib::{DeviceManager, prompt};
 use clap::Parser;
 use crossterm::terminal;
 use crossterm::tty::IsTty;
@@ -11,20 +16,23 @@ mod complete;
 mod dumb;
 mod interactive;
 mod io;
+mod many;
 mod marker;
 mod pty;
 
 #[derive(Parser, Debug)]
 #[command(about)]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
     #[arg(
         short,
         long,
         value_name = "DEVICE",
         env = "ARES_DEVICE",
-        help = "Specify DEVICE to use"
+        value_delimiter = ',',
+        help = "Specify DEVICE to use, or @GROUP or tag:TAG for many. Separate several with commas"
     )]
-    device: Option<String>,
+    device: Vec<String>,
     #[arg(short, long, value_name = "COMMAND", help = "Run COMMAND")]
     run: Option<String>,
     #[arg(long, group = "pty_opt", help = "Force pseudo-terminal allocation")]
@@ -36,20 +44,71 @@ struct Cli {
         help = "Disable the local prompt and line editor used without a pseudo-terminal"
     )]
     no_prompt: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Text,
+        help = "Print errors, and the results of a run on many devices, as FORMAT: text or json"
+    )]
+    output: OutputFormat,
+    #[arg(
+        long,
+        help = "Never prompt. Fail when a passphrase or password is missing"
+    )]
+    batch: bool,
+    #[arg(
+        short = 'o',
+        long = "ssh-option",
+        value_name = "KEY=VALUE",
+        help = "Connection setting such as ConnectTimeout=30 (repeatable). Wins over the device list"
+    )]
+    ssh_option: Vec<String>,
 }
 
 fn main() {
     let cli = Cli::parse();
+    set_output_format(cli.output);
+    prompt::set_batch(cli.batch);
+    fail(
+        session::set_ssh_options(&cli.ssh_option),
+        "read --ssh-option",
+    );
     let manager = DeviceManager::default();
     // A local failure exits 255, so it can't be mistaken for the remote command's status.
-    let Some(device) = fail(manager.find_or_default(cli.device.as_ref()), "find device") else {
-        eprintln!("Device not found");
-        exit(255);
+    let devices = fail(manager.resolve(&cli.device), "find device");
+    let [device] = devices.as_slice() else {
+        let Some(command) = &cli.run else {
+            CliError::new(
+                ErrorKind::InvalidInput,
+                "A shell works on one device at a time. Give --run COMMAND to run it on many",
+            )
+            .with_exit_code(255)
+            .exit();
+        };
+        let results = for_each_device(&devices, |device| many::run_on(device, command));
+        // The status all devices agree on, as for a failure.
+        let first = results.first().map_or(0, |r| r.exit_status);
+        exit(if results.iter().all(|r| r.exit_status == first) {
+            first
+        } else {
+            1
+        });
     };
 
-    let session = fail(device.new_session(), &format!("connect to {}", device.name));
-    let ch = fail(session.new_channel(), "open a channel");
-    fail(ch.open_session(), "open a session");
+    let session = Arc::new(fail(
+        device.new_session(),
+        &format!("connect to {}", device.name),
+    ));
+    // A shell can sit idle for long. Keep routers from dropping it meanwhile.
+    keepalive::keep_alive(&session);
+    let ch = fail(
+        session.new_channel().map_err(SessionError::from),
+        "open a channel",
+    );
+    fail(
+        ch.open_session().map_err(SessionError::from),
+        "open a session",
+    );
     let mut has_pty = false;
     if !cli.no_pty && (cli.pty || stdout().is_tty()) {
         let (width, height) = terminal::size().unwrap_or((80, 24));
@@ -58,8 +117,12 @@ fn main() {
             // Only --pty makes this fatal, so only there is the reason worth
             // printing. Otherwise say what happens next instead.
             if cli.pty {
-                eprintln!("The device refused a pseudo-terminal: {e:?}");
-                exit(255);
+                CliError::new(
+                    ErrorKind::Device,
+                    format!("The device refused a pseudo-terminal: {e:?}"),
+                )
+                .with_exit_code(255)
+                .exit();
             }
             eprintln!("PTY is not available, using dumb shell instead.");
         } else {
@@ -68,9 +131,15 @@ fn main() {
     }
     let run_command = cli.run.is_some();
     if let Some(command) = cli.run {
-        fail(ch.request_exec(&command), "run the command");
+        fail(
+            ch.request_exec(&command).map_err(SessionError::from),
+            "run the command",
+        );
     } else {
-        fail(ch.request_shell(), "start a shell");
+        fail(
+            ch.request_shell().map_err(SessionError::from),
+            "start a shell",
+        );
     }
     // Without a remote pty the shell has no prompt, no echo and no line editing.
     // Draw them locally instead, as long as there is a user in front of us.
@@ -79,23 +148,19 @@ fn main() {
     let result = if has_pty {
         pty::shell(ch)
     } else if local_prompt {
-        interactive::shell(ch, &device)
+        interactive::shell(ch, device)
     } else {
         dumb::shell(ch)
     };
     match result {
         Ok(code) => exit(code),
-        Err(e) => {
-            eprintln!("Error: {e}");
-            exit(255);
-        }
+        Err(e) => CliError::new(e.error_kind(), format!("Error: {e}"))
+            .with_exit_code(255)
+            .exit(),
     }
 }
 
-/// Return the value, or print `Failed to <action>: <error>` and exit with code 255.
-fn fail<T, E: std::fmt::Display>(result: Result<T, E>, action: &str) -> T {
-    result.unwrap_or_else(|e| {
-        eprintln!("Failed to {action}: {e}");
-        exit(255);
-    })
+/// Return the value, or report `Failed to <action>: <error>` and exit with code 255.
+fn fail<T, E: std::fmt::Display + Classify>(result: Result<T, E>, action: &str) -> T {
+    result.unwrap_or_else(|e| CliError::failed_to(action, &e).with_exit_code(255).exit())
 }
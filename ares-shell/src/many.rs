@@ -0,0 +1,166 @@
+//! `--run` on many devices, one after another, for `-d @GROUP` and the like.
+
+use std::io::{Error, Write, stderr, stdout};
+use std::thread;
+use std::time::Duration;
+
+use ares_connection_lib::session::{NewSession, SessionError};
+use ares_device_lib::Device;
+use ares_device_lib::cli::{CliError, json_output};
+use libssh_rs::Channel;
+use libssh_rs::Error::TryAgain;
+use serde::Serialize;
+
+use crate::io::io_error;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What `--output json` prints for one device: the exit status and what the
+/// command wrote.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommandResult {
+    pub(crate) exit_status: i32,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+/// Run `command` on `device` with no input. Its output goes to ours with the
+/// device name before each line, or into the result with `--output json`.
+pub(crate) fn run_on(device: &Device, command: &str) -> Result<CommandResult, CliError> {
+    let fail = |action: &str, e: SessionError| CliError::failed_to(action, &e).with_exit_code(255);
+    let session = device
+        .new_session()
+        .map_err(|e| fail(&format!("connect to {}", device.name), e))?;
+    let ch = session
+        .new_channel()
+        .map_err(|e| fail("open a channel", e.into()))?;
+    ch.open_session()
+        .map_err(|e| fail("open a session", e.into()))?;
+    ch.request_exec(command)
+        .map_err(|e| fail("run the command", e.into()))?;
+    let _ = ch.send_eof();
+
+    let label = format!("{}: ", device.name);
+    let (status, out, err) = if json_output() {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let status = collect(&ch, &mut out, &mut err);
+        (status, out, err)
+    } else {
+        let status = collect(
+            &ch,
+            &mut Labeled::new(stdout(), &label),
+            &mut Labeled::new(stderr(), &label),
+        );
+        (status, Vec::new(), Vec::new())
+    };
+    let status =
+        status.map_err(|e| CliError::failed_to("read the output", &e).with_exit_code(255))?;
+    Ok(CommandResult {
+        exit_status: status,
+        stdout: String::from_utf8_lossy(&out).into_owned(),
+        stderr: String::from_utf8_lossy(&err).into_owned(),
+    })
+}
+
+/// Copy both remote streams until the command ends, and return its status.
+fn collect<O: Write, E: Write>(ch: &Channel, out: &mut O, err: &mut E) -> Result<i32, Error> {
+    let mut buf = [0u8; 8192];
+    loop {
+        let wrote = drain(ch, &mut buf, false, out)? | drain(ch, &mut buf, true, err)?;
+        if ch.is_eof() || ch.is_closed() {
+            break;
+        }
+        if !wrote {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    // Whatever arrived along with the end of the stream.
+    drain(ch, &mut buf, false, out)?;
+    drain(ch, &mut buf, true, err)?;
+    out.flush()?;
+    err.flush()?;
+    Ok(ch.get_exit_status().unwrap_or(-1))
+}
+
+/// Write what one remote stream has now, without blocking. Returns whether
+/// there was anything.
+fn drain<W: Write>(
+    ch: &Channel,
+    buf: &mut [u8],
+    is_stderr: bool,
+    out: &mut W,
+) -> Result<bool, Error> {
+    let mut wrote = false;
+    loop {
+        match ch.read_nonblocking(buf, is_stderr) {
+            Ok(0) | Err(TryAgain) => return Ok(wrote),
+            Ok(size) => {
+                out.write_all(&buf[..size])?;
+                wrote = true;
+            }
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+}
+
+/// Puts a label before every line written through it.
+struct Labeled<W: Write> {
+    inner: W,
+    label: Vec<u8>,
+    line_start: bool,
+}
+
+impl<W: Write> Labeled<W> {
+    fn new(inner: W, label: &str) -> Self {
+        Self {
+            inner,
+            label: label.as_bytes().to_vec(),
+            line_start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for Labeled<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.line_start {
+                self.inner.write_all(&self.label)?;
+            }
+            self.inner.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Labeled<W> {
+    /// End a last line that had no newline, so the next device starts on its own.
+    fn drop(&mut self) {
+        if !self.line_start {
+            let _ = self.inner.write_all(b"\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::Labeled;
+
+    #[test]
+    fn every_line_gets_the_label() {
+        let mut out = Vec::new();
+        {
+            let mut labeled = Labeled::new(&mut out, "tv: ");
+            labeled.write_all(b"one\ntw").unwrap();
+            labeled.write_all(b"o\nthree").unwrap();
+        }
+        assert_eq!(out, b"tv: one\ntv: two\ntv: three\n");
+    }
+}
@@ -0,0 +1,251 @@
+//! What a device can do, found out once instead of by failing.
+//!
+//! [`DeviceSession::capabilities`] runs one shell script on the device, and
+//! tries an SFTP handshake. The result is kept in `capabilities.json` next to
+//! the device list for [`CACHE_TTL`], so most runs don't probe at all.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ares_device_lib::{Device, DeviceManager};
+use libssh_rs::Session;
+use serde::{Deserialize, Serialize};
+
+use crate::session::DeviceSession;
+use crate::transfer::TransferError;
+
+/// How long a probe stays good. An update of the firmware or of the Homebrew
+/// Channel can change the answers, so it isn't kept forever.
+pub const CACHE_TTL: Duration = Duration::from_hours(24);
+
+const CACHE_FILE_NAME: &str = "capabilities.json";
+
+/// Prints `key=value` lines. Each line stands alone, so a command the device
+/// lacks only leaves its own value empty.
+const PROBE: &str = r#"echo "uid=$(id -u 2>/dev/null)"
+echo "arch=$(uname -m 2>/dev/null)"
+echo "version=$(nyx-cmd OSInfo query webos_release 2>/dev/null)"
+echo "build=$(nyx-cmd OSInfo query webos_build_id 2>/dev/null)"
+echo "soc=$(nyx-cmd DeviceInfo query board_type 2>/dev/null)"
+for c in sha256sum tar gzip find; do command -v $c >/dev/null 2>&1 && echo "has=$c"; done
+[ -d /media/developer/apps/usr/palm/applications/org.webosbrew.hbchannel ] && echo hbchannel=1
+true"#;
+
+/// What a device runs and which commands it has.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Capabilities {
+    /// The webOS release, such as `6.3.1`.
+    pub webos_version: Option<String>,
+    /// The firmware build, such as `dreadlocks2-dudhwa`.
+    pub webos_build: Option<String>,
+    /// The board the firmware names, which tells the chip, such as `O20N_DVB_EU`.
+    pub soc: Option<String>,
+    /// What `uname -m` says, such as `armv7l` or `aarch64`.
+    pub arch: Option<String>,
+    /// `true` when an SFTP session starts.
+    pub sftp: bool,
+    pub sha256sum: bool,
+    pub tar: bool,
+    pub gzip: bool,
+    pub find: bool,
+    /// The user id the login really has.
+    pub uid: Option<u32>,
+    /// `true` when the Homebrew Channel is installed.
+    pub homebrew_channel: bool,
+}
+
+impl Capabilities {
+    /// `true` when the login is root, as on a rooted device.
+    #[must_use]
+    pub fn is_root(&self) -> bool {
+        self.uid == Some(0)
+    }
+
+    /// Run the probe on `session`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the script can't run at all.
+    pub fn probe(session: &Session) -> Result<Self, TransferError> {
+        let ch = session.new_channel()?;
+        ch.open_session()?;
+        ch.request_exec(PROBE)?;
+        ch.send_eof()?;
+        let mut out = String::new();
+        ch.stdout().read_to_string(&mut out)?;
+        ch.close()?;
+        let mut capabilities = Self::parse(&out);
+        capabilities.sftp = session.sftp().is_ok();
+        Ok(capabilities)
+    }
+
+    /// Read the output of the probe script. SFTP is left off.
+    fn parse(out: &str) -> Self {
+        let mut capabilities = Self::default();
+        for line in out.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            let text = || Some(value.to_string()).filter(|v| !v.is_empty());
+            match key {
+                "uid" => capabilities.uid = value.parse().ok(),
+                "arch" => capabilities.arch = text(),
+                "version" => capabilities.webos_version = text(),
+                "build" => capabilities.webos_build = text(),
+                "soc" => capabilities.soc = text(),
+                "has" => match value {
+                    "sha256sum" => capabilities.sha256sum = true,
+                    "tar" => capabilities.tar = true,
+                    "gzip" => capabilities.gzip = true,
+                    "find" => capabilities.find = true,
+                    _ => {}
+                },
+                "hbchannel" => capabilities.homebrew_channel = true,
+                _ => {}
+            }
+        }
+        capabilities
+    }
+}
+
+/// One device in the cache. The address is kept to notice a name that now
+/// points at another device.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Cached {
+    host: String,
+    port: u16,
+    username: String,
+    /// Seconds since the Unix epoch.
+    probed_at: u64,
+    #[serde(flatten)]
+    capabilities: Capabilities,
+}
+
+impl DeviceSession {
+    /// What the device can do. Probed on first use, unless the cache has an
+    /// answer younger than [`CACHE_TTL`], and kept for the rest of the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the probe can't run. A cache that can't be read
+    /// or written only means probing again next time.
+    pub fn capabilities(&self) -> Result<&Capabilities, TransferError> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities);
+        }
+        let cache = DeviceManager::default()
+            .conf_dir()
+            .ok()
+            .map(|dir| dir.join(CACHE_FILE_NAME));
+        let now = unix_now();
+        let cached = cache
+            .as_deref()
+            .and_then(|cache| read_cached(cache, &self.device, now));
+        let capabilities = if let Some(capabilities) = cached {
+            capabilities
+        } else {
+            let capabilities = Capabilities::probe(&self.session)?;
+            // An SFTP handshake can fail once and work the next time, so a
+            // probe that found no SFTP is left out of the cache.
+            if let Some(cache) = cache.as_ref().filter(|_| capabilities.sftp) {
+                write_cached(cache, &self.device, &capabilities, now);
+            }
+            capabilities
+        };
+        Ok(self.capabilities.get_or_init(|| capabilities))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn read_cache(cache: &Path) -> BTreeMap<String, Cached> {
+    fs::read_to_string(cache)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn read_cached(cache: &Path, device: &Device, now: u64) -> Option<Capabilities> {
+    let cached = read_cache(cache).remove(&device.name)?;
+    let fresh = now.saturating_sub(cached.probed_at) < CACHE_TTL.as_secs();
+    let same = cached.host == device.host
+        && cached.port == device.port
+        && cached.username == device.username;
+    (fresh && same).then_some(cached.capabilities)
+}
+
+fn write_cached(cache: &Path, device: &Device, capabilities: &Capabilities, now: u64) {
+    let mut entries = read_cache(cache);
+    entries.insert(
+        device.name.clone(),
+        Cached {
+            host: device.host.clone(),
+            port: device.port,
+            username: device.username.clone(),
+            probed_at: now,
+            capabilities: capabilities.clone(),
+        },
+    );
+    if let Ok(text) = serde_json::to_string_pretty(&entries) {
+        let _ = fs::write(cache, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use ares_device_lib::Device;
+
+    use super::{CACHE_TTL, Capabilities, read_cached, write_cached};
+
+    #[test]
+    fn probe_output_reads_line_by_line() {
+        let capabilities = Capabilities::parse(
+            "uid=0\narch=armv7l\nversion=6.3.1\nbuild=\nsoc=O20N\n\
+             has=sha256sum\nhas=find\nhbchannel=1\n",
+        );
+        assert!(capabilities.is_root());
+        assert_eq!(capabilities.arch.as_deref(), Some("armv7l"));
+        assert_eq!(capabilities.webos_version.as_deref(), Some("6.3.1"));
+        assert_eq!(capabilities.webos_build, None);
+        assert!(capabilities.sha256sum && capabilities.find);
+        assert!(!capabilities.tar && !capabilities.gzip && !capabilities.sftp);
+        assert!(capabilities.homebrew_channel);
+
+        let bare = Capabilities::parse("uid=\narch=\n");
+        assert_eq!(bare, Capabilities::default());
+    }
+
+    #[test]
+    fn the_cache_expires_and_follows_the_address() {
+        let dir = std::env::temp_dir().join(format!("ares-capabilities-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cache = dir.join("capabilities.json");
+        let mut tv = Device::with_defaults("tv", "10.0.0.5");
+        let capabilities = Capabilities {
+            tar: true,
+            ..Capabilities::default()
+        };
+
+        write_cached(&cache, &tv, &capabilities, 1000);
+        assert_eq!(read_cached(&cache, &tv, 1000), Some(capabilities.clone()));
+        let later = 1000 + CACHE_TTL.as_secs();
+        assert_eq!(read_cached(&cache, &tv, later), None);
+
+        tv.host = String::from("10.0.0.6");
+        assert_eq!(read_cached(&cache, &tv, 1000), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+//! Reaching a device through a gateway, as OpenSSH's `ProxyJump` does.
+//!
+//! The gateway gets a session of its own, logged in the way the device list
+//! says. A `direct-tcpip` channel on it leads to the device, and a thread pumps
+//! bytes between that channel and one end of a local socket pair. The device's
+//! session runs over the other end, so nothing above [`crate::session::connect`]
+//! knows about the gateway.
+
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Write};
+use std::thread;
+
+use ares_device_lib::{Device, DeviceManager};
+use libssh_rs::{Channel, Session};
+
+use crate::session::{NewSession, SessionError};
+
+/// More gateways in a row than this is taken for a loop in the device list.
+const MAX_HOPS: usize = 8;
+
+/// The device `device.proxyJump` names: one in the device list, or one built
+/// from `user@host:port`.
+///
+/// # Errors
+///
+/// Returns [`IoErrorKind::NotFound`] if it names no device, or
+/// [`IoErrorKind::InvalidInput`] if the gateways lead back to a device already
+/// on the way.
+pub fn gateway(manager: &DeviceManager, device: &Device) -> Result<Option<Device>, IoError> {
+    let Some(first) = &device.proxy_jump else {
+        return Ok(None);
+    };
+    let mut seen = vec![device.name.clone()];
+    let mut next = Some(first.clone());
+    let mut gateway = None;
+    while let Some(name) = next.take() {
+        if seen.contains(&name) || seen.len() > MAX_HOPS {
+            return Err(IoError::new(
+                IoErrorKind::InvalidInput,
+                format!("The proxyJump of {} leads back to {name}", device.name),
+            ));
+        }
+        let hop = manager.find_or_default(Some(&name))?.ok_or_else(|| {
+            IoError::new(
+                IoErrorKind::NotFound,
+                format!("Gateway {name} of {} not found", device.name),
+            )
+        })?;
+        next.clone_from(&hop.proxy_jump);
+        seen.push(name);
+        gateway.get_or_insert(hop);
+    }
+    Ok(gateway)
+}
+
+/// Log in to `gateway` and open a tunnel from it to `device`. Returns the
+/// socket for the device's session to use. libssh closes it with the session,
+/// and the tunnel and the gateway's session close after it.
+pub(crate) fn tunnel(gateway: &Device, device: &Device) -> Result<Socket, SessionError> {
+    let session = gateway.new_session()?;
+    let channel = session.new_channel()?;
+    channel
+        .open_forward(&device.host, device.port, "127.0.0.1", 0)
+        .map_err(|e| SessionError::from_connect(e, device))?;
+    let (ours, theirs) = socket_pair()?;
+    thread::spawn(move || {
+        let _ = pump(ours, &session, &channel);
+        let _ = channel.close();
+        drop(session);
+    });
+    Ok(theirs)
+}
+
+/// Copy bytes both ways until either side closes, sleeping until the local
+/// socket or the gateway's connection has something to read.
+///
+/// One thread per direction won't do: every call on a channel holds the lock
+/// of its session, so a read waiting for the device would hold up writes.
+fn pump(mut local: Local, session: &Session, channel: &Channel) -> Result<(), IoError> {
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        // libssh may already hold data it took off the socket, which waiting
+        // on the socket wouldn't see.
+        loop {
+            match channel.read_nonblocking(&mut buf, false) {
+                Ok(0) => break,
+                Ok(n) => local.write_all(&buf[..n])?,
+                Err(e) => return Err(IoError::other(e.to_string())),
+            }
+        }
+        if channel.is_eof() || channel.is_closed() {
+            return Ok(());
+        }
+        if wait(&local, session)? {
+            match local.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => channel.stdin().write_all(&buf[..n])?,
+                Err(e) if e.kind() == IoErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Block until `local` or `session` can be read. Returns whether `local` can.
+#[cfg(unix)]
+fn wait(local: &Local, session: &Session) -> Result<bool, IoError> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fds = [local.as_raw_fd(), session.as_raw_fd()].map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+    // SAFETY: `fds` is a valid array of `pollfd` for the length given.
+    while unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+        let error = IoError::last_os_error();
+        if error.kind() != IoErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(fds[0].revents != 0)
+}
+
+/// Block until `local` or `session` can be read. Returns whether `local` can.
+#[cfg(windows)]
+fn wait(local: &Local, session: &Session) -> Result<bool, IoError> {
+    use std::os::windows::io::AsRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{POLLRDNORM, SOCKET, WSAPOLLFD, WSAPoll};
+
+    let mut fds = [local.as_raw_socket(), session.as_raw_socket()].map(|fd| WSAPOLLFD {
+        fd: fd as SOCKET,
+        events: POLLRDNORM,
+        revents: 0,
+    });
+    // SAFETY: `fds` is a valid array of `WSAPOLLFD` for the length given.
+    if unsafe { WSAPoll(fds.as_mut_ptr(), 2, -1) } < 0 {
+        return Err(IoError::last_os_error());
+    }
+    Ok(fds[0].revents != 0)
+}
+
+#[cfg(unix)]
+type Local = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type Local = std::net::TcpStream;
+
+#[cfg(unix)]
+pub(crate) type Socket = std::os::unix::io::RawFd;
+#[cfg(windows)]
+pub(crate) type Socket = std::os::windows::io::RawSocket;
+
+/// Two connected sockets. The second is handed over as a raw socket, since
+/// libssh takes ownership of it.
+#[cfg(unix)]
+fn socket_pair() -> Result<(Local, Socket), IoError> {
+    use std::os::unix::io::IntoRawFd;
+
+    let (ours, theirs) = Local::pair()?;
+    Ok((ours, theirs.into_raw_fd()))
+}
+
+/// Windows has no socket pair, so connect over loopback. The accepted
+/// connection must come from the socket just connected, not from another
+/// program that found the port first.
+#[cfg(windows)]
+fn socket_pair() -> Result<(Local, Socket), IoError> {
+    use std::net::TcpListener;
+    use std::os::windows::io::IntoRawSocket;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let theirs = Local::connect(listener.local_addr()?)?;
+    let (ours, peer) = listener.accept()?;
+    if peer != theirs.local_addr()? {
+        return Err(IoError::new(
+            IoErrorKind::ConnectionRefused,
+            "Another program connected to the tunnel",
+        ));
+    }
+    Ok((ours, theirs.into_raw_socket()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    use ares_device_lib::{Device, DeviceManager};
+
+    use super::gateway;
+
+    fn manager(label: &str, devices: &str) -> (DeviceManager, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("ares-jump-{label}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("novacom-devices.json"), devices).unwrap();
+        (DeviceManager::with_dirs(dir.clone(), dir.join("ssh")), dir)
+    }
+
+    fn device(name: &str, jump: Option<&str>) -> String {
+        let jump = jump.map_or_else(String::new, |jump| format!(r#","proxyJump":"{jump}""#));
+        format!(
+            r#"{{"profile":"ose","name":"{name}","host":"10.0.0.1","port":22,"username":"root"{jump}}}"#
+        )
+    }
+
+    #[test]
+    fn the_gateway_is_a_device_or_an_address() {
+        let (manager, dir) = manager(
+            "gateway",
+            &format!(
+                "[{},{}]",
+                device("tv", Some("bastion")),
+                device("bastion", None)
+            ),
+        );
+        let tv = &manager.list().unwrap()[0];
+        assert_eq!(gateway(&manager, tv).unwrap().unwrap().name, "bastion");
+
+        let mut direct = tv.clone();
+        direct.proxy_jump = Some(String::from("admin@gw.lab:2222"));
+        let hop = gateway(&manager, &direct).unwrap().unwrap();
+        assert_eq!((hop.username.as_str(), hop.port), ("admin", 2222));
+
+        direct.proxy_jump = None;
+        assert!(gateway(&manager, &direct).unwrap().is_none());
+
+        let missing = Device {
+            proxy_jump: Some(String::from("nowhere")),
+            ..tv.clone()
+        };
+        let error = gateway(&manager, &missing).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_loop_of_gateways_is_refused() {
+        let (manager, dir) = manager(
+            "loop",
+            &format!(
+                "[{},{},{}]",
+                device("tv", Some("a")),
+                device("a", Some("b")),
+                device("b", Some("a"))
+            ),
+        );
+        let tv = &manager.list().unwrap()[0];
+        let error = gateway(&manager, tv).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
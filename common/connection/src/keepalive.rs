@@ -0,0 +1,145 @@
+//! Keeping a quiet connection open, and opening it again when it drops.
+//!
+//! A tool that runs for long, like a port forward, sends a keepalive every
+//! `serverAliveInterval` seconds so that routers don't forget the connection.
+//! libssh gives no reply to wait for, so a keepalive counts as missed when it
+//! can't be sent or the session has closed. After `serverAliveCountMax` misses
+//! in a row, a [`ReconnectingSession`] opens a new session and says so.
+
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+use std::time::Duration;
+
+use ares_device_lib::Device;
+use ares_device_lib::cli::eprint_line;
+
+use crate::session::{ConnectOptions, DeviceSession, MAX_BACKOFF, NewSession, SessionError};
+
+/// Sent as the payload of each keepalive. libssh reads it as a C string.
+const PING: &[u8] = b"ares\0";
+
+/// `true` when the keepalive went out on a live session.
+fn ping(session: &DeviceSession) -> bool {
+    session.is_connected() && session.send_ignore(PING).is_ok()
+}
+
+/// Send keepalives on `session` until it is dropped. Does nothing when
+/// `serverAliveInterval` is 0.
+pub fn keep_alive(session: &Arc<DeviceSession>) {
+    let Some(interval) = ConnectOptions::for_device(&session.device).server_alive_interval else {
+        return;
+    };
+    let session = Arc::downgrade(session);
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            let Some(session) = session.upgrade() else {
+                return;
+            };
+            ping(&session);
+        }
+    });
+}
+
+/// A session to one device that is opened again whenever it drops.
+///
+/// Take the live session with [`ReconnectingSession::session`] each time you
+/// open a channel. When a channel fails on a session that has closed, call
+/// [`ReconnectingSession::reconnect`] with it.
+pub struct ReconnectingSession {
+    device: Device,
+    current: Mutex<Arc<DeviceSession>>,
+}
+
+impl ReconnectingSession {
+    /// Connect to `device`, and watch the session with keepalives.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first connection. Only later ones are retried.
+    pub fn open(device: &Device) -> Result<Arc<Self>, SessionError> {
+        let session = Arc::new(device.new_session()?);
+        let reconnecting = Arc::new(Self {
+            device: device.clone(),
+            current: Mutex::new(session),
+        });
+        if let Some(interval) = ConnectOptions::for_device(device).server_alive_interval {
+            let watched = Arc::downgrade(&reconnecting);
+            thread::spawn(move || watch(&watched, interval));
+        }
+        Ok(reconnecting)
+    }
+
+    /// The session that is live now.
+    #[must_use]
+    pub fn session(&self) -> Arc<DeviceSession> {
+        Arc::clone(&self.current.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Replace `stale` with a new session, and return that.
+    ///
+    /// When another thread has replaced `stale` already, its session is
+    /// returned and nothing is opened. A device that is away is tried again,
+    /// with waits that grow to 30 seconds, until it is back.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error when the device turns the login down, which trying
+    /// again won't change.
+    pub fn reconnect(
+        &self,
+        stale: &Arc<DeviceSession>,
+    ) -> Result<Arc<DeviceSession>, SessionError> {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        if !Arc::ptr_eq(&current, stale) {
+            return Ok(Arc::clone(&current));
+        }
+        let name = &self.device.name;
+        eprint_line(format!("Lost the connection to {name}. Reconnecting..."));
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match self.device.new_session() {
+                Ok(session) => {
+                    *current = Arc::new(session);
+                    eprint_line(format!("Reconnected to {name}"));
+                    return Ok(Arc::clone(&current));
+                }
+                Err(e) if e.is_transient() => {
+                    eprint_line(format!("{e}. Trying again in {}s", delay.as_secs()));
+                    thread::sleep(delay);
+                    delay = (delay * 2).min(MAX_BACKOFF);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Ping the live session every `interval`, and reconnect after too many
+/// misses. Stops when the [`ReconnectingSession`] is dropped.
+fn watch(reconnecting: &Weak<ReconnectingSession>, interval: Duration) {
+    let mut missed = 0;
+    loop {
+        thread::sleep(interval);
+        let Some(reconnecting) = reconnecting.upgrade() else {
+            return;
+        };
+        let session = reconnecting.session();
+        if ping(&session) {
+            missed = 0;
+            continue;
+        }
+        missed += 1;
+        let count_max = ConnectOptions::for_device(&reconnecting.device).server_alive_count_max;
+        if missed >= count_max {
+            missed = 0;
+            if let Err(e) = reconnecting.reconnect(&session) {
+                eprint_line(format!(
+                    "Failed to reconnect to {}: {e}",
+                    reconnecting.device.name
+                ));
+                return;
+            }
+        }
+    }
+}
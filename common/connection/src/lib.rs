@@ -1,5 +1,8 @@
 use std::io::Error;
 
+pub mod capabilities;
+pub mod jump;
+pub mod keepalive;
 pub mod luna;
 pub mod session;
 pub mod setup;
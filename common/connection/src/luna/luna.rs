@@ -5,7 +5,8 @@ use serde::Serialize;
 use serde::de::DeserializeOwned;
 use serde_json::Error as JsonError;
 
-use crate::luna::{Luna, LunaError, Subscription};
+use crate::luna::response::method_name;
+use crate::luna::{Luna, LunaError, LunaResponse, Subscription};
 use crate::session::SessionError;
 
 impl Luna for Session {
@@ -17,22 +18,25 @@ impl Luna for Session {
         let ch = self.new_channel()?;
         ch.open_session()?;
         let luna_cmd = if public { "luna-send-pub" } else { "luna-send" };
+        let method = method_name(uri);
         let uri = snailquote::escape(uri.into());
         let payload_str = serde_json::to_string(&payload)?;
         ch.request_exec(&format!(
             "{luna_cmd} -n 1 {uri} {}",
             snailquote::escape(&payload_str)
         ))?;
-        let mut buf = String::new();
-        ch.stdout().read_to_string(&mut buf)?;
+        let mut stdout = String::new();
+        ch.stdout().read_to_string(&mut stdout)?;
         let mut stderr = String::new();
         ch.stderr().read_to_string(&mut stderr)?;
         let exit_code = ch.get_exit_status().unwrap_or(0);
         ch.close()?;
-        if exit_code == 0 {
-            return Ok(serde_json::from_str(&buf)?);
+        // A failed reply can come with a non-zero exit, and says more than it.
+        let response = serde_json::from_str::<LunaResponse>(&stdout);
+        if exit_code != 0 && !response.as_ref().is_ok_and(|r| !r.return_value) {
+            return Err(LunaError::failed(method, exit_code, stdout, stderr));
         }
-        Err(LunaError::NotAvailable)
+        Ok(serde_json::from_value(response?.into_result(method)?)?)
     }
 
     fn subscribe<P>(&self, uri: &str, payload: P, public: bool) -> Result<Subscription, LunaError>
@@ -42,6 +46,7 @@ impl Luna for Session {
         let ch = self.new_channel()?;
         ch.open_session()?;
         let luna_cmd = if public { "luna-send-pub" } else { "luna-send" };
+        let method = method_name(uri);
         let uri = snailquote::escape(uri.into());
         let payload_str = serde_json::to_string(&payload)?;
         ch.request_exec(&format!(
@@ -50,6 +55,7 @@ impl Luna for Session {
         ))?;
         Ok(Subscription {
             ch,
+            method: method.to_string(),
             buffer: Vec::new(),
         })
     }
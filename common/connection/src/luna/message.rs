@@ -1,9 +1,20 @@
 use serde::de::DeserializeOwned;
 
-use crate::luna::Message;
+use crate::luna::{LunaError, LunaResponse, Message};
 
 impl Message {
     pub fn deserialize<T: DeserializeOwned>(self) -> Result<T, serde_json::Error> {
         serde_json::from_value(self.value)
     }
+
+    /// The payload of the message, or the error it reports.
+    ///
+    /// # Errors
+    ///
+    /// Returns what [`LunaResponse::into_result`] returns, or an error when
+    /// the payload doesn't parse as `T`.
+    pub fn response<T: DeserializeOwned>(self) -> Result<T, LunaError> {
+        let response: LunaResponse = serde_json::from_value(self.value)?;
+        Ok(serde_json::from_value(response.into_result(&self.method)?)?)
+    }
 }
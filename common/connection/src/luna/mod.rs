@@ -1,15 +1,17 @@
 use std::fmt::{Display, Formatter};
 use std::io::Error as IoError;
 
+use ares_device_lib::cli::{Classify, ErrorKind};
 use libssh_rs::Channel;
-use serde::Serialize;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::session::SessionError;
 
 mod luna;
 mod message;
+mod response;
 mod subscription;
 
 pub trait Luna {
@@ -27,7 +29,31 @@ pub trait Luna {
 pub enum LunaError {
     Session(SessionError),
     Io(IoError),
-    NotAvailable,
+    /// The service answered `returnValue: false`.
+    Service {
+        method: String,
+        code: Option<i32>,
+        text: String,
+    },
+    /// The hub turned the call down: the method isn't public, or the ACG of
+    /// the caller doesn't allow it.
+    Denied {
+        method: String,
+        text: String,
+    },
+    /// The service or the method isn't on the device.
+    NoService {
+        method: String,
+        text: String,
+    },
+    /// `luna-send` failed without an answer from the service. What it printed
+    /// is kept, to see what happened.
+    Failed {
+        method: String,
+        exit_code: i32,
+        stdout: String,
+        stderr: String,
+    },
 }
 
 impl Display for LunaError {
@@ -35,20 +61,103 @@ impl Display for LunaError {
         match self {
             LunaError::Session(e) => write!(f, "{e}"),
             LunaError::Io(e) => write!(f, "{e}"),
-            LunaError::NotAvailable => write!(f, "luna service is not available"),
+            LunaError::Service { method, code, text } => match code {
+                Some(code) => write!(f, "{method} answered: {text} (error {code})"),
+                None => write!(f, "{method} answered: {text}"),
+            },
+            LunaError::Denied { method, text } => write!(f, "{method} is not allowed: {text}"),
+            LunaError::NoService { method, text } => {
+                write!(f, "{method} is not on the device: {text}")
+            }
+            LunaError::Failed {
+                method,
+                exit_code,
+                stderr,
+                ..
+            } => {
+                write!(f, "luna-send for {method} exited with {exit_code}")?;
+                match stderr.trim() {
+                    "" => Ok(()),
+                    stderr => write!(f, ": {stderr}"),
+                }
+            }
         }
     }
 }
 
 impl std::error::Error for LunaError {}
 
+impl Classify for LunaError {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            LunaError::Session(e) => e.error_kind(),
+            LunaError::Io(e) => e.error_kind(),
+            LunaError::Service { .. } | LunaError::Failed { .. } => ErrorKind::Device,
+            LunaError::Denied { .. } => ErrorKind::PermissionDenied,
+            LunaError::NoService { .. } => ErrorKind::NotFound,
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            LunaError::Session(e) => e.exit_code(),
+            LunaError::Io(_) | LunaError::Service { .. } => 1,
+            // Apart from the codes of `SessionError`, so that a script can
+            // tell a refused login from a refused call.
+            LunaError::Denied { .. } => 80,
+            LunaError::NoService { .. } => 81,
+            LunaError::Failed { .. } => 82,
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        match self {
+            LunaError::Session(e) => e.hint(),
+            LunaError::Denied { .. } => Some(String::from(
+                "Developer Mode only reaches public methods. Others need a rooted device",
+            )),
+            LunaError::NoService { .. } => Some(String::from(
+                "It may be missing from the webOS version of the device",
+            )),
+            LunaError::Failed { exit_code: 127, .. } => Some(String::from(
+                "luna-send is missing, so this may not be a webOS device",
+            )),
+            LunaError::Io(_) | LunaError::Service { .. } | LunaError::Failed { .. } => None,
+        }
+    }
+}
+
+/// The reply of a luna service. It has `returnValue`, and one that
+/// failed has `errorCode` and `errorText`. The other fields are `T`'s.
+///
+/// A failed reply usually lacks fields `T` needs, so parse it as
+/// `LunaResponse<Value>` first, and `T` from what [`LunaResponse::into_result`]
+/// gives back. When every field of `T` is optional, it can be parsed in one go.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LunaResponse<T = Value> {
+    /// Missing in some subscription messages, which then aren't failures.
+    #[serde(default = "success")]
+    pub return_value: bool,
+    pub error_code: Option<i32>,
+    pub error_text: Option<String>,
+    #[serde(flatten)]
+    pub payload: T,
+}
+
+fn success() -> bool {
+    true
+}
+
 pub struct Subscription {
     ch: Channel,
+    method: String,
     buffer: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub struct Message {
+    method: String,
     value: Value,
 }
 
@@ -0,0 +1,188 @@
+use crate::luna::{LunaError, LunaResponse};
+
+impl<T> LunaResponse<T> {
+    /// The payload, or the error the service answered with.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LunaError::Denied`] or [`LunaError::NoService`] when the hub
+    /// turned the call down, and [`LunaError::Service`] when the service did.
+    pub fn into_result(self, method: &str) -> Result<T, LunaError> {
+        if self.return_value {
+            return Ok(self.payload);
+        }
+        let text = self
+            .error_text
+            .unwrap_or_else(|| String::from("unknown error"));
+        // Only the hub answers -1 with its own texts. A service may use
+        // either for a failure of its own.
+        let refusal = refusal(&text).filter(|_| self.error_code == Some(-1));
+        Err(match refusal {
+            Some(Refusal::Denied) => LunaError::Denied {
+                method: method.to_string(),
+                text,
+            },
+            Some(Refusal::NoService) => LunaError::NoService {
+                method: method.to_string(),
+                text,
+            },
+            None => LunaError::Service {
+                method: method.to_string(),
+                code: self.error_code,
+                text,
+            },
+        })
+    }
+}
+
+impl LunaError {
+    /// `luna-send` exited with `exit_code` and printed no failed reply. It
+    /// says on stderr, in the hub's words, when the hub turned it down.
+    pub(crate) fn failed(method: &str, exit_code: i32, stdout: String, stderr: String) -> Self {
+        let text = stderr.trim().to_string();
+        match refusal(&text) {
+            Some(Refusal::Denied) => LunaError::Denied {
+                method: method.to_string(),
+                text,
+            },
+            Some(Refusal::NoService) => LunaError::NoService {
+                method: method.to_string(),
+                text,
+            },
+            None => LunaError::Failed {
+                method: method.to_string(),
+                exit_code,
+                stdout,
+                stderr,
+            },
+        }
+    }
+}
+
+/// Why the hub didn't pass a call on.
+#[derive(Debug, Eq, PartialEq)]
+enum Refusal {
+    Denied,
+    NoService,
+}
+
+/// The hub answers error code -1 for every call it doesn't pass on, so only
+/// its own texts tell a denial from a missing service.
+fn refusal(text: &str) -> Option<Refusal> {
+    if text.contains("Denied method call") {
+        Some(Refusal::Denied)
+    } else if text.contains("Service does not exist") || text.contains("Unknown method") {
+        Some(Refusal::NoService)
+    } else {
+        None
+    }
+}
+
+/// `com.webos.applicationManager/launch` for
+/// `luna://com.webos.applicationManager/launch`.
+pub(crate) fn method_name(uri: &str) -> &str {
+    uri.strip_prefix("luna://").unwrap_or(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    use super::method_name;
+    use crate::luna::{LunaError, LunaResponse};
+
+    #[derive(Deserialize, Debug)]
+    struct Running {
+        running: Vec<String>,
+    }
+
+    fn reply(json: &str) -> Result<Value, LunaError> {
+        serde_json::from_str::<LunaResponse>(json)
+            .unwrap()
+            .into_result("com.webos.applicationManager/launch")
+    }
+
+    #[test]
+    fn a_good_reply_gives_its_payload() {
+        let payload = reply(r#"{"returnValue":true,"running":["a","b"]}"#).unwrap();
+        let running: Running = serde_json::from_value(payload).unwrap();
+        assert_eq!(running.running, ["a", "b"]);
+    }
+
+    #[test]
+    fn a_failed_reply_is_sorted_by_its_text() {
+        let error = reply(
+            r#"{"returnValue":false,"errorCode":-101,"errorText":"\"com.foo\" was not found"}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            LunaError::Service {
+                code: Some(-101),
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "com.webos.applicationManager/launch answered: \"com.foo\" was not found (error -101)"
+        );
+
+        let error = reply(
+            r#"{"returnValue":false,"errorCode":-1,"errorText":"Denied method call \"launch\" for category \"/\""}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, LunaError::Denied { .. }));
+
+        let error = reply(
+            r#"{"returnValue":false,"errorCode":-1,"errorText":"Service does not exist: com.webos.applicationManager."}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, LunaError::NoService { .. }));
+
+        // A service's own failure stays one, whatever its text says.
+        let error = reply(
+            r#"{"returnValue":false,"errorCode":-5,"errorText":"Denied method call while unpacking"}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, LunaError::Service { .. }));
+        let error =
+            reply(r#"{"returnValue":false,"errorCode":-1,"errorText":"Permission denied"}"#)
+                .unwrap_err();
+        assert!(matches!(error, LunaError::Service { .. }));
+    }
+
+    #[test]
+    fn luna_send_failing_keeps_what_it_printed() {
+        let error = LunaError::failed(
+            "a/b",
+            127,
+            String::new(),
+            String::from("sh: luna-send: not found\n"),
+        );
+        assert!(matches!(
+            &error,
+            LunaError::Failed { exit_code: 127, stderr, .. } if stderr.contains("not found")
+        ));
+        assert_eq!(
+            error.to_string(),
+            "luna-send for a/b exited with 127: sh: luna-send: not found"
+        );
+
+        let error = LunaError::failed(
+            "a/b",
+            1,
+            String::new(),
+            String::from("Denied method call \"b\" for category \"/\""),
+        );
+        assert!(matches!(error, LunaError::Denied { .. }));
+    }
+
+    #[test]
+    fn the_method_drops_the_scheme() {
+        assert_eq!(
+            method_name("luna://com.webos.service.tv.systemproperty/getSystemInfo"),
+            "com.webos.service.tv.systemproperty/getSystemInfo"
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use std::io::{Error, ErrorKind};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use libssh_rs::Error as SshError;
 
@@ -9,15 +9,42 @@ impl Iterator for Subscription {
     type Item = std::io::Result<Message>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.poll(None)
+    }
+}
+
+impl Subscription {
+    /// Wait for the next message until `deadline`. Returns an error of kind
+    /// [`ErrorKind::TimedOut`] if none has arrived by then, and `None` once
+    /// the subscription has ended.
+    pub fn next_before(&mut self, deadline: Instant) -> Option<std::io::Result<Message>> {
+        self.poll(Some(deadline))
+    }
+
+    fn close(&mut self) -> Result<i32, Error> {
+        self.ch.send_eof()?;
+        self.ch.request_send_signal("TERM")?;
+        let status = self.ch.get_exit_status();
+        self.ch.close()?;
+        Ok(status.unwrap_or(-1) as i32)
+    }
+
+    fn poll(&mut self, deadline: Option<Instant>) -> Option<std::io::Result<Message>> {
         loop {
             // Emit any complete line already buffered before reading more, so a
             // single read that returns multiple lines is drained one at a time.
-            if let Some(item) = take_line(&mut self.buffer) {
+            if let Some(item) = take_line(&mut self.buffer, &self.method) {
                 return Some(item);
             }
             if self.ch.is_closed() || self.ch.is_eof() {
                 return None;
             }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Some(Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "No message before the deadline",
+                )));
+            }
             let mut buffer = [0; 1024];
             match self
                 .ch
@@ -44,13 +71,16 @@ impl Iterator for Subscription {
 
 /// Take the first complete line out of `buffer` and parse it as one message.
 /// Returns `None` while no line has arrived yet, so the caller reads more.
-fn take_line(buffer: &mut Vec<u8>) -> Option<std::io::Result<Message>> {
+fn take_line(buffer: &mut Vec<u8>, method: &str) -> Option<std::io::Result<Message>> {
     let idx = buffer.iter().position(|&r| r == b'\n')?;
     let item = serde_json::from_slice(&buffer[..idx]);
     buffer.drain(..idx + 1);
     Some(
         item.map_err(|e| Error::new(ErrorKind::InvalidData, format!("Bad JSON response: {e:?}")))
-            .map(|value| Message { value }),
+            .map(|value| Message {
+                method: method.to_string(),
+                value,
+            }),
     )
 }
 
@@ -63,16 +93,6 @@ impl Drop for Subscription {
     }
 }
 
-impl Subscription {
-    fn close(&mut self) -> Result<i32, Error> {
-        self.ch.send_eof()?;
-        self.ch.request_send_signal("TERM")?;
-        let status = self.ch.get_exit_status();
-        self.ch.close()?;
-        Ok(status.unwrap_or(-1) as i32)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::take_line;
@@ -84,7 +104,7 @@ mod tests {
     #[test]
     fn no_line_yet_reads_more() {
         let mut buf = buffer(r#"{"returnValue":true"#);
-        assert!(take_line(&mut buf).is_none());
+        assert!(take_line(&mut buf, "a/b").is_none());
         // Nothing was consumed, so the rest of the line can still arrive.
         assert_eq!(buf, buffer(r#"{"returnValue":true"#));
     }
@@ -95,29 +115,35 @@ mod tests {
         // more than one of them.
         let mut buf = buffer("{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n");
         for expected in 1..=3 {
-            let message = take_line(&mut buf).expect("a line").expect("valid JSON");
+            let message = take_line(&mut buf, "a/b")
+                .expect("a line")
+                .expect("valid JSON");
             let value: serde_json::Value = message.deserialize().unwrap();
             assert_eq!(value["a"], expected);
         }
-        assert!(take_line(&mut buf).is_none());
+        assert!(take_line(&mut buf, "a/b").is_none());
         assert!(buf.is_empty());
     }
 
     #[test]
     fn a_partial_trailing_line_is_kept() {
         let mut buf = buffer("{\"a\":1}\n{\"a\":2");
-        assert!(take_line(&mut buf).is_some());
-        assert!(take_line(&mut buf).is_none());
+        assert!(take_line(&mut buf, "a/b").is_some());
+        assert!(take_line(&mut buf, "a/b").is_none());
         assert_eq!(buf, buffer("{\"a\":2"));
     }
 
     #[test]
     fn bad_json_is_an_error_and_still_consumes_the_line() {
         let mut buf = buffer("not json\n{\"a\":1}\n");
-        let error = take_line(&mut buf).expect("a line").expect_err("bad JSON");
+        let error = take_line(&mut buf, "a/b")
+            .expect("a line")
+            .expect_err("bad JSON");
         assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
         // The bad line must not block the good one behind it.
-        let message = take_line(&mut buf).expect("a line").expect("valid JSON");
+        let message = take_line(&mut buf, "a/b")
+            .expect("a line")
+            .expect("valid JSON");
         let value: serde_json::Value = message.deserialize().unwrap();
         assert_eq!(value["a"], 1);
     }
@@ -125,7 +151,9 @@ mod tests {
     #[test]
     fn carriage_returns_do_not_break_parsing() {
         let mut buf = buffer("{\"a\":1}\r\n");
-        let message = take_line(&mut buf).expect("a line").expect("valid JSON");
+        let message = take_line(&mut buf, "a/b")
+            .expect("a line")
+            .expect("valid JSON");
         let value: serde_json::Value = message.deserialize().unwrap();
         assert_eq!(value["a"], 1);
     }
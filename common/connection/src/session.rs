@@ -1,18 +1,89 @@
 use std::fmt::{Debug, Display, Formatter};
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
 use std::time::Duration;
 
-use ares_device_lib::{Device, FileTransfer, PrivateKey};
+use ares_device_lib::cli::{Classify, ErrorKind, eprint_line};
+use ares_device_lib::{
+    AuthMethod, ConnectSettings, Device, DeviceManager, FileTransfer, PrivateKey, prompt,
+};
 use libssh_rs::{AuthStatus, Error as SshError, Session, SshKey, SshOption};
 
+use crate::capabilities::Capabilities;
+use crate::jump;
+
 pub trait NewSession {
     fn new_session(&self) -> Result<DeviceSession, SessionError>;
 }
 
+/// What `-o` set for this run. It wins over the device list.
+static OVERRIDES: Mutex<Option<ConnectSettings>> = Mutex::new(None);
+
+/// Take `-o KEY=VALUE` options, such as `ConnectTimeout=30`, for every device
+/// this run connects to. Each tool calls this with its `--ssh-option` values.
+///
+/// # Errors
+///
+/// Returns [`IoErrorKind::InvalidInput`] for an option that isn't one of the
+/// fields of [`ConnectSettings`].
+pub fn set_ssh_options<S: AsRef<str>>(options: &[S]) -> Result<(), IoError> {
+    let settings = ConnectSettings::parse(options)?;
+    if let Ok(mut overrides) = OVERRIDES.lock() {
+        *overrides = Some(settings);
+    }
+    Ok(())
+}
+
+/// How to connect to one device: `-o` first, then the device list, then the
+/// defaults below.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConnectOptions {
+    /// 10 seconds unless set.
+    pub connect_timeout: Duration,
+    /// 30 seconds unless set. `None` when set to 0.
+    pub server_alive_interval: Option<Duration>,
+    /// 3 unless set.
+    pub server_alive_count_max: u32,
+    /// 1 unless set, so a script finds out at once that a device is away.
+    pub connection_attempts: u32,
+}
+
+impl ConnectOptions {
+    #[must_use]
+    pub fn for_device(device: &Device) -> Self {
+        let overrides = OVERRIDES
+            .lock()
+            .ok()
+            .and_then(|overrides| *overrides)
+            .unwrap_or_default();
+        Self::from_settings(&overrides.or(&device.connection))
+    }
+
+    fn from_settings(settings: &ConnectSettings) -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(settings.connect_timeout.unwrap_or(10)),
+            server_alive_interval: match settings.server_alive_interval.unwrap_or(30) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            server_alive_count_max: settings.server_alive_count_max.unwrap_or(3).max(1),
+            connection_attempts: settings.connection_attempts.unwrap_or(1).max(1),
+        }
+    }
+}
+
+/// The longest wait between two tries to connect.
+pub(crate) const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
 /// Open a session to `device` and connect it, with the options a webOS device
 /// needs already set.
 ///
+/// A device that is away, refuses or doesn't answer in time is tried up to
+/// `connectionAttempts` times, 1 second apart at first and twice as long after
+/// each failure. Each retry is announced on stderr.
+///
 /// The session is not authenticated yet. Call [`authenticate`] next, or use
 /// [`NewSession::new_session`] when the key can be read the ordinary way.
 ///
@@ -21,58 +92,300 @@ pub trait NewSession {
 /// Returns the libssh error if an option is rejected or the device cannot be
 /// reached.
 pub fn connect(device: &Device) -> Result<Session, SessionError> {
+    let options = ConnectOptions::for_device(device);
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 1;
+    loop {
+        match connect_once(device, &options) {
+            Err(e) if e.is_transient() && attempt < options.connection_attempts => {
+                eprint_line(format!(
+                    "{e}. Trying again in {}s ({attempt} of {} attempts failed)",
+                    delay.as_secs(),
+                    options.connection_attempts
+                ));
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn connect_once(device: &Device, options: &ConnectOptions) -> Result<Session, SessionError> {
     let session = Session::new()?;
     configure_session(&session)?;
+    session.set_option(SshOption::Timeout(options.connect_timeout))?;
+    if let Some(gateway) = jump::gateway(&DeviceManager::default(), device)? {
+        session.set_option(SshOption::Socket(jump::tunnel(&gateway, device)?))?;
+    }
     session.set_option(SshOption::Hostname(device.host.clone()))?;
     session.set_option(SshOption::Port(device.port))?;
     session.set_option(SshOption::User(Some(device.username.clone())))?;
-    session.connect()?;
+    if uses_ssh_config(device) {
+        // What a matching Host block sets wins over the device list, as the
+        // command line loses to it for ssh.
+        session.options_parse_config(None)?;
+    }
+    session
+        .connect()
+        .map_err(|e| SessionError::from_connect(e, device))?;
     Ok(session)
 }
 
+/// Turns reading `~/.ssh/config` on for every device.
+pub const SSH_CONFIG_ENV: &str = "ARES_SSH_CONFIG";
+
+/// `true` when `~/.ssh/config` applies to `device`: its `sshConfig` says so,
+/// or else [`SSH_CONFIG_ENV`] is `1`, `true` or `yes`.
+#[must_use]
+pub fn uses_ssh_config(device: &Device) -> bool {
+    device.ssh_config.unwrap_or_else(|| {
+        std::env::var(SSH_CONFIG_ENV)
+            .is_ok_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+    })
+}
+
 /// Authenticate a connected session as `device`.
 ///
 /// `key` is the private key itself, in OpenSSH format. The caller reads it,
 /// because where a [`crate::session::NewSession`] implementer looks for a key
-/// name is its own business. Pass `None` to fall back to the password, and to
-/// no authentication after that.
+/// name is its own business. Pass `None` when the device has no key file.
+///
+/// The methods in [`auth_order`] are tried in turn until one works. A key
+/// with no passphrase, or a password the device list lacks, is asked for on
+/// the terminal. With no terminal, or with `--batch`, the method fails at once
+/// with a message that names the missing field.
+///
+/// A passphrase or a password kept in a secret store is read back here, with
+/// [`Device::unlocked`]. A caller whose device list lives elsewhere can unlock
+/// the device itself first.
 ///
 /// # Errors
 ///
-/// Returns [`SessionError::Authorization`] if the device turns the attempt
-/// down, or the libssh error if the key does not parse.
+/// Returns [`SessionError::Authorization`] with the last refusal if the device
+/// turns every method down, or when a missing secret can't be asked for. Returns
+/// the libssh error if the key does not parse, or an I/O error if the secret
+/// store can't be read.
 pub fn authenticate(
     session: &Session,
     device: &Device,
     key: Option<&str>,
 ) -> Result<(), SessionError> {
-    let (status, refused) = match (key, &device.password) {
-        (Some(key), _) => {
-            let key = SshKey::from_privkey_base64(key, device.valid_passphrase().as_deref())?;
-            (
-                session.userauth_publickey(None, &key)?,
-                "Key authorization failed",
-            )
+    login(session, device, key).map(|_| ())
+}
+
+/// What the user typed in while logging in.
+#[derive(Default)]
+struct Typed {
+    passphrase: Option<String>,
+    password: Option<String>,
+}
+
+/// [`authenticate`], and what the user typed in on the way.
+fn login(session: &Session, device: &Device, key: Option<&str>) -> Result<Typed, SessionError> {
+    let device = &device.unlocked()?;
+    let mut typed = Typed::default();
+    let mut refused = "No way to log in was configured";
+    for method in auth_order(device, key.is_some()) {
+        let status = match (method, key) {
+            (AuthMethod::PublicKey, Some(key)) => {
+                refused = "Key authorization failed";
+                let key = parse_key(device, key, &mut typed)?;
+                session.userauth_publickey(None, &key)?
+            }
+            (AuthMethod::PublicKey, None) if uses_ssh_config(device) => {
+                // The IdentityFile lines of ~/.ssh/config, then the usual
+                // ~/.ssh/id_* keys.
+                refused = "No key from the SSH config was accepted";
+                session.userauth_public_key_auto(None, device.valid_passphrase().as_deref())?
+            }
+            (AuthMethod::PublicKey, None) => continue,
+            (AuthMethod::Agent, _) => {
+                refused = "No key in the SSH agent was accepted";
+                // No agent running is just one more refusal.
+                session.userauth_agent(None).unwrap_or(AuthStatus::Denied)
+            }
+            (AuthMethod::Password, _) => {
+                refused = "Bad SSH password";
+                let password = password(device, &mut typed)?;
+                session.userauth_password(None, Some(&password))?
+            }
+            (AuthMethod::KeyboardInteractive, _) => {
+                refused = "The device turned the answers down";
+                keyboard_interactive(session, device, &mut typed)?
+            }
+            (AuthMethod::None, _) => {
+                refused = "Host needs authorization";
+                session.userauth_none(None)?
+            }
+        };
+        if status == AuthStatus::Success {
+            return Ok(typed);
         }
-        (None, Some(password)) => (
-            session.userauth_password(None, Some(password))?,
-            "Bad SSH password",
-        ),
-        (None, None) => (session.userauth_none(None)?, "Host needs authorization"),
-    };
-    if status == AuthStatus::Success {
-        return Ok(());
     }
     Err(SessionError::Authorization {
         message: refused.to_string(),
     })
 }
 
+/// Read `key`, with the device's passphrase, or one the user types in when the
+/// device has none.
+fn parse_key(device: &Device, key: &str, typed: &mut Typed) -> Result<SshKey, SessionError> {
+    let passphrase = device.valid_passphrase();
+    let wrong = |_| SessionError::WrongPassphrase {
+        device: device.name.clone(),
+    };
+    match SshKey::from_privkey_base64(key, passphrase.as_deref()) {
+        Ok(key) => Ok(key),
+        Err(e) if passphrase.is_some() => Err(wrong(e)),
+        Err(_) => {
+            let passphrase = ask(prompt::secret(
+                &format!("Passphrase for the key of {}: ", device.name),
+                &format!(
+                    "The key of {} needs a passphrase, and the device has no `passphrase`",
+                    device.name
+                ),
+            ))?;
+            let key = SshKey::from_privkey_base64(key, Some(&passphrase)).map_err(wrong)?;
+            typed.passphrase = Some(passphrase);
+            Ok(key)
+        }
+    }
+}
+
+/// The device's password, or one the user types in when it has none.
+fn password(device: &Device, typed: &mut Typed) -> Result<String, SessionError> {
+    if let Some(password) = device.password.as_ref().or(typed.password.as_ref()) {
+        return Ok(password.clone());
+    }
+    let password = ask(prompt::secret(
+        &format!("Password for {}@{}: ", device.username, device.host),
+        &format!(
+            "{} needs a password, and the device has no `password`",
+            device.name
+        ),
+    ))?;
+    typed.password = Some(password.clone());
+    Ok(password)
+}
+
+/// Answer what the server asks. The first hidden question gets the password,
+/// the way OpenSSH does it, and the user answers the rest.
+fn keyboard_interactive(
+    session: &Session,
+    device: &Device,
+    typed: &mut Typed,
+) -> Result<AuthStatus, SessionError> {
+    let mut password_used = false;
+    let mut status = session.userauth_keyboard_interactive(None, None)?;
+    while status == AuthStatus::Info {
+        let info = session.userauth_keyboard_interactive_info()?;
+        if !info.instruction.is_empty() {
+            prompt::note(&info.instruction);
+        }
+        let missing = format!(
+            "{} asks questions, and there is no terminal to answer them",
+            device.name
+        );
+        let mut answers = Vec::new();
+        for question in &info.prompts {
+            let answer = if question.echo {
+                ask(prompt::line(&question.prompt, &missing))?
+            } else if password_used {
+                ask(prompt::secret(&question.prompt, &missing))?
+            } else {
+                password_used = true;
+                password(device, typed)?
+            };
+            answers.push(answer);
+        }
+        session.userauth_keyboard_interactive_set_answers(&answers)?;
+        status = session.userauth_keyboard_interactive(None, None)?;
+    }
+    Ok(status)
+}
+
+/// A prompt the tool may not show is a failure to log in.
+fn ask(answer: Result<String, IoError>) -> Result<String, SessionError> {
+    answer.map_err(|e| match e.kind() {
+        IoErrorKind::PermissionDenied => SessionError::Authorization {
+            message: e.to_string(),
+        },
+        _ => SessionError::Io(e),
+    })
+}
+
+/// Offer to keep what the user typed in, when `device` is in the device list.
+/// It goes where the device keeps its secrets: a store, or the list itself.
+fn offer_to_save(device: &Device, typed: Typed) {
+    let what = match (&typed.passphrase, &typed.password) {
+        (Some(_), Some(_)) => "passphrase and password",
+        (Some(_), None) => "passphrase",
+        (None, Some(_)) => "password",
+        (None, None) => return,
+    };
+    let manager = DeviceManager::default();
+    let Some(mut saved) = manager
+        .list()
+        .ok()
+        .and_then(|devices| devices.into_iter().find(|d| d.name == device.name))
+    else {
+        return;
+    };
+    if !prompt::confirm(&format!("Save the {what} for {}?", device.name)) {
+        return;
+    }
+    saved.passphrase = typed.passphrase.or(saved.passphrase);
+    saved.password = typed.password.or(saved.password);
+    let stored = match saved.secret_ref.as_ref().map(|r| r.store) {
+        Some(store) => manager.store_secrets(&mut saved, store),
+        None => Ok(()),
+    };
+    if let Err(e) = stored.and_then(|()| manager.modify(&device.name, &saved)) {
+        eprintln!("Failed to save the {what} of {}: {e}", device.name);
+    }
+}
+
+/// The methods [`authenticate`] tries for `device`, in order.
+///
+/// The device's `authMethods` wins. Without it, a device with a key file uses
+/// the key, and one with `{"agent": true}` the SSH agent. One with a password
+/// uses it, first as a password, then to answer the server's questions. A
+/// device with none of these tries the agent when `SSH_AUTH_SOCK` is set, then
+/// no authentication, then a password the user types in. When
+/// [`uses_ssh_config`], that device tries the keys `~/.ssh/config` names first.
+#[must_use]
+pub fn auth_order(device: &Device, has_key: bool) -> Vec<AuthMethod> {
+    if let Some(methods) = &device.auth_methods {
+        return methods.clone();
+    }
+    if has_key {
+        vec![AuthMethod::PublicKey]
+    } else if matches!(device.private_key, Some(PrivateKey::Agent { agent: true })) {
+        vec![AuthMethod::Agent]
+    } else if device.password.is_some() {
+        vec![AuthMethod::Password, AuthMethod::KeyboardInteractive]
+    } else if uses_ssh_config(device) {
+        vec![
+            AuthMethod::PublicKey,
+            AuthMethod::None,
+            AuthMethod::Password,
+        ]
+    } else if std::env::var_os("SSH_AUTH_SOCK").is_some() {
+        vec![AuthMethod::Agent, AuthMethod::None, AuthMethod::Password]
+    } else {
+        vec![AuthMethod::None, AuthMethod::Password]
+    }
+}
+
 /// Set the timeout, the crypto algorithms and the host-key policy that a webOS
 /// device needs.
 ///
 /// A device runs an old SSH server, so the lists below keep algorithms that
 /// current defaults drop. Known hosts are off: a device has no stable host key.
+/// The timeout is 10 seconds; [`connect`] sets the one from [`ConnectOptions`]
+/// after this.
 ///
 /// Call this on a new [`Session`], before you set the host, port and user. Use
 /// it to build a session yourself, when [`NewSession::new_session`] does not fit
@@ -147,6 +460,12 @@ pub trait SshConnection {
     /// `true` when this user may chmod any path. A non-root user can't change the
     /// mode of a directory somebody else owns, so `mkdir` leaves the mode alone.
     fn is_root(&self) -> bool;
+
+    /// What the device can do, when the connection knows. File transfer uses
+    /// it to skip a path that can't work, instead of trying it first.
+    fn known_capabilities(&self) -> Option<&Capabilities> {
+        None
+    }
 }
 
 impl SshConnection for DeviceSession {
@@ -161,18 +480,88 @@ impl SshConnection for DeviceSession {
     fn is_root(&self) -> bool {
         self.device.username == "root"
     }
+
+    fn known_capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.get()
+    }
 }
 
 pub struct DeviceSession {
     pub device: Device,
     pub session: Session,
+    /// Filled in by [`DeviceSession::capabilities`].
+    pub(crate) capabilities: OnceLock<Capabilities>,
 }
 
+/// Why a session couldn't be opened. Each variant past the first three has a
+/// hint for the user and an exit code of its own, which don't change between
+/// releases.
 #[derive(Debug)]
 pub enum SessionError {
     Io(IoError),
     LibSsh(SshError),
-    Authorization { message: String },
+    /// The device turned every way to log in down.
+    Authorization {
+        message: String,
+    },
+    /// The host name doesn't resolve.
+    HostNotFound {
+        host: String,
+    },
+    /// Nothing listens on the port.
+    Refused {
+        host: String,
+        port: u16,
+    },
+    /// The device didn't answer in time.
+    Timeout {
+        host: String,
+        port: u16,
+    },
+    /// The key file the device list names isn't there.
+    KeyFileMissing {
+        device: String,
+        path: String,
+    },
+    /// The key doesn't open with the passphrase.
+    WrongPassphrase {
+        device: String,
+    },
+}
+
+impl SessionError {
+    /// `true` when trying again later might work: the device is away, busy or
+    /// dropped the connection, rather than turning the login down.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            SessionError::LibSsh(_)
+                | SessionError::HostNotFound { .. }
+                | SessionError::Refused { .. }
+                | SessionError::Timeout { .. }
+        )
+    }
+
+    /// Read what went wrong out of a failed [`Session::connect`]. libssh only
+    /// says it in words, so the words are matched.
+    pub(crate) fn from_connect(error: SshError, device: &Device) -> Self {
+        let text = error.to_string().to_lowercase();
+        let host = device.host.clone();
+        let port = device.port;
+        if text.contains("refused") {
+            SessionError::Refused { host, port }
+        } else if text.contains("timeout") || text.contains("timed out") {
+            SessionError::Timeout { host, port }
+        } else if text.contains("resolve hostname")
+            || text.contains("name or service not known")
+            || text.contains("nodename nor servname")
+        {
+            SessionError::HostNotFound { host }
+        } else {
+            SessionError::LibSsh(error)
+        }
+    }
 }
 
 impl Display for SessionError {
@@ -181,24 +570,104 @@ impl Display for SessionError {
             SessionError::Io(e) => write!(f, "{e}"),
             SessionError::LibSsh(e) => write!(f, "{e}"),
             SessionError::Authorization { message } => write!(f, "not authorized: {message}"),
+            SessionError::HostNotFound { host } => write!(f, "can't resolve host {host}"),
+            SessionError::Refused { host, port } => {
+                write!(f, "{host}:{port} refused the connection")
+            }
+            SessionError::Timeout { host, port } => {
+                write!(f, "{host}:{port} didn't answer in time")
+            }
+            SessionError::KeyFileMissing { device, path } => {
+                write!(f, "the key file of {device} is missing: {path}")
+            }
+            SessionError::WrongPassphrase { device } => {
+                write!(f, "the passphrase of the key of {device} is wrong")
+            }
         }
     }
 }
 
 impl std::error::Error for SessionError {}
 
+impl Classify for SessionError {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            SessionError::Io(e) => e.error_kind(),
+            SessionError::LibSsh(_)
+            | SessionError::HostNotFound { .. }
+            | SessionError::Refused { .. } => ErrorKind::Connection,
+            SessionError::Timeout { .. } => ErrorKind::Timeout,
+            SessionError::KeyFileMissing { .. } => ErrorKind::NotFound,
+            SessionError::Authorization { .. } | SessionError::WrongPassphrase { .. } => {
+                ErrorKind::Auth
+            }
+        }
+    }
+
+    /// Codes from BSD's `sysexits.h`, which other tools use for the same
+    /// failures.
+    fn exit_code(&self) -> i32 {
+        match self {
+            SessionError::Io(_) | SessionError::LibSsh(_) => 1,
+            SessionError::WrongPassphrase { .. } => 65,
+            SessionError::KeyFileMissing { .. } => 66,
+            SessionError::HostNotFound { .. } => 68,
+            SessionError::Refused { .. } => 69,
+            SessionError::Timeout { .. } => 75,
+            SessionError::Authorization { .. } => 77,
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        Some(match self {
+            SessionError::Io(_) | SessionError::LibSsh(_) => return None,
+            SessionError::Authorization { .. } => String::from(
+                "Check the username, key and password of the device with `ares-setup-device --listfull`",
+            ),
+            SessionError::HostNotFound { .. } => {
+                String::from("Check the host of the device with `ares-setup-device --listfull`")
+            }
+            SessionError::Refused { .. } => String::from(
+                "Is SSH running on the device? In Developer Mode, check that the app still runs \
+                 and the port is 9922. A rooted device usually listens on port 22",
+            ),
+            SessionError::Timeout { .. } => {
+                String::from("Is the TV on and awake, and on the same network as this computer?")
+            }
+            SessionError::KeyFileMissing { device, .. } => {
+                format!("Fetch the key again with `ares-novacom -d {device} --getkey`")
+            }
+            SessionError::WrongPassphrase { device } => format!(
+                "Set the passphrase the Developer Mode app shows with \
+                 `ares-setup-device --modify {device} --info passphrase=...`"
+            ),
+        })
+    }
+}
+
 impl NewSession for Device {
     fn new_session(&self) -> Result<DeviceSession, SessionError> {
         let session = connect(self)?;
-        let key = self
-            .private_key
-            .as_ref()
-            .map(PrivateKey::content)
-            .transpose()?;
-        authenticate(&session, self, key.as_deref())?;
+        let key = match &self.private_key {
+            Some(PrivateKey::Agent { .. }) | None => None,
+            Some(key) => Some(key.content().map_err(|e| {
+                match e.kind() {
+                    IoErrorKind::NotFound => SessionError::KeyFileMissing {
+                        device: self.name.clone(),
+                        path: key
+                            .file()
+                            .map_or_else(String::new, |path| path.display().to_string()),
+                    },
+                    _ => SessionError::Io(e),
+                }
+            })?),
+        };
+        let typed = login(&session, self, key.as_deref())?;
+        offer_to_save(self, typed);
         Ok(DeviceSession {
             device: self.clone(),
             session,
+            capabilities: OnceLock::new(),
         })
     }
 }
@@ -222,3 +691,98 @@ impl From<IoError> for SessionError {
         SessionError::Io(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use ares_device_lib::cli::Classify;
+    use ares_device_lib::{AuthMethod, Device};
+    use libssh_rs::Error as SshError;
+
+    use super::{ConnectOptions, SessionError, auth_order, connect, set_ssh_options};
+
+    fn device(auth: &str) -> Device {
+        let json = format!(
+            r#"{{"profile":"ose","name":"tv","host":"1.2.3.4","port":22,"username":"root",{auth}}}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn connect_options_fill_in_defaults() {
+        let options = ConnectOptions::for_device(&device(r#""serverAliveInterval":0"#));
+        assert_eq!(options.connect_timeout, Duration::from_secs(10));
+        assert_eq!(options.server_alive_interval, None);
+        assert_eq!(options.connection_attempts, 1);
+
+        let patient = device(r#""connectTimeout":30,"connectionAttempts":4"#);
+        let options = ConnectOptions::for_device(&patient);
+        assert_eq!(options.connect_timeout, Duration::from_secs(30));
+        assert_eq!(options.server_alive_interval, Some(Duration::from_secs(30)));
+        assert_eq!(options.connection_attempts, 4);
+
+        assert!(set_ssh_options(&["Port=22"]).is_err());
+    }
+
+    #[test]
+    fn auth_order_follows_the_device() {
+        let agent = device(r#""privateKey":{"agent":true}"#);
+        assert_eq!(auth_order(&agent, false), vec![AuthMethod::Agent]);
+
+        let listed = device(r#""password":"pw","authMethods":["agent","password"]"#);
+        assert_eq!(
+            auth_order(&listed, true),
+            vec![AuthMethod::Agent, AuthMethod::Password]
+        );
+
+        let password = device(r#""password":"pw""#);
+        assert_eq!(auth_order(&password, true), vec![AuthMethod::PublicKey]);
+        assert_eq!(
+            auth_order(&password, false),
+            vec![AuthMethod::Password, AuthMethod::KeyboardInteractive]
+        );
+
+        let configured = device(r#""sshConfig":true"#);
+        assert_eq!(auth_order(&configured, false)[0], AuthMethod::PublicKey);
+    }
+
+    #[test]
+    fn a_closed_port_reads_as_refused() {
+        // Bind a port, then free it, so nothing listens there.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut tv = device(r#""password":"pw""#);
+        tv.host = String::from("127.0.0.1");
+        tv.port = port;
+        let Err(error) = connect(&tv) else {
+            panic!("nothing listens there");
+        };
+        assert!(matches!(error, SessionError::Refused { port: p, .. } if p == port));
+        assert_eq!(error.exit_code(), 69);
+        assert!(error.hint().is_some());
+    }
+
+    #[test]
+    fn libssh_messages_are_sorted_out() {
+        let tv = device(r#""password":"pw""#);
+        let read =
+            |message: &str| SessionError::from_connect(SshError::Fatal(String::from(message)), &tv);
+        assert!(matches!(
+            read("Timeout connecting to 1.2.3.4"),
+            SessionError::Timeout { .. }
+        ));
+        assert!(matches!(
+            read("Failed to resolve hostname tv.lan (Name or service not known)"),
+            SessionError::HostNotFound { .. }
+        ));
+        assert!(matches!(
+            read("Socket error: disconnected"),
+            SessionError::LibSsh(_)
+        ));
+    }
+}
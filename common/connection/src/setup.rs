@@ -1,16 +1,23 @@
+use std::ffi::{CStr, c_char};
 use std::io::{Error, ErrorKind, Read, Write};
 use std::net::{TcpStream, ToSocketAddrs};
+use std::ptr;
 use std::time::Duration;
 
 use ares_device_lib::DeviceManager;
 use httparse::{Response, Status};
-use libssh_rs::SshKey;
+use libssh_rs::{SshKey, sys};
 
 use crate::DeviceSetupManager;
+use crate::transfer::{PathKind, Transfer, TransferError};
 
 /// The port the key server on a device in developer mode listens on.
 pub const NOVACOM_KEY_PORT: u16 = 9991;
 
+/// Where the SSH server of a device looks for keys. The path is relative, and
+/// both SFTP and a shell read it from the home directory of the login.
+pub const AUTHORIZED_KEYS: &str = ".ssh/authorized_keys";
+
 /// The largest key the server may return. The key is a few kilobytes, so this
 /// only stops a wrong server from filling memory.
 const MAX_KEY_RESPONSE: u64 = 65536;
@@ -60,6 +67,149 @@ pub fn fetch_key(host: &str, port: u16) -> Result<String, Error> {
     Ok(String::from_utf8_lossy(&buffer[size_to_skip..]).to_string())
 }
 
+/// A key pair made by [`generate_key`].
+pub struct KeyPair {
+    /// The private key in OpenSSH format, with no passphrase.
+    pub private_key: String,
+    /// The `authorized_keys` line: key type, key and comment.
+    pub public_key: String,
+}
+
+/// Make a new ed25519 key pair, with `comment` at the end of its public key.
+///
+/// libssh makes the key, so the tools need no crypto library of their own for
+/// it.
+///
+/// # Errors
+///
+/// Returns an error if libssh can't make or export the key.
+pub fn generate_key(comment: &str) -> Result<KeyPair, Error> {
+    let mut key: sys::ssh_key = ptr::null_mut();
+    // SAFETY: `key` is a valid place for libssh to put the new key.
+    let status =
+        unsafe { sys::ssh_pki_generate(sys::ssh_keytypes_e_SSH_KEYTYPE_ED25519, 0, &raw mut key) };
+    if status != 0 || key.is_null() {
+        return Err(Error::other("libssh can't generate an ed25519 key"));
+    }
+    let mut private_key: *mut c_char = ptr::null_mut();
+    let mut public_key: *mut c_char = ptr::null_mut();
+    // SAFETY: `key` is the live key made above, and is freed once, last. No
+    // passphrase and no callback are passed, which libssh allows.
+    let (private_status, public_status) = unsafe {
+        let exported = (
+            sys::ssh_pki_export_privkey_base64(
+                key,
+                ptr::null(),
+                None,
+                ptr::null_mut(),
+                &raw mut private_key,
+            ),
+            sys::ssh_pki_export_pubkey_base64(key, &raw mut public_key),
+        );
+        sys::ssh_key_free(key);
+        exported
+    };
+    match (
+        private_status,
+        public_status,
+        take_string(private_key),
+        take_string(public_key),
+    ) {
+        (0, 0, Some(private_key), Some(public_key)) => Ok(KeyPair {
+            private_key,
+            public_key: format!("ssh-ed25519 {public_key} {comment}"),
+        }),
+        _ => Err(Error::other("libssh can't export the new key")),
+    }
+}
+
+/// Copy a string libssh made, and free it.
+fn take_string(text: *mut c_char) -> Option<String> {
+    if text.is_null() {
+        return None;
+    }
+    // SAFETY: `text` is a NUL-terminated string from libssh, freed once, here.
+    unsafe {
+        let copy = CStr::from_ptr(text).to_string_lossy().into_owned();
+        sys::ssh_string_free_char(text);
+        Some(copy)
+    }
+}
+
+/// Add `public_key` to [`AUTHORIZED_KEYS`] on the device, unless the file
+/// holds that key already. Returns `true` when the key was added.
+///
+/// # Errors
+///
+/// Returns an error if `~/.ssh` can't be made, or the file can't be read or
+/// written.
+pub fn authorize_key(transfer: &Transfer, public_key: &str) -> Result<bool, TransferError> {
+    transfer.mkdir(".ssh", 0o700)?;
+    let mut keys = Vec::new();
+    if transfer.stat(AUTHORIZED_KEYS)? == PathKind::File {
+        transfer.get(AUTHORIZED_KEYS, &mut keys, |_| {})?;
+    }
+    let Some(keys) = with_key(&String::from_utf8_lossy(&keys), public_key) else {
+        return Ok(false);
+    };
+    transfer.put(&mut keys.as_bytes(), AUTHORIZED_KEYS, |_| {})?;
+    Ok(true)
+}
+
+/// Take `public_key` out of [`AUTHORIZED_KEYS`] on the device, to undo
+/// [`authorize_key`]. Returns `true` when a line held the key.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or written.
+pub fn revoke_key(transfer: &Transfer, public_key: &str) -> Result<bool, TransferError> {
+    if transfer.stat(AUTHORIZED_KEYS)? != PathKind::File {
+        return Ok(false);
+    }
+    let mut keys = Vec::new();
+    transfer.get(AUTHORIZED_KEYS, &mut keys, |_| {})?;
+    let Some(keys) = without_key(&String::from_utf8_lossy(&keys), public_key) else {
+        return Ok(false);
+    };
+    transfer.put(&mut keys.as_bytes(), AUTHORIZED_KEYS, |_| {})?;
+    Ok(true)
+}
+
+/// The type and the key of an `authorized_keys` line, without the comment.
+fn key_of(line: &str) -> Vec<&str> {
+    line.split_whitespace().take(2).collect()
+}
+
+/// `keys` with `public_key` added as a line of its own, or `None` when a line
+/// holds the same key already. Comments don't count.
+fn with_key(keys: &str, public_key: &str) -> Option<String> {
+    let wanted = key_of(public_key);
+    if keys.lines().any(|line| key_of(line) == wanted) {
+        return None;
+    }
+    let mut keys = keys.to_string();
+    if !keys.is_empty() && !keys.ends_with('\n') {
+        keys.push('\n');
+    }
+    keys.push_str(public_key.trim_end());
+    keys.push('\n');
+    Some(keys)
+}
+
+/// `keys` without the lines that hold `public_key`, or `None` when none does.
+fn without_key(keys: &str, public_key: &str) -> Option<String> {
+    let wanted = key_of(public_key);
+    if !keys.lines().any(|line| key_of(line) == wanted) {
+        return None;
+    }
+    let mut kept = String::new();
+    for line in keys.lines().filter(|line| key_of(line) != wanted) {
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    Some(kept)
+}
+
 impl DeviceSetupManager for DeviceManager {
     fn novacom_getkey(&self, address: &str, passphrase: &str) -> Result<String, Error> {
         let content = fetch_key(address, NOVACOM_KEY_PORT)
@@ -113,6 +263,36 @@ mod tests {
         assert_eq!(result.unwrap(), expected_key);
     }
 
+    #[test]
+    fn a_generated_key_parses() {
+        let pair = generate_key("ares@tv").unwrap();
+        assert!(SshKey::from_privkey_base64(&pair.private_key, None).is_ok());
+        assert!(pair.public_key.starts_with("ssh-ed25519 AAAA"));
+        assert!(pair.public_key.ends_with(" ares@tv"));
+    }
+
+    #[test]
+    fn a_key_is_added_once() {
+        let key = "ssh-ed25519 AAAAkey ares@tv";
+        assert_eq!(with_key("", key).unwrap(), format!("{key}\n"));
+        assert_eq!(
+            with_key("ssh-rsa AAAAold", key).unwrap(),
+            format!("ssh-rsa AAAAold\n{key}\n")
+        );
+        assert!(with_key("ssh-ed25519 AAAAkey other comment\n", key).is_none());
+    }
+
+    #[test]
+    fn a_key_is_taken_out_again() {
+        let key = "ssh-ed25519 AAAAkey ares@tv";
+        assert_eq!(
+            without_key(&format!("ssh-rsa AAAAold\n{key}\n# note\n"), key).unwrap(),
+            "ssh-rsa AAAAold\n# note\n"
+        );
+        assert_eq!(without_key(&format!("{key}\n"), key).unwrap(), "");
+        assert!(without_key("ssh-rsa AAAAold\n", key).is_none());
+    }
+
     #[test]
     fn fetch_key_refused() {
         let result = fetch_key("127.0.0.1", 9991);
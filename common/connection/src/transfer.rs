@@ -2,6 +2,7 @@ use std::fmt::{Display, Formatter};
 use std::io::{Error as IoError, ErrorKind, Read, Write};
 use std::path::Path;
 
+use ares_device_lib::cli::{Classify, ErrorKind as FailureKind};
 use libssh_rs::{Error as SshError, FileType, OpenFlags, Session, Sftp};
 use path_slash::PathExt;
 
@@ -105,6 +106,8 @@ pub struct Transfer<'a> {
     session: &'a Session,
     sftp: Option<Sftp>,
     is_root: bool,
+    /// `false` only when the device is known to lack `sha256sum`.
+    has_sha256sum: bool,
 }
 
 #[derive(Debug)]
@@ -118,9 +121,13 @@ impl<'a> Transfer<'a> {
     /// Open a transfer over `connection`.
     ///
     /// Falls back to streaming when the device is set to stream, and also when
-    /// the SFTP session does not start.
+    /// the SFTP session does not start. When the connection knows the device's
+    /// [`Capabilities`](crate::capabilities::Capabilities), a device without SFTP
+    /// streams without trying it, and the login's real user id decides whether
+    /// it is root.
     pub fn open<T: SshConnection + ?Sized>(connection: &'a T) -> Self {
-        let sftp = if connection.supports_sftp() {
+        let capabilities = connection.known_capabilities();
+        let sftp = if connection.supports_sftp() && capabilities.is_none_or(|c| c.sftp) {
             connection.session().sftp().ok()
         } else {
             None
@@ -128,7 +135,10 @@ impl<'a> Transfer<'a> {
         Self {
             session: connection.session(),
             sftp,
-            is_root: connection.is_root(),
+            is_root: capabilities
+                .and_then(|c| c.uid)
+                .map_or_else(|| connection.is_root(), |uid| uid == 0),
+            has_sha256sum: capabilities.is_none_or(|c| c.sha256sum),
         }
     }
 
@@ -337,6 +347,9 @@ impl<'a> Transfer<'a> {
     ///
     /// Returns an error when the command cannot run at all.
     pub fn sha256sum<P: AsRef<Path>>(&self, path: P) -> Result<Option<String>, TransferError> {
+        if !self.has_sha256sum {
+            return Ok(None);
+        }
         let path = path.as_ref().to_slash_lossy();
         let (out, status) =
             self.exec(&format!("sha256sum {}", snailquote::escape(path.as_ref())))?;
@@ -347,21 +360,30 @@ impl<'a> Transfer<'a> {
         Ok(parse_sha256sum(&out))
     }
 
-    /// Run `command` on the device and read its stdout. Returns the output and
-    /// the exit status.
     fn exec(&self, command: &str) -> Result<(String, i32), TransferError> {
-        let ch = self.session.new_channel()?;
-        ch.open_session()?;
-        ch.request_exec(command)?;
-        ch.send_eof()?;
-        let mut out = String::new();
-        ch.stdout().read_to_string(&mut out)?;
-        let status = ch.get_exit_status().unwrap_or(0);
-        ch.close()?;
-        Ok((out, status))
+        exec(self.session, command)
     }
 }
 
+/// Run `command` on the device and read its stdout. Returns the output and
+/// the exit status.
+///
+/// # Errors
+///
+/// Returns [`TransferError`] if the command can't be started or its output
+/// can't be read. A command that fails still returns, with its status.
+pub fn exec(session: &Session, command: &str) -> Result<(String, i32), TransferError> {
+    let ch = session.new_channel()?;
+    ch.open_session()?;
+    ch.request_exec(command)?;
+    ch.send_eof()?;
+    let mut out = String::new();
+    ch.stdout().read_to_string(&mut out)?;
+    let status = ch.get_exit_status().unwrap_or(0);
+    ch.close()?;
+    Ok((out, status))
+}
+
 impl TransferError {
     /// `true` when the device turned the operation down for want of permission.
     #[must_use]
@@ -390,6 +412,17 @@ impl Display for TransferError {
 
 impl std::error::Error for TransferError {}
 
+impl Classify for TransferError {
+    fn error_kind(&self) -> FailureKind {
+        match self {
+            _ if self.is_permission_denied() => FailureKind::PermissionDenied,
+            TransferError::Ssh(e) if sftp_status(e) == Some(2) => FailureKind::NotFound,
+            TransferError::Io(e) if e.kind() == ErrorKind::NotFound => FailureKind::NotFound,
+            _ => FailureKind::Transfer,
+        }
+    }
+}
+
 impl<T: SshConnection> FileTransfer for T {
     fn maybe_sftp(&self) -> Result<Sftp, SshError> {
         if !self.supports_sftp() {
@@ -1,13 +1,511 @@
-use std::fmt::Display;
+//! What the tools share on the command line: `--output`, tables, and how a
+//! failure is reported.
+//!
+//! Each tool calls [`set_output_format`] with its `--output` value first.
+//! From then on [`unwrap_or_exit`] and [`CliError::exit`] print errors in that
+//! format, and [`json_output`] tells the tool how to print its results. With
+//! `--output json`, stdout holds exactly one JSON document: the result, or
+//! `{"error": {...}}`.
+//!
+//! [`for_each_device`] runs a tool's work on every device `-d` picks, with the
+//! device name before each line of text.
+
+use std::fmt::{Display, Formatter};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::process::exit;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use serde::Serialize;
+
+use crate::Device;
+
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static DEVICE_LABEL: Mutex<Option<String>> = Mutex::new(None);
+
+/// How a tool prints its results and errors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Text for people, with errors on stderr.
+    #[default]
+    Text,
+    /// One JSON document on stdout, for scripts.
+    Json,
+}
+
+/// What went wrong, as scripts see it in `--output json`. The names are
+/// stable.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// A device, app or file doesn't exist.
+    NotFound,
+    /// An argument or an input file is wrong.
+    InvalidInput,
+    /// The device can't be reached, or the connection broke.
+    Connection,
+    /// The device turned down the key or the password.
+    Auth,
+    /// The device doesn't allow this.
+    PermissionDenied,
+    /// A service on the device answered with an error.
+    Device,
+    /// A file couldn't be copied to or from the device.
+    Transfer,
+    /// The device took too long.
+    Timeout,
+    /// The user stopped the tool.
+    Cancelled,
+    /// Reading or writing a local file failed.
+    Io,
+    Other,
+}
+
+/// Errors that know their [`ErrorKind`], so [`unwrap_or_exit`] can report it.
+pub trait Classify {
+    fn error_kind(&self) -> ErrorKind;
+
+    /// The code the tool exits with.
+    fn exit_code(&self) -> i32 {
+        1
+    }
+
+    /// What the user can do about it, printed after the message.
+    fn hint(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A failure, as printed by a tool just before it exits.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub exit_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+/// One row of a device list. Secrets are left out.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceEntry<'a> {
+    pub name: &'a str,
+    pub default: bool,
+    pub profile: &'a str,
+    pub host: &'a str,
+    pub port: u16,
+    pub username: &'a str,
+    pub connection: &'static str,
+    pub groups: &'a [String],
+    pub tags: &'a [String],
+}
+
+#[derive(Serialize)]
+struct ErrorDocument<'a> {
+    error: &'a CliError,
+}
+
+/// What `--output json` prints for each device of a run over many.
+#[derive(Serialize)]
+struct DeviceOutcome<'a, T> {
+    device: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a CliError>,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown format {s}, use text or json")),
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl CliError {
+    /// An error that exits with code 1.
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            exit_code: 1,
+            hint: None,
+        }
+    }
+
+    /// `Failed to <action>: <error>`, with the kind, exit code and hint of
+    /// `error`.
+    pub fn failed_to<E: Display + Classify>(action: &str, error: &E) -> Self {
+        Self {
+            kind: error.error_kind(),
+            message: format!("Failed to {action}: {error}"),
+            exit_code: error.exit_code(),
+            hint: error.hint(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_exit_code(mut self, exit_code: i32) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    /// Print the error in the output format, and exit with its code.
+    pub fn exit(&self) -> ! {
+        match output_format() {
+            OutputFormat::Text => self.eprint(),
+            OutputFormat::Json => print_json(&ErrorDocument { error: self }),
+        }
+        exit(self.exit_code);
+    }
+
+    /// Print the message and the hint to stderr, as text.
+    fn eprint(&self) {
+        eprint_line(&self.message);
+        if let Some(hint) = &self.hint {
+            eprint_line(format_args!("Hint: {hint}"));
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Classify for CliError {
+    fn error_kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    fn hint(&self) -> Option<String> {
+        self.hint.clone()
+    }
+}
+
+impl Classify for IoError {
+    fn error_kind(&self) -> ErrorKind {
+        match self.kind() {
+            IoErrorKind::NotFound => ErrorKind::NotFound,
+            IoErrorKind::InvalidInput | IoErrorKind::InvalidData => ErrorKind::InvalidInput,
+            IoErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            IoErrorKind::TimedOut => ErrorKind::Timeout,
+            IoErrorKind::Interrupted => ErrorKind::Cancelled,
+            IoErrorKind::ConnectionRefused
+            | IoErrorKind::ConnectionReset
+            | IoErrorKind::ConnectionAborted
+            | IoErrorKind::NotConnected
+            | IoErrorKind::AddrNotAvailable
+            | IoErrorKind::HostUnreachable
+            | IoErrorKind::NetworkUnreachable => ErrorKind::Connection,
+            _ => ErrorKind::Io,
+        }
+    }
+}
+
+impl Classify for serde_json::Error {
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::InvalidInput
+    }
+}
+
+impl Classify for String {
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<'a> From<&'a Device> for DeviceEntry<'a> {
+    fn from(device: &'a Device) -> Self {
+        Self {
+            name: &device.name,
+            default: device.default == Some(true),
+            profile: &device.profile,
+            host: &device.host,
+            port: device.port,
+            username: &device.username,
+            connection: "ssh",
+            groups: device.groups.as_deref().unwrap_or_default(),
+            tags: device.tags.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// Set the output format for the rest of the run. Only the first call counts.
+pub fn set_output_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+#[must_use]
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default()
+}
+
+/// `true` with `--output json`.
+#[must_use]
+pub fn json_output() -> bool {
+    output_format() == OutputFormat::Json
+}
 
-/// Return the value, or print `Failed to <action>: <error>` and exit with code 1.
+/// Print `value` to stdout as pretty JSON.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{json}"),
+        Err(e) => {
+            eprintln!("Failed to write JSON: {e}");
+            exit(1);
+        }
+    }
+}
+
+/// Return the value, or report `Failed to <action>: <error>` and exit.
 ///
 /// Use this in place of `unwrap()` in binaries. A panic gives users a backtrace
 /// notice instead of a message they can act on.
-pub fn unwrap_or_exit<T, E: Display>(result: Result<T, E>, action: &str) -> T {
-    result.unwrap_or_else(|e| {
-        eprintln!("Failed to {action}: {e}");
-        exit(1);
-    })
+pub fn unwrap_or_exit<T, E: Display + Classify>(result: Result<T, E>, action: &str) -> T {
+    result.unwrap_or_else(|e| CliError::failed_to(action, &e).exit())
+}
+
+/// Run `task` on each of `devices` in turn, and return what it gave.
+///
+/// With one device, this is the task alone: `--output json` prints its result,
+/// and a failure ends the tool. With more, [`print_line`] puts the device name
+/// before each line, a failure is reported and the next device goes on, and
+/// `--output json` prints one array of `{"device", "result"}` or
+/// `{"device", "error"}`. After the last device, any failure ends the tool with
+/// its exit code, or 1 if the failures disagree.
+pub fn for_each_device<T, F>(devices: &[Device], mut task: F) -> Vec<T>
+where
+    T: Serialize,
+    F: FnMut(&Device) -> Result<T, CliError>,
+{
+    if let [device] = devices {
+        let value = task(device).unwrap_or_else(|e| e.exit());
+        if json_output() {
+            print_json(&value);
+        }
+        return vec![value];
+    }
+    let mut outcomes = Vec::with_capacity(devices.len());
+    for device in devices {
+        set_device_label(Some(&device.name));
+        let result = task(device);
+        if let Err(e) = &result
+            && !json_output()
+        {
+            e.eprint();
+        }
+        outcomes.push((device, result));
+    }
+    set_device_label(None);
+    if json_output() {
+        let document: Vec<DeviceOutcome<T>> = outcomes
+            .iter()
+            .map(|(device, result)| DeviceOutcome {
+                device: &device.name,
+                result: result.as_ref().ok(),
+                error: result.as_ref().err(),
+            })
+            .collect();
+        print_json(&document);
+    }
+    let codes: Vec<i32> = outcomes
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().err())
+        .map(|e| e.exit_code)
+        .collect();
+    if let Some(&first) = codes.first() {
+        exit(if codes.iter().all(|&code| code == first) {
+            first
+        } else {
+            1
+        });
+    }
+    outcomes
+        .into_iter()
+        .filter_map(|(_, result)| result.ok())
+        .collect()
+}
+
+/// The device [`for_each_device`] is on, when it runs on more than one.
+#[must_use]
+pub fn device_label() -> Option<String> {
+    DEVICE_LABEL.lock().ok().and_then(|label| label.clone())
+}
+
+fn set_device_label(name: Option<&str>) {
+    if let Ok(mut label) = DEVICE_LABEL.lock() {
+        *label = name.map(String::from);
+    }
+}
+
+/// Print a line to stdout, after the [`device_label`] if there is one.
+pub fn print_line<D: Display>(line: D) {
+    match device_label() {
+        Some(label) => println!("{label}: {line}"),
+        None => println!("{line}"),
+    }
+}
+
+/// Print a line to stderr, after the [`device_label`] if there is one.
+pub fn eprint_line<D: Display>(line: D) {
+    match device_label() {
+        Some(label) => eprintln!("{label}: {line}"),
+        None => eprintln!("{line}"),
+    }
+}
+
+/// Print a left-aligned, space-padded table with a dashed header underline,
+/// like the reference CLI does.
+pub fn print_table<const N: usize>(headers: &[&str; N], rows: &[[String; N]]) {
+    let mut widths = headers.map(str::len);
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+
+    let print_row = |cells: &[String; N]| {
+        let line: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{cell:<width$}", width = widths[i]))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+
+    let header_row: [String; N] = std::array::from_fn(|i| headers[i].to_string());
+    let separator_row: [String; N] = std::array::from_fn(|i| "-".repeat(widths[i]));
+    print_row(&header_row);
+    print_row(&separator_row);
+    for row in rows {
+        print_row(row);
+    }
+}
+
+/// Print the device list: name (with a `(default)` marker), deviceinfo,
+/// connection, profile and passphrase, or the [`DeviceEntry`] of each device.
+///
+/// The passphrase shows as `****` unless `show_secrets` is set, and as the
+/// store's name when it is kept in one.
+pub fn print_device_list(devices: &[Device], show_secrets: bool) {
+    if json_output() {
+        let entries: Vec<DeviceEntry> = devices.iter().map(DeviceEntry::from).collect();
+        print_json(&entries);
+        return;
+    }
+    let headers = ["name", "deviceinfo", "connection", "profile", "passphrase"];
+    let rows: Vec<[String; 5]> = devices
+        .iter()
+        .map(|device| device_row(device, show_secrets))
+        .collect();
+    print_table(&headers, &rows);
+}
+
+fn device_row(device: &Device, show_secrets: bool) -> [String; 5] {
+    let name = if device.default == Some(true) {
+        format!("{} (default)", device.name)
+    } else {
+        device.name.clone()
+    };
+    [
+        name,
+        format!("{}@{}:{}", device.username, device.host, device.port),
+        String::from("ssh"),
+        device.profile.clone(),
+        passphrase_cell(device, show_secrets),
+    ]
+}
+
+fn passphrase_cell(device: &Device, show_secrets: bool) -> String {
+    if let Some(secret_ref) = &device.secret_ref {
+        return format!("({})", secret_ref.store);
+    }
+    match device.valid_passphrase() {
+        Some(passphrase) if show_secrets => passphrase,
+        Some(_) => String::from("****"),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+    use super::{CliError, ErrorDocument, ErrorKind, OutputFormat};
+
+    #[test]
+    fn output_formats_parse_by_name() {
+        assert_eq!("json".parse::<OutputFormat>(), Ok(OutputFormat::Json));
+        assert_eq!("text".parse::<OutputFormat>(), Ok(OutputFormat::Text));
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn errors_keep_their_field_names() {
+        let e = IoError::new(IoErrorKind::NotFound, "Device tv not found");
+        let error = CliError::failed_to("find device", &e);
+        let json = serde_json::to_value(ErrorDocument { error: &error }).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"error": {
+                "kind": "not-found",
+                "message": "Failed to find device: Device tv not found",
+                "exitCode": 1
+            }})
+        );
+        let cancelled = CliError::new(ErrorKind::Cancelled, "Cancelled").with_exit_code(130);
+        assert_eq!(
+            serde_json::to_value(cancelled).unwrap()["kind"],
+            "cancelled"
+        );
+    }
+
+    #[test]
+    fn a_hint_comes_along() {
+        struct Asleep;
+        impl std::fmt::Display for Asleep {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "no answer")
+            }
+        }
+        impl super::Classify for Asleep {
+            fn error_kind(&self) -> ErrorKind {
+                ErrorKind::Timeout
+            }
+            fn exit_code(&self) -> i32 {
+                75
+            }
+            fn hint(&self) -> Option<String> {
+                Some(String::from("Wake the TV up"))
+            }
+        }
+        let error = CliError::failed_to("connect to tv", &Asleep);
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["exitCode"], 75);
+        assert_eq!(json["hint"], "Wake the TV up");
+    }
 }
@@ -0,0 +1,107 @@
+//! Reading connection settings from `-o KEY=VALUE` and `--info`.
+
+use std::io::{Error, ErrorKind};
+
+use crate::ConnectSettings;
+
+impl ConnectSettings {
+    /// Set one field by its OpenSSH name, such as `ConnectTimeout`, or its name
+    /// in the device list, such as `connectTimeout`. Case doesn't matter.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] for a name it doesn't know, or a
+    /// value that isn't a whole number.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let number = || {
+            value.trim().parse::<u64>().map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{key} must be a whole number, not {value:?}"),
+                )
+            })
+        };
+        let count = || {
+            u32::try_from(number()?)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{key} is too large")))
+        };
+        match key.to_lowercase().as_str() {
+            "connecttimeout" => self.connect_timeout = Some(number()?),
+            "serveraliveinterval" => self.server_alive_interval = Some(number()?),
+            "serveralivecountmax" => self.server_alive_count_max = Some(count()?),
+            "connectionattempts" => self.connection_attempts = Some(count()?),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown option {key}. Use ConnectTimeout, ServerAliveInterval, \
+                         ServerAliveCountMax or ConnectionAttempts"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Set fields from `KEY=VALUE` strings, the way `-o` gives them.
+    ///
+    /// # Errors
+    ///
+    /// See [`ConnectSettings::set`]. A string with no `=` is an error too.
+    pub fn parse<S: AsRef<str>>(options: &[S]) -> Result<Self, Error> {
+        let mut settings = Self::default();
+        for option in options {
+            let option = option.as_ref();
+            let (key, value) = option.split_once('=').ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Expected KEY=VALUE, got {option:?}"),
+                )
+            })?;
+            settings.set(key.trim(), value)?;
+        }
+        Ok(settings)
+    }
+
+    /// These settings, with the unset ones taken from `fallback`.
+    #[must_use]
+    pub fn or(self, fallback: &ConnectSettings) -> Self {
+        Self {
+            connect_timeout: self.connect_timeout.or(fallback.connect_timeout),
+            server_alive_interval: self
+                .server_alive_interval
+                .or(fallback.server_alive_interval),
+            server_alive_count_max: self
+                .server_alive_count_max
+                .or(fallback.server_alive_count_max),
+            connection_attempts: self.connection_attempts.or(fallback.connection_attempts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ConnectSettings, Device};
+
+    #[test]
+    fn options_parse_by_either_name() {
+        let settings =
+            ConnectSettings::parse(&["ConnectTimeout=30", "serverAliveInterval = 15"]).unwrap();
+        assert_eq!(settings.connect_timeout, Some(30));
+        assert_eq!(settings.server_alive_interval, Some(15));
+        assert!(ConnectSettings::parse(&["Compression=yes"]).is_err());
+        assert!(ConnectSettings::parse(&["ConnectTimeout=soon"]).is_err());
+    }
+
+    #[test]
+    fn settings_sit_next_to_the_other_fields() {
+        let json = r#"{"profile":"ose","name":"tv","host":"1.2.3.4","port":22,
+            "username":"root","connectTimeout":30,"connectionAttempts":3}"#;
+        let device: Device = serde_json::from_str(json).unwrap();
+        assert_eq!(device.connection.connect_timeout, Some(30));
+
+        let out = serde_json::to_value(&device).unwrap();
+        assert_eq!(out["connectionAttempts"], 3);
+        assert!(out.get("serverAliveInterval").is_none());
+    }
+}
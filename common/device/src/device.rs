@@ -1,4 +1,4 @@
-use crate::Device;
+use crate::{ConnectSettings, Device, FileTransfer};
 
 impl AsRef<Device> for Device {
     fn as_ref(&self) -> &Device {
@@ -7,6 +7,37 @@ impl AsRef<Device> for Device {
 }
 
 impl Device {
+    /// A device at `host`, with what `ares-setup-device --add` fills in when it
+    /// isn't given: profile `ose`, port 9922, user `root` and SFTP.
+    #[must_use]
+    pub fn with_defaults(name: &str, host: &str) -> Self {
+        Self {
+            order: None,
+            default: None,
+            profile: String::from("ose"),
+            name: String::from(name),
+            description: None,
+            host: String::from(host),
+            port: 9922,
+            username: String::from("root"),
+            new: false,
+            private_key: None,
+            files: Some(FileTransfer::Sftp),
+            passphrase: None,
+            password: None,
+            secret_ref: None,
+            auth_methods: None,
+            log_daemon: None,
+            no_port_forwarding: None,
+            indelible: None,
+            groups: None,
+            tags: None,
+            proxy_jump: None,
+            ssh_config: None,
+            connection: ConnectSettings::default(),
+        }
+    }
+
     #[must_use]
     pub fn valid_passphrase(&self) -> Option<String> {
         self.passphrase.clone().filter(|s| !s.is_empty())
@@ -15,7 +46,7 @@ impl Device {
 
 #[cfg(test)]
 mod tests {
-    use crate::Device;
+    use crate::{ConnectSettings, Device};
 
     fn device_with_passphrase(passphrase: Option<&str>) -> Device {
         Device {
@@ -32,9 +63,16 @@ mod tests {
             files: None,
             passphrase: passphrase.map(String::from),
             password: None,
+            secret_ref: None,
+            auth_methods: None,
             log_daemon: None,
             no_port_forwarding: None,
             indelible: None,
+            groups: None,
+            tags: None,
+            proxy_jump: None,
+            ssh_config: None,
+            connection: ConnectSettings::default(),
         }
     }
 
@@ -1,10 +1,18 @@
 use serde::{Deserialize, Serialize};
 
 pub mod cli;
+mod connect;
 mod device;
 pub mod io;
 mod manager;
 mod privkey;
+pub mod prompt;
+mod secret;
+mod select;
+mod uri;
+
+pub use secret::VAULT_PASSWORD_ENV;
+pub use select::Selector;
 
 /// Reads and writes the device list.
 ///
@@ -41,6 +49,15 @@ pub struct Device {
     pub passphrase: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    /// Where the passphrase and the password are kept, when they aren't in the
+    /// device list.
+    #[serde(rename = "secretRef", skip_serializing_if = "Option::is_none")]
+    pub secret_ref: Option<SecretRef>,
+    /// The ways to log in, tried in this order until one works. Without it,
+    /// the device logs in with its key, its password or nothing, whichever it
+    /// has.
+    #[serde(rename = "authMethods", skip_serializing_if = "Option::is_none")]
+    pub auth_methods: Option<Vec<AuthMethod>>,
     #[serde(rename = "logDaemon", skip_serializing_if = "Option::is_none")]
     pub log_daemon: Option<String>,
     #[serde(
@@ -51,6 +68,48 @@ pub struct Device {
     pub no_port_forwarding: Option<bool>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub indelible: Option<bool>,
+    /// Groups this device belongs to, picked with `-d @GROUP`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+    /// Free-form labels, such as `webos6` or `signage`, picked with `-d tag:TAG`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// The device to connect through first: a device in the list, or
+    /// `user@host:port`. Set it when the device is only reachable from a gateway.
+    #[serde(rename = "proxyJump", default, skip_serializing_if = "Option::is_none")]
+    pub proxy_jump: Option<String>,
+    /// Let `~/.ssh/config` change how to reach the device, as it does for `ssh`.
+    /// Off unless set, or unless `ARES_SSH_CONFIG=1`.
+    #[serde(rename = "sshConfig", default, skip_serializing_if = "Option::is_none")]
+    pub ssh_config: Option<bool>,
+    /// Timeouts and keepalives, stored next to the other fields.
+    #[serde(flatten)]
+    pub connection: ConnectSettings,
+}
+
+/// How patient to be with a device. Unset fields take the defaults of the
+/// tools, and `-o` on the command line wins over all of them. The names are
+/// the OpenSSH ones, in camelCase.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ConnectSettings {
+    /// Seconds to wait for the device to answer.
+    #[serde(rename = "connectTimeout", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Seconds between keepalives on a quiet connection. 0 sends none.
+    #[serde(
+        rename = "serverAliveInterval",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub server_alive_interval: Option<u64>,
+    /// Keepalives that may fail in a row before the connection counts as lost.
+    #[serde(
+        rename = "serverAliveCountMax",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub server_alive_count_max: Option<u32>,
+    /// Times to try the first connection, waiting longer after each failure.
+    #[serde(rename = "connectionAttempts", skip_serializing_if = "Option::is_none")]
+    pub connection_attempts: Option<u32>,
 }
 
 /// How a device's SSH key is stored. The variants are untagged, so each one is
@@ -76,7 +135,50 @@ pub enum PrivateKey {
         #[serde(rename = "openSshData")]
         data: String,
     },
+    /// Keys held by the SSH agent at `SSH_AUTH_SOCK`, such as a hardware key.
+    /// Written as `{"agent": true}`.
+    Agent { agent: bool },
+}
+
+/// One way to log in to a device. The names are the ones OpenSSH uses for
+/// `PreferredAuthentications`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// The device's key file.
+    PublicKey,
+    /// The keys in the SSH agent.
+    Agent,
+    /// The device's password.
+    Password,
+    /// Answers to the questions the server asks, such as a password or a
+    /// one-time code.
+    #[serde(rename = "keyboard-interactive")]
+    KeyboardInteractive,
+    /// No credentials at all.
+    None,
+}
+
+/// The entry in a [`SecretStore`] that holds a device's passphrase and
+/// password.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct SecretRef {
+    pub store: SecretStore,
+    /// The device name when the secrets were stored, so a renamed device still
+    /// finds them.
+    pub id: String,
+}
+
+/// Where secrets go instead of the device list.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretStore {
+    /// An encrypted file next to the device list, unlocked with a password.
+    Vault,
+    /// The keyring of the OS: Keychain, Credential Manager or Secret Service.
+    Keyring,
 }
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub enum FileTransfer {
     #[serde(rename = "stream")]
@@ -118,6 +220,9 @@ mod device_json_tests {
         let inline: PrivateKey = serde_json::from_str(r#"{"openSshData":"KEY"}"#).unwrap();
         assert!(matches!(inline, PrivateKey::Data { ref data } if data == "KEY"));
 
+        let agent: PrivateKey = serde_json::from_str(r#"{"agent":true}"#).unwrap();
+        assert!(matches!(agent, PrivateKey::Agent { agent: true }));
+
         assert_eq!(
             serde_json::to_string(&PrivateKey::Name {
                 name: "webos_key".into()
@@ -54,6 +54,16 @@ fn normalize_private_key(device: &mut Device, ssh_dir: &Path) -> Result<(), Erro
     Ok(())
 }
 
+/// The device called `name` in `devices`, or one built from `name` if it is a
+/// connection URI. A saved device wins, so a name holding an `@` still works.
+pub(crate) fn find_in(devices: &[Device], name: &str) -> Option<Result<Device, Error>> {
+    match devices.iter().find(|d| d.name == name) {
+        Some(device) => Some(Ok(device.clone())),
+        None if Device::is_uri(name) => Some(Device::from_uri(name)),
+        None => None,
+    }
+}
+
 impl DeviceManager {
     /// A manager that reads the directories the webOS SDK uses.
     #[must_use]
@@ -111,24 +121,46 @@ impl DeviceManager {
         read_in(&self.conf_dir()?)
     }
 
+    /// The device called `name`, or the default device without a name. A name
+    /// that isn't in the list but reads as a connection URI gives a device
+    /// built from it, as [`Device::from_uri`] does.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the device list cannot be read.
+    /// Returns an error if the device list cannot be read, or if `name` is a
+    /// connection URI that doesn't parse.
     pub fn find_or_default<S: AsRef<str>>(
         &self,
         name: Option<&S>,
     ) -> Result<Option<Device>, Error> {
         let devices = self.list()?;
-        Ok(devices
+        match name {
+            Some(name) => find_in(&devices, name.as_ref()).transpose(),
+            None => Ok(devices.into_iter().find(|d| d.default.unwrap_or(false))),
+        }
+    }
+
+    /// The devices called `names`, in that order. Connection URIs are
+    /// accepted as in [`DeviceManager::find_or_default`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::NotFound`] naming the first device that isn't in
+    /// the list, or an error if the list cannot be read or a URI doesn't parse.
+    pub fn find_all<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<Device>, Error> {
+        let devices = self.list()?;
+        names
             .iter()
-            .find(|d| {
-                if let Some(name) = &name {
-                    d.name == name.as_ref()
-                } else {
-                    d.default.unwrap_or(false)
-                }
+            .map(|name| {
+                let name = name.as_ref();
+                find_in(&devices, name).unwrap_or_else(|| {
+                    Err(Error::new(
+                        ErrorKind::NotFound,
+                        format!("Device {name} not found"),
+                    ))
+                })
             })
-            .cloned())
+            .collect()
     }
 
     /// # Errors
@@ -244,7 +276,9 @@ impl DeviceManager {
                 }
                 if let Some(name) = device.private_key.and_then(|k| match k {
                     PrivateKey::Name { name } => Some(name),
-                    PrivateKey::Path { .. } | PrivateKey::Data { .. } => None,
+                    PrivateKey::Path { .. }
+                    | PrivateKey::Data { .. }
+                    | PrivateKey::Agent { .. } => None,
                 }) {
                     if !name.starts_with("webos_") {
                         continue;
@@ -307,6 +341,51 @@ mod tests {
         remove_dir_all(&dir).ok();
     }
 
+    #[test]
+    fn devices_are_found_by_name_and_group() {
+        let (manager, dir) = temp_manager("many");
+        let mut tv1 = device("tv1", false);
+        tv1.groups = Some(vec![String::from("lab")]);
+        manager.add(&tv1).unwrap();
+        manager.add(&device("tv2", false)).unwrap();
+
+        let names = |devices: Vec<Device>| devices.into_iter().map(|d| d.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(manager.find_all(&["tv2", "tv1"]).unwrap()),
+            ["tv2", "tv1"]
+        );
+        assert_eq!(
+            manager.find_all(&["tv3"]).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(names(manager.resolve(&["@lab"]).unwrap()), ["tv1"]);
+        assert_eq!(
+            manager.resolve(&["@office"]).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            names(manager.find_all(&["tv1", "root@10.0.0.5"]).unwrap()),
+            ["tv1", "root@10.0.0.5:9922"]
+        );
+        let adhoc = manager.find_or_default(Some(&"prisoner@10.0.0.5:22"));
+        assert_eq!(adhoc.unwrap().unwrap().username, "prisoner");
+
+        let mut tv3 = device("tv3", false);
+        tv3.groups = Some(vec![String::from("lab")]);
+        tv3.tags = Some(vec![String::from("webos6")]);
+        manager.add(&tv3).unwrap();
+        assert_eq!(
+            names(manager.resolve(&["@lab", "tv2", "tag:webos6"]).unwrap()),
+            ["tv1", "tv3", "tv2"]
+        );
+        assert_eq!(
+            manager.resolve(&["tag:webos4"]).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+
+        remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn a_normal_device_needs_no_force() {
         let (manager, dir) = temp_manager("normal");
@@ -1,6 +1,6 @@
 use std::fs::File;
-use std::io::{Error, Read};
-use std::path::Path;
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
 
 use crate::PrivateKey;
 use crate::io::ssh_dir;
@@ -11,6 +11,27 @@ impl PrivateKey {
         self.content_in(None)
     }
 
+    /// Where the key file is, resolving [`PrivateKey::Name`] against the user's
+    /// `~/.ssh`. `None` for a key that isn't kept in a file.
+    #[must_use]
+    pub fn file(&self) -> Option<PathBuf> {
+        self.file_in(None)
+    }
+
+    /// Where the key file is, resolving [`PrivateKey::Name`] against `ssh_dir`
+    /// as [`PrivateKey::content_in`] does.
+    #[must_use]
+    pub fn file_in(&self, ssh_dir_override: Option<&Path>) -> Option<PathBuf> {
+        match self {
+            PrivateKey::Name { name } => match ssh_dir_override {
+                Some(dir) => Some(dir.join(name)),
+                None => ssh_dir().ok().map(|dir| dir.join(name)),
+            },
+            PrivateKey::Path { path } => Some(PathBuf::from(path)),
+            PrivateKey::Data { .. } | PrivateKey::Agent { .. } => None,
+        }
+    }
+
     /// Read the key, resolving [`PrivateKey::Name`] against `ssh_dir`.
     /// `None` means the user's `~/.ssh`.
     pub fn content_in(&self, ssh_dir_override: Option<&Path>) -> Result<String, Error> {
@@ -24,6 +45,10 @@ impl PrivateKey {
             }
             PrivateKey::Path { path } => read_file(path),
             PrivateKey::Data { data } => Ok(data.clone()),
+            PrivateKey::Agent { .. } => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The key is in the SSH agent, which doesn't hand it out",
+            )),
         }
     }
 }
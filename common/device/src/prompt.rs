@@ -0,0 +1,133 @@
+//! Asking the user for what the device list lacks, such as a password.
+//!
+//! A tool only asks when stdin and stderr are both terminals and `--batch` is
+//! off. Otherwise [`secret`] fails at once with the message it is given,
+//! which should name what is missing, so a script or CI job never hangs on a
+//! prompt nobody sees.
+//!
+//! Threads that connect to several devices at once ask one at a time.
+
+use std::io::{Error, ErrorKind, IsTerminal, Write, stderr, stdin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+static BATCH: AtomicBool = AtomicBool::new(false);
+
+/// Runs a prompt with the tool's progress bars out of the way.
+pub type Suspend = Box<dyn Fn(&mut dyn FnMut()) + Send>;
+
+/// Held from a prompt until its answer is read, so that threads working on
+/// several devices ask one at a time and an answer can't reach another prompt.
+/// It holds what hides the progress bars meanwhile.
+static PROMPT: Mutex<Option<Suspend>> = Mutex::new(None);
+
+/// Never ask, for the rest of the run. Each tool calls this with `--batch`.
+pub fn set_batch(batch: bool) {
+    BATCH.store(batch, Ordering::Relaxed);
+}
+
+/// Run every prompt through `suspend`, or directly with `None`. A tool that
+/// draws progress bars sets this while they are shown.
+pub fn set_suspend(suspend: Option<Suspend>) {
+    *PROMPT.lock().unwrap_or_else(PoisonError::into_inner) = suspend;
+}
+
+/// `true` when the tool may ask the user something.
+#[must_use]
+pub fn interactive() -> bool {
+    !BATCH.load(Ordering::Relaxed) && stdin().is_terminal() && stderr().is_terminal()
+}
+
+/// Ask for a secret, with echo off.
+///
+/// # Errors
+///
+/// Returns [`ErrorKind::PermissionDenied`] with `missing` as the message when
+/// the tool may not ask, or an I/O error if the terminal can't be read.
+pub fn secret(prompt: &str, missing: &str) -> Result<String, Error> {
+    if !interactive() {
+        return Err(Error::new(ErrorKind::PermissionDenied, missing));
+    }
+    asking(|| rpassword::prompt_password(prompt))
+}
+
+/// Ask for a line of text, shown as it is typed.
+///
+/// # Errors
+///
+/// The same as [`secret`].
+pub fn line(prompt: &str, missing: &str) -> Result<String, Error> {
+    if !interactive() {
+        return Err(Error::new(ErrorKind::PermissionDenied, missing));
+    }
+    asking(|| {
+        eprint!("{prompt}");
+        stderr().flush()?;
+        let mut answer = String::new();
+        stdin().read_line(&mut answer)?;
+        Ok(answer.trim_end_matches(['\r', '\n']).to_string())
+    })
+}
+
+/// Show `text` to the user before the prompts it explains, when the tool may
+/// ask.
+pub fn note(text: &str) {
+    if interactive() {
+        let _ = asking(|| {
+            eprintln!("{text}");
+            Ok(())
+        });
+    }
+}
+
+/// Run `ask` while no other prompt runs, with the progress bars hidden.
+fn asking<T>(ask: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    let suspend = PROMPT.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(suspend) = suspend.as_ref() else {
+        return ask();
+    };
+    let mut ask = Some(ask);
+    let mut answer = None;
+    suspend(&mut || answer = ask.take().map(|ask| ask()));
+    answer.unwrap_or_else(|| Err(Error::other("The prompt did not run")))
+}
+
+/// Ask a yes or no `question`. Anything but "y" or "yes" is no, and so is not
+/// being allowed to ask.
+#[must_use]
+pub fn confirm(question: &str) -> bool {
+    line(&format!("{question} [y/N] "), "")
+        .is_ok_and(|answer| matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{asking, confirm, secret, set_batch, set_suspend};
+
+    #[test]
+    fn prompts_run_through_the_suspend() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        set_suspend(Some(Box::new(move |ask| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            ask();
+        })));
+        assert_eq!(asking(|| Ok(7)).unwrap(), 7);
+        set_suspend(None);
+        assert_eq!(asking(|| Ok(8)).unwrap(), 8);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn batch_mode_names_what_is_missing() {
+        set_batch(true);
+        let error = secret("Password: ", "tv has no `password`").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(error.to_string(), "tv has no `password`");
+        assert!(!confirm("Save?"));
+    }
+}
@@ -0,0 +1,508 @@
+//! Passphrases and passwords kept out of the device list, which other tools
+//! read and which is plain text.
+//!
+//! A device with a [`SecretRef`] has no `passphrase` or `password` in the list.
+//! They are in the vault, a file next to the list encrypted with a password, or
+//! in the OS keyring. [`Device::unlocked`] puts them back for a connection.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::fs::{OpenOptions, read_to_string, remove_file, rename};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::io::conf_dir;
+use crate::prompt;
+use crate::{Device, DeviceManager, SecretRef, SecretStore};
+
+/// The variable that holds the vault password, for CI and scripts. Without it,
+/// the tools ask for the password in the terminal.
+pub const VAULT_PASSWORD_ENV: &str = "ARES_VAULT_PASSWORD";
+
+const VAULT_FILE: &str = "ares-vault.json";
+const VAULT_VERSION: u32 = 1;
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "ares-cli-rs";
+
+/// The salt and the key of the vault unlocked last. Every connection unlocks
+/// its device, so without it `--all` would ask for the password and run Argon2
+/// once per device. It is locked while asking, so one prompt shows at a time.
+static UNLOCKED: Mutex<Option<(Vec<u8>, Key)>> = Mutex::new(None);
+
+/// What one store entry holds.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+struct Secrets {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passphrase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+}
+
+/// The vault file. `data` is the entries as JSON, encrypted with
+/// ChaCha20-Poly1305 under a key that Argon2id derives from the password and
+/// `salt`.
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+/// An unlocked vault.
+struct Vault {
+    path: PathBuf,
+    salt: Vec<u8>,
+    key: Key,
+    entries: BTreeMap<String, Secrets>,
+}
+
+impl FromStr for SecretStore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vault" => Ok(SecretStore::Vault),
+            "keyring" => Ok(SecretStore::Keyring),
+            _ => Err(format!("unknown secret store {s}, use vault or keyring")),
+        }
+    }
+}
+
+impl Display for SecretStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretStore::Vault => write!(f, "vault"),
+            SecretStore::Keyring => write!(f, "keyring"),
+        }
+    }
+}
+
+impl Device {
+    /// This device with its passphrase and password read back from its
+    /// [`SecretRef`], using the vault next to the SDK's device list. A device
+    /// without one comes back as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store can't be read, the vault password is
+    /// wrong, or the store has no entry for the device.
+    pub fn unlocked(&self) -> Result<Device, Error> {
+        match &self.secret_ref {
+            Some(_) => self.unlocked_in(&conf_dir()?),
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// Like [`Device::unlocked`], with the vault in `conf_dir`.
+    ///
+    /// # Errors
+    ///
+    /// See [`Device::unlocked`].
+    pub fn unlocked_in(&self, conf_dir: &Path) -> Result<Device, Error> {
+        let mut device = self.clone();
+        let Some(secret_ref) = &self.secret_ref else {
+            return Ok(device);
+        };
+        let secrets = match secret_ref.store {
+            SecretStore::Vault => Vault::open(conf_dir, false)?
+                .entries
+                .remove(&secret_ref.id)
+                .ok_or_else(|| missing(&secret_ref.id))?,
+            SecretStore::Keyring => os_keyring::get(&secret_ref.id)?,
+        };
+        // What the store holds wins over what another tool left in the list.
+        device.passphrase = secrets.passphrase.or(device.passphrase);
+        device.password = secrets.password.or(device.password);
+        Ok(device)
+    }
+}
+
+impl DeviceManager {
+    /// [`Device::unlocked_in`] with this manager's directory.
+    ///
+    /// # Errors
+    ///
+    /// See [`Device::unlocked`].
+    pub fn unlock(&self, device: &Device) -> Result<Device, Error> {
+        device.unlocked_in(&self.conf_dir()?)
+    }
+
+    /// Move the passphrase and the password of `device` into `store`, and
+    /// leave a [`SecretRef`] in their place. A secret the device doesn't carry
+    /// keeps the value the store already has. Save the device next, with
+    /// [`DeviceManager::add`] or [`DeviceManager::modify`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store can't be read or written, or the vault
+    /// password is wrong.
+    pub fn store_secrets(&self, device: &mut Device, store: SecretStore) -> Result<(), Error> {
+        let id = device
+            .secret_ref
+            .as_ref()
+            .filter(|r| r.store == store)
+            .map_or_else(|| device.name.clone(), |r| r.id.clone());
+        let update = |mut secrets: Secrets| {
+            if device.passphrase.is_some() {
+                secrets.passphrase.clone_from(&device.passphrase);
+            }
+            if device.password.is_some() {
+                secrets.password.clone_from(&device.password);
+            }
+            secrets
+        };
+        match store {
+            SecretStore::Vault => {
+                let mut vault = Vault::open(&self.conf_dir()?, true)?;
+                let secrets = update(vault.entries.remove(&id).unwrap_or_default());
+                vault.entries.insert(id.clone(), secrets);
+                vault.save()?;
+            }
+            SecretStore::Keyring => {
+                let existing = match os_keyring::get(&id) {
+                    Err(e) if e.kind() == ErrorKind::NotFound => Secrets::default(),
+                    other => other?,
+                };
+                os_keyring::set(&id, &update(existing))?;
+            }
+        }
+        device.passphrase = None;
+        device.password = None;
+        device.secret_ref = Some(SecretRef { store, id });
+        Ok(())
+    }
+
+    /// Delete the secrets of `device` from its store. A device without a
+    /// [`SecretRef`] has nothing to delete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store can't be written, or the vault password
+    /// is wrong.
+    pub fn forget_secrets(&self, device: &Device) -> Result<(), Error> {
+        let Some(secret_ref) = &device.secret_ref else {
+            return Ok(());
+        };
+        match secret_ref.store {
+            SecretStore::Vault => {
+                let mut vault = Vault::open(&self.conf_dir()?, false)?;
+                if vault.entries.remove(&secret_ref.id).is_some() {
+                    vault.save()?;
+                }
+                Ok(())
+            }
+            SecretStore::Keyring => os_keyring::delete(&secret_ref.id),
+        }
+    }
+}
+
+impl Vault {
+    /// Read and decrypt the vault in `dir`. With `create`, a missing vault
+    /// starts out empty, under a password asked twice. The password is asked
+    /// once per run.
+    fn open(dir: &Path, create: bool) -> Result<Vault, Error> {
+        let mut unlocked = UNLOCKED.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = dir.join(VAULT_FILE);
+        if !path.exists() {
+            if !create {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("There is no vault at {}", path.display()),
+                ));
+            }
+            let mut salt = vec![0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(&vault_password(true)?, &salt)?;
+            *unlocked = Some((salt.clone(), key));
+            return Ok(Vault {
+                path,
+                salt,
+                key,
+                entries: BTreeMap::new(),
+            });
+        }
+        let file: VaultFile = serde_json::from_str(&read_to_string(&path)?)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if file.version != VAULT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The vault is version {}, which this build can't read",
+                    file.version
+                ),
+            ));
+        }
+        let salt = decode(&file.salt)?;
+        let nonce = decode(&file.nonce)?;
+        if nonce.len() != 12 {
+            return Err(damaged());
+        }
+        let key = match &*unlocked {
+            Some((unlocked_salt, key)) if *unlocked_salt == salt => *key,
+            _ => derive_key(&vault_password(false)?, &salt)?,
+        };
+        let plain = ChaCha20Poly1305::new(&key)
+            .decrypt(Nonce::from_slice(&nonce), decode(&file.data)?.as_slice())
+            .map_err(|_| {
+                Error::new(
+                    ErrorKind::PermissionDenied,
+                    "Wrong vault password, or the vault is damaged",
+                )
+            })?;
+        let entries =
+            serde_json::from_slice(&plain).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        *unlocked = Some((salt.clone(), key));
+        Ok(Vault {
+            path,
+            salt,
+            key,
+            entries,
+        })
+    }
+
+    /// Encrypt the entries under a fresh nonce and write the vault, readable
+    /// by its owner alone.
+    fn save(&self) -> Result<(), Error> {
+        let plain =
+            serde_json::to_vec(&self.entries).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let data = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, plain.as_slice())
+            .map_err(|_| Error::other("Failed to encrypt the vault"))?;
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            salt: BASE64.encode(&self.salt),
+            nonce: BASE64.encode(nonce),
+            data: BASE64.encode(data),
+        };
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        // Write a copy and move it over the vault, so that a write cut short
+        // leaves the old vault whole. The copy is new, so it always gets the
+        // owner-only mode, even where the old vault had a wider one.
+        let temp = self.path.with_extension("tmp");
+        let _ = remove_file(&temp);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let written = options.open(&temp).and_then(|mut file| {
+            file.write_all(json.as_bytes())?;
+            file.sync_all()
+        });
+        match written.and_then(|()| rename(&temp, &self.path)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = remove_file(&temp);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// The vault password, from [`VAULT_PASSWORD_ENV`] or asked in the terminal.
+/// `confirm` asks twice, for a new vault.
+fn vault_password(confirm: bool) -> Result<String, Error> {
+    if let Ok(password) = env::var(VAULT_PASSWORD_ENV) {
+        return Ok(password);
+    }
+    let locked = format!(
+        "The vault is locked. Set {VAULT_PASSWORD_ENV}, or run in a terminal without --batch"
+    );
+    let password = prompt::secret("Vault password: ", &locked)?;
+    if confirm && prompt::secret("Repeat the vault password: ", &locked)? != password {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The vault passwords don't match",
+        ));
+    }
+    Ok(password)
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> Result<Key, Error> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    Ok(key)
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, Error> {
+    BASE64.decode(text).map_err(|_| damaged())
+}
+
+fn damaged() -> Error {
+    Error::new(ErrorKind::InvalidData, "The vault is damaged")
+}
+
+fn missing(id: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("The secret store has nothing for {id}"),
+    )
+}
+
+#[cfg(feature = "keyring")]
+mod os_keyring {
+    use std::io::{Error, ErrorKind};
+
+    use keyring::{Entry, Error as KeyringError};
+
+    use super::{KEYRING_SERVICE, Secrets, missing};
+
+    pub(super) fn get(id: &str) -> Result<Secrets, Error> {
+        match entry(id)?.get_password() {
+            Ok(json) => {
+                serde_json::from_str(&json).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+            }
+            Err(KeyringError::NoEntry) => Err(missing(id)),
+            Err(e) => Err(keyring_error(&e)),
+        }
+    }
+
+    pub(super) fn set(id: &str, secrets: &Secrets) -> Result<(), Error> {
+        let json =
+            serde_json::to_string(secrets).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        entry(id)?
+            .set_password(&json)
+            .map_err(|e| keyring_error(&e))
+    }
+
+    pub(super) fn delete(id: &str) -> Result<(), Error> {
+        match entry(id)?.delete_credential() {
+            Ok(()) | Err(KeyringError::NoEntry) => Ok(()),
+            Err(e) => Err(keyring_error(&e)),
+        }
+    }
+
+    fn entry(id: &str) -> Result<Entry, Error> {
+        Entry::new(KEYRING_SERVICE, id).map_err(|e| keyring_error(&e))
+    }
+
+    fn keyring_error(e: &KeyringError) -> Error {
+        Error::other(format!("Keyring: {e}"))
+    }
+}
+
+/// Without the `keyring` feature, a device that points at the keyring fails
+/// to connect instead of trying without its secrets.
+#[cfg(not(feature = "keyring"))]
+mod os_keyring {
+    use std::io::{Error, ErrorKind};
+
+    use super::Secrets;
+
+    fn unsupported() -> Error {
+        Error::new(ErrorKind::Unsupported, "This build has no keyring support")
+    }
+
+    pub(super) fn get(_id: &str) -> Result<Secrets, Error> {
+        Err(unsupported())
+    }
+
+    pub(super) fn set(_id: &str, _secrets: &Secrets) -> Result<(), Error> {
+        Err(unsupported())
+    }
+
+    pub(super) fn delete(_id: &str) -> Result<(), Error> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+
+    fn device(passphrase: &str) -> Device {
+        let mut device = Device::with_defaults("tv", "10.0.0.2");
+        device.passphrase = Some(String::from(passphrase));
+        device
+    }
+
+    #[test]
+    fn secrets_go_to_the_vault_and_come_back() {
+        // SAFETY: no other test reads or writes this variable.
+        unsafe { env::set_var(VAULT_PASSWORD_ENV, "master") };
+        let dir = env::temp_dir().join(format!("ares-vault-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let manager = DeviceManager::with_dirs(dir.clone(), dir.join("ssh"));
+
+        let mut tv = device("ABC123");
+        manager.store_secrets(&mut tv, SecretStore::Vault).unwrap();
+        assert_eq!(tv.passphrase, None);
+        assert_eq!(
+            tv.secret_ref,
+            Some(SecretRef {
+                store: SecretStore::Vault,
+                id: String::from("tv")
+            })
+        );
+        let on_disk = read_to_string(dir.join(VAULT_FILE)).unwrap();
+        assert!(!on_disk.contains("ABC123"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join(VAULT_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert_eq!(
+            manager.unlock(&tv).unwrap().passphrase.as_deref(),
+            Some("ABC123")
+        );
+
+        // A password alone keeps the stored passphrase. Saving again also
+        // narrows a vault left readable by others.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let loose = std::fs::Permissions::from_mode(0o644);
+            std::fs::set_permissions(dir.join(VAULT_FILE), loose).unwrap();
+        }
+        tv.password = Some(String::from("pw"));
+        manager.store_secrets(&mut tv, SecretStore::Vault).unwrap();
+        let unlocked = manager.unlock(&tv).unwrap();
+        assert_eq!(unlocked.passphrase.as_deref(), Some("ABC123"));
+        assert_eq!(unlocked.password.as_deref(), Some("pw"));
+        assert!(!dir.join(VAULT_FILE).with_extension("tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join(VAULT_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        manager.forget_secrets(&tv).unwrap();
+        assert_eq!(manager.unlock(&tv).unwrap_err().kind(), ErrorKind::NotFound);
+        remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn a_device_without_a_reference_is_left_alone() {
+        let tv = device("ABC123");
+        assert_eq!(tv.unlocked().unwrap().passphrase.as_deref(), Some("ABC123"));
+    }
+}
@@ -0,0 +1,124 @@
+//! Picking devices with `-d`: a name, a connection URI, `@GROUP` for the
+//! devices in a group, or `tag:TAG` for the devices with a tag.
+
+use std::io::{Error, ErrorKind};
+
+use crate::manager::find_in;
+use crate::{Device, DeviceManager};
+
+/// What one `-d` value picks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selector<'a> {
+    /// A device name, or a connection URI.
+    Device(&'a str),
+    /// `@GROUP`: the devices in a group.
+    Group(&'a str),
+    /// `tag:TAG`: the devices with a tag.
+    Tag(&'a str),
+}
+
+impl<'a> Selector<'a> {
+    #[must_use]
+    pub fn parse(target: &'a str) -> Self {
+        if let Some(group) = target.strip_prefix('@') {
+            Selector::Group(group)
+        } else if let Some(tag) = target.strip_prefix("tag:") {
+            Selector::Tag(tag)
+        } else {
+            Selector::Device(target)
+        }
+    }
+
+    /// `true` for `@GROUP` and `tag:TAG`, which pick any number of devices.
+    #[must_use]
+    pub fn is_set(&self) -> bool {
+        !matches!(self, Selector::Device(_))
+    }
+
+    #[must_use]
+    pub fn matches(&self, device: &Device) -> bool {
+        match self {
+            Selector::Device(name) => device.name == *name,
+            Selector::Group(group) => has_label(device.groups.as_deref(), group),
+            Selector::Tag(tag) => has_label(device.tags.as_deref(), tag),
+        }
+    }
+}
+
+impl DeviceManager {
+    /// The devices `targets` pick, read as [`Selector`]s, in order and each one
+    /// once. No targets picks the default device. A device in the list whose
+    /// name is the whole target wins over a group, a tag or a URI.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::NotFound`] for a device that isn't in the list, a
+    /// group or a tag no device has, or no targets and no default device.
+    /// Returns an error if the list cannot be read or a URI doesn't parse.
+    pub fn resolve<S: AsRef<str>>(&self, targets: &[S]) -> Result<Vec<Device>, Error> {
+        let devices = self.list()?;
+        if targets.is_empty() {
+            return devices
+                .into_iter()
+                .find(|d| d.default.unwrap_or(false))
+                .map(|device| vec![device])
+                .ok_or_else(|| not_found(String::from("No device given, and no default device")));
+        }
+        let mut picked: Vec<Device> = Vec::new();
+        for target in targets {
+            for device in select(&devices, target.as_ref())? {
+                if !picked.iter().any(|d| d.name == device.name) {
+                    picked.push(device);
+                }
+            }
+        }
+        Ok(picked)
+    }
+}
+
+fn select(devices: &[Device], target: &str) -> Result<Vec<Device>, Error> {
+    let selector = Selector::parse(target);
+    if !selector.is_set() || devices.iter().any(|d| d.name == target) {
+        return match find_in(devices, target) {
+            Some(device) => Ok(vec![device?]),
+            None => Err(not_found(format!("Device {target} not found"))),
+        };
+    }
+    let found: Vec<Device> = devices
+        .iter()
+        .filter(|d| selector.matches(d))
+        .cloned()
+        .collect();
+    if found.is_empty() {
+        return Err(not_found(match selector {
+            Selector::Group(group) => format!("No devices in group {group}"),
+            Selector::Tag(tag) => format!("No devices tagged {tag}"),
+            Selector::Device(name) => format!("Device {name} not found"),
+        }));
+    }
+    Ok(found)
+}
+
+fn has_label(labels: Option<&[String]>, label: &str) -> bool {
+    labels.unwrap_or_default().iter().any(|l| l == label)
+}
+
+fn not_found(message: String) -> Error {
+    Error::new(ErrorKind::NotFound, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selector;
+
+    #[test]
+    fn targets_parse_by_prefix() {
+        assert_eq!(Selector::parse("@lab"), Selector::Group("lab"));
+        assert_eq!(Selector::parse("tag:webos6"), Selector::Tag("webos6"));
+        assert_eq!(Selector::parse("tv"), Selector::Device("tv"));
+        assert_eq!(
+            Selector::parse("root@10.0.0.5"),
+            Selector::Device("root@10.0.0.5")
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Devices given as a connection URI instead of a name in the device list, such
+//! as `ssh://prisoner@10.0.0.5:9922?key=~/.ssh/webos_tv&passphrase=ABC123` or
+//! `root@10.0.0.5`.
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use crate::{Device, PrivateKey};
+
+impl Device {
+    /// `true` if `target` reads as a connection URI rather than a device name:
+    /// it starts with `ssh://` or holds an `@`.
+    #[must_use]
+    pub fn is_uri(target: &str) -> bool {
+        target.starts_with("ssh://") || target.contains('@')
+    }
+
+    /// A device that isn't in the list, built from a connection URI. What the
+    /// URI leaves out takes the defaults of [`Device::with_defaults`]. The
+    /// query may give `key` (a key file, `~` for the home directory),
+    /// `passphrase` and `password`, percent-encoded.
+    ///
+    /// The device is named `user@host:port`, with an IPv6 host in brackets, so
+    /// the secrets don't show up in messages and the name reads back as a URI.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] if the URI has no host, a bad port,
+    /// an IPv6 address outside brackets or an unknown parameter.
+    pub fn from_uri(uri: &str) -> Result<Device, Error> {
+        let rest = uri.strip_prefix("ssh://").unwrap_or(uri);
+        let (authority, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let authority = authority.trim_end_matches('/');
+        let (user, address) = match authority.rsplit_once('@') {
+            Some((user, address)) => (Some(decode(user)?), address),
+            None => (None, authority),
+        };
+        let (host, port) = split_port(address)?;
+        if host.is_empty() {
+            return Err(invalid(format!("{uri} has no host")));
+        }
+
+        let mut device = Device::with_defaults("", host);
+        if let Some(user) = user.filter(|user| !user.is_empty()) {
+            device.username = user;
+        }
+        if let Some(port) = port {
+            device.port = port;
+        }
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("{pair} in {uri} has no value")))?;
+            let value = decode(value)?;
+            match key {
+                "key" => {
+                    device.private_key = Some(PrivateKey::Path {
+                        path: expand_home(&value).to_string_lossy().into_owned(),
+                    });
+                }
+                "passphrase" => device.passphrase = Some(value),
+                "password" => device.password = Some(value),
+                _ => return Err(invalid(format!("Unknown parameter {key} in {uri}"))),
+            }
+        }
+        device.name = if device.host.contains(':') {
+            format!("{}@[{}]:{}", device.username, device.host, device.port)
+        } else {
+            format!("{}@{}:{}", device.username, device.host, device.port)
+        };
+        Ok(device)
+    }
+}
+
+/// Split `host:port`. An IPv6 address goes in brackets, as in `[::1]:22`,
+/// since its last group can't be told from a port otherwise.
+fn split_port(address: &str) -> Result<(&str, Option<u16>), Error> {
+    let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| invalid(format!("{address} is missing a ]")))?;
+        let port = match rest {
+            "" => None,
+            rest => Some(
+                rest.strip_prefix(':')
+                    .ok_or_else(|| invalid(format!("{address} has {rest} after the address")))?,
+            ),
+        };
+        (host, port)
+    } else {
+        match address.split_once(':') {
+            Some((_, port)) if port.contains(':') => {
+                return Err(invalid(format!(
+                    "Put the IPv6 address {address} in brackets, as in [{address}]"
+                )));
+            }
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        }
+    };
+    let port = port
+        .map(|port| {
+            port.parse::<u16>()
+                .ok()
+                .filter(|port| *port != 0)
+                .ok_or_else(|| invalid(format!("Invalid port {port}")))
+        })
+        .transpose()?;
+    Ok((host, port))
+}
+
+/// Undo percent-encoding, so a passphrase can hold `&`, `=` or `@`.
+fn decode(text: &str) -> Result<String, Error> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = text
+                .get(i + 1..i + 3)
+                // from_str_radix would take a sign, as in `%+1`.
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid(format!("Bad percent-encoding in {text}")))?;
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| invalid(format!("{text} is not UTF-8")))
+}
+
+/// Replace a leading `~` with the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return PathBuf::from(path),
+    };
+    match env::home_dir() {
+        Some(home) => home.join(rest.trim_start_matches(['/', '\\'])),
+        None => PathBuf::from(path),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{Device, PrivateKey};
+
+    #[test]
+    fn a_full_uri_sets_every_field() {
+        let device =
+            Device::from_uri("ssh://prisoner@10.0.0.5:9922?key=/keys/webos_x&passphrase=AB%26C123")
+                .unwrap();
+        assert_eq!(device.name, "prisoner@10.0.0.5:9922");
+        assert_eq!(device.username, "prisoner");
+        assert_eq!(device.host, "10.0.0.5");
+        assert_eq!(device.port, 9922);
+        assert_eq!(device.passphrase.as_deref(), Some("AB&C123"));
+        assert!(matches!(
+            device.private_key,
+            Some(PrivateKey::Path { ref path }) if path == "/keys/webos_x"
+        ));
+    }
+
+    #[test]
+    fn a_short_form_takes_the_defaults() {
+        let device = Device::from_uri("root@10.0.0.5").unwrap();
+        assert_eq!(device.name, "root@10.0.0.5:9922");
+        assert_eq!(device.port, 9922);
+        assert_eq!(device.profile, "ose");
+
+        let device = Device::from_uri("ssh://[fe80::1]:22").unwrap();
+        assert_eq!(device.host, "fe80::1");
+        assert_eq!(device.username, "root");
+        assert_eq!(device.port, 22);
+        assert_eq!(device.name, "root@[fe80::1]:22");
+        let again = Device::from_uri(&device.name).unwrap();
+        assert_eq!((again.host, again.port), (device.host, device.port));
+    }
+
+    #[test]
+    fn names_and_uris_are_told_apart() {
+        assert!(Device::is_uri("root@10.0.0.5"));
+        assert!(Device::is_uri("ssh://10.0.0.5"));
+        assert!(!Device::is_uri("living-room-tv"));
+    }
+
+    #[test]
+    fn bad_uris_are_refused() {
+        for uri in [
+            "ssh://root@",
+            "root@10.0.0.5:99999",
+            "root@10.0.0.5?colour=red",
+            "root@10.0.0.5?passphrase=%zz",
+            "root@h?password=%+1",
+            "root@fe80::1",
+            "root@[fe80::1]22",
+        ] {
+            let e = Device::from_uri(uri).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput, "{uri}");
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use ar::Builder as ArBuilder;
+
+use crate::error::PackageError;
+use crate::input::control::ControlFile;
+use crate::input::data::DataInfo;
+use crate::input::project::ProjectInfo;
+use crate::input::validation::{PackageArch, Validation};
+use crate::packaging::compression::Compression;
+use crate::packaging::control::{AppendControl, ControlInfo};
+use crate::packaging::data::{AddedEntry, AppendData, DataArchive};
+use crate::packaging::header::AppendHeader;
+
+/// Called with each entry as it goes into the package.
+type Progress<'a> = Box<dyn FnMut(&AddedEntry) + 'a>;
+
+/// Put together an ipk from an app and its services.
+///
+/// ```no_run
+/// use ares_package_lib::builder::PackageBuilder;
+///
+/// let mut package = PackageBuilder::new()
+///     .app_dir("my-app")
+///     .exclude("*.map")
+///     .progress(|entry| println!("Adding {}", entry.path))
+///     .build()?;
+/// let file = std::fs::File::create(package.file_name())?;
+/// package.write_to(file)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Default)]
+pub struct PackageBuilder<'a> {
+    project: ProjectInfo,
+    force_arch: Option<PackageArch>,
+    compression: Compression,
+    control_file: Option<ControlFile>,
+    control_fields: Vec<(String, String)>,
+    temp_dir: Option<PathBuf>,
+    mtime: Option<u64>,
+    progress: Option<Progress<'a>>,
+}
+
+/// A package that has been read and checked, ready to write.
+pub struct Package<'a> {
+    data: DataInfo,
+    control: ControlInfo,
+    arch: PackageArch,
+    file_name: String,
+    compression: Compression,
+    temp_dir: Option<PathBuf>,
+    mtime: u64,
+    progress: Option<Progress<'a>>,
+}
+
+impl<'a> PackageBuilder<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from a project, such as one read from `ares-package.json`.
+    #[must_use]
+    pub fn from_project(project: ProjectInfo) -> Self {
+        Self {
+            project,
+            ..Self::default()
+        }
+    }
+
+    /// The app directory, holding `appinfo.json`.
+    #[must_use]
+    pub fn app_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.project.app = Some(dir.into());
+        self
+    }
+
+    /// Add a service directory, holding `services.json`.
+    #[must_use]
+    pub fn service_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.project.services.push(dir.into());
+        self
+    }
+
+    /// Leave out files matching `pattern`, as `--app-exclude` does.
+    #[must_use]
+    pub fn exclude<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.project.exclude.push(pattern.into());
+        self
+    }
+
+    /// Mark files matching `pattern` executable, as `--executable` does.
+    #[must_use]
+    pub fn executable<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.project.executable.push(pattern.into());
+        self
+    }
+
+    /// Use `arch` whatever the binaries say. This wins over the project's.
+    #[must_use]
+    pub fn force_arch(mut self, arch: PackageArch) -> Self {
+        self.force_arch = Some(arch);
+        self
+    }
+
+    #[must_use]
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Take control fields from `file`, over the ones from `appinfo.json`.
+    #[must_use]
+    pub fn control_file(mut self, file: ControlFile) -> Self {
+        self.control_file = Some(file);
+        self
+    }
+
+    /// Set a control field. Fields are set in order, after the control file.
+    #[must_use]
+    pub fn control_field<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.control_fields.push((name.into(), value.into()));
+        self
+    }
+
+    /// Where the compressed data waits while the package is written. Pass the
+    /// output directory, as the system temporary directory may be in memory.
+    #[must_use]
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Timestamp for the entries the packager makes up, in seconds since the
+    /// epoch. Defaults to now.
+    #[must_use]
+    pub fn mtime(mut self, mtime: u64) -> Self {
+        self.mtime = Some(mtime);
+        self
+    }
+
+    /// Call `progress` for every file, directory and link added.
+    #[must_use]
+    pub fn progress<F: FnMut(&AddedEntry) + 'a>(mut self, progress: F) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Read the app and services, work out the architecture and fill in the
+    /// control file. Nothing is compressed yet, so mistakes show up quickly.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PackageError::Input`] if the inputs can't be read,
+    /// [`PackageError::Arch`] if the architecture is in doubt, and
+    /// [`PackageError::Control`] for a bad control field.
+    pub fn build(self) -> Result<Package<'a>, PackageError> {
+        let pinned = self.project.pinned_arch().map_err(PackageError::Input)?;
+        let data = DataInfo::from_input(&self.project).map_err(PackageError::Input)?;
+        let forced = self.force_arch.clone().or(pinned);
+        let validation = data
+            .validate(forced.is_some())
+            .map_err(|e| PackageError::Arch(e.to_string()))?;
+        let arch = forced
+            .or_else(|| validation.arch.clone())
+            .unwrap_or(PackageArch::ALL);
+        if let Some(validation_arch) = &validation.arch
+            && std::mem::discriminant(&arch) != std::mem::discriminant(validation_arch)
+        {
+            return Err(PackageError::Arch(format!(
+                "Incompatible architecture: {arch} != {validation_arch}"
+            )));
+        }
+
+        let control = self
+            .control_info(&data, arch.to_string())
+            .map_err(|e| PackageError::Control(e.to_string()))?;
+        let file_name = self
+            .project
+            .output_name(&data.package.id, &data.package.version, &arch.to_string())
+            .map_err(PackageError::Input)?;
+        let mtime = self.mtime.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+        Ok(Package {
+            data,
+            control,
+            arch,
+            file_name,
+            compression: self.compression,
+            temp_dir: self.temp_dir,
+            mtime,
+            progress: self.progress,
+        })
+    }
+
+    /// Fill in the control file. Each source overrides the one before it: the
+    /// defaults, `appinfo.json`, the control file, then the fields in order.
+    fn control_info(&self, data: &DataInfo, architecture: String) -> std::io::Result<ControlInfo> {
+        let package = &data.package;
+        let mut control =
+            ControlInfo::new(package.id.clone(), package.version.clone(), architecture, 0);
+        if let Some(app) = &data.app {
+            let app = &app.info;
+            if let Some(vendor) = app.vendor.as_deref().filter(|v| !v.trim().is_empty()) {
+                control.set("Maintainer", vendor)?;
+            }
+            control.set("Description", &app.title)?;
+        } else if let Some(description) = data
+            .services
+            .iter()
+            .find_map(|service| service.info.description.as_deref())
+            .filter(|d| !d.trim().is_empty())
+        {
+            control.set("Description", description)?;
+        }
+
+        if let Some(file) = &self.control_file {
+            let known = [
+                ("Maintainer", &file.maintainer),
+                ("Description", &file.description),
+                ("Depends", &file.depends),
+                ("Source", &file.source),
+                ("Homepage", &file.homepage),
+            ];
+            for (name, value) in known {
+                if let Some(value) = value {
+                    control.set(name, value)?;
+                }
+            }
+            for (name, value) in &file.control {
+                control.set(name, value)?;
+            }
+        }
+        for (name, value) in &self.control_fields {
+            control.set(name.trim(), value)?;
+        }
+        Ok(control)
+    }
+}
+
+impl Package<'_> {
+    #[must_use]
+    pub fn data(&self) -> &DataInfo {
+        &self.data
+    }
+
+    /// The control file. `Installed-Size` is filled in by [`Package::write_to`].
+    #[must_use]
+    pub fn control(&self) -> &ControlInfo {
+        &self.control
+    }
+
+    #[must_use]
+    pub fn arch(&self) -> &PackageArch {
+        &self.arch
+    }
+
+    /// The package file name, `{id}_{version}_{arch}.ipk` unless the project
+    /// says otherwise.
+    #[must_use]
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Compress the contents and write the ipk to `writer`, then hand the
+    /// writer back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PackageError::Entry`] for a file that can't go into a package,
+    /// and [`PackageError::Io`] if reading or writing fails.
+    pub fn write_to<W: Write>(&mut self, writer: W) -> Result<W, PackageError> {
+        let mut ignore = |_: &AddedEntry| {};
+        let progress: &mut dyn FnMut(&AddedEntry) = match &mut self.progress {
+            Some(progress) => progress,
+            None => &mut ignore,
+        };
+        let data_archive = DataArchive::build(
+            &self.data,
+            self.mtime,
+            self.compression,
+            self.temp_dir.as_deref(),
+            progress,
+        )?;
+        self.control.installed_size = data_archive.installed_size;
+
+        let mut ar = ArBuilder::new(BufWriter::new(writer));
+        ar.append_header(self.mtime)?;
+        ar.append_control(&self.control, self.mtime, self.compression)?;
+        ar.append_data(data_archive, self.mtime)?;
+        let mut writer = ar.into_inner()?;
+        writer.flush()?;
+        writer
+            .into_inner()
+            .map_err(|e| PackageError::Io(e.into_error()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::PackageBuilder;
+    use crate::reader::IpkContents;
+
+    #[test]
+    fn builds_a_package_that_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        fs::create_dir(&app).unwrap();
+        fs::write(
+            app.join("appinfo.json"),
+            r#"{"id":"com.example.app","version":"1.0.0","type":"web","main":"index.html","title":"Example"}"#,
+        )
+        .unwrap();
+        fs::write(app.join("index.html"), "<html></html>").unwrap();
+
+        let mut package = PackageBuilder::new()
+            .app_dir(&app)
+            .control_field("Depends", "libc")
+            .temp_dir(dir.path())
+            .mtime(0)
+            .build()
+            .unwrap();
+        assert_eq!(package.file_name(), "com.example.app_1.0.0_all.ipk");
+        let ipk = package.write_to(Vec::new()).unwrap();
+
+        let contents = IpkContents::read(Cursor::new(ipk)).unwrap();
+        assert_eq!(contents.field("Package"), Some("com.example.app"));
+        assert_eq!(contents.field("Description"), Some("Example"));
+        assert_eq!(contents.field("Depends"), Some("libc"));
+        assert!(
+            contents
+                .files
+                .contains_key("usr/palm/applications/com.example.app/index.html")
+        );
+        assert!(
+            contents
+                .files
+                .contains_key("usr/palm/packages/com.example.app/packageinfo.json")
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::reader::{EntryKind, IpkContents, IpkEntry};
+
+/// What changed between two packages. Directories only count for their modes.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpkDiff {
+    pub control: Vec<FieldChange>,
+    pub added: Vec<FileSummary>,
+    pub removed: Vec<FileSummary>,
+    pub modified: Vec<FileChange>,
+    pub modes: Vec<ModeChange>,
+    /// Changes inside `appinfo.json` and `services.json`.
+    pub metadata: Vec<JsonChange>,
+    pub installed_size: SizeChange,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileSummary {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub old: FileVersion,
+    pub new: FileVersion,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileVersion {
+    pub kind: EntryKind,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModeChange {
+    pub path: String,
+    /// Octal, such as `0755`.
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonChange {
+    pub path: String,
+    /// JSON pointer to the changed value, `""` for the whole document.
+    pub pointer: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// The `Installed-Size` fields, when both packages have one.
+#[derive(Debug, Default, Serialize)]
+pub struct SizeChange {
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+    pub delta: Option<i64>,
+}
+
+impl IpkDiff {
+    #[must_use]
+    pub fn new(old: &IpkContents, new: &IpkContents) -> IpkDiff {
+        let mut diff = IpkDiff::default();
+
+        let names: BTreeSet<String> = old
+            .control
+            .iter()
+            .chain(&new.control)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names {
+            let (old_value, new_value) = (old.field(&name), new.field(&name));
+            if old_value != new_value {
+                diff.control.push(FieldChange {
+                    name,
+                    old: old_value.map(String::from),
+                    new: new_value.map(String::from),
+                });
+            }
+        }
+
+        for (path, old_entry) in &old.files {
+            let Some(new_entry) = new.files.get(path) else {
+                if old_entry.kind != EntryKind::Dir {
+                    diff.removed.push(FileSummary::new(path, old_entry));
+                }
+                continue;
+            };
+            if old_entry.mode != new_entry.mode {
+                diff.modes.push(ModeChange {
+                    path: path.clone(),
+                    old: format!("{:04o}", old_entry.mode),
+                    new: format!("{:04o}", new_entry.mode),
+                });
+            }
+            if old_entry.kind == EntryKind::Dir && new_entry.kind == EntryKind::Dir {
+                continue;
+            }
+            if (&old_entry.kind, &old_entry.sha256, &old_entry.link)
+                != (&new_entry.kind, &new_entry.sha256, &new_entry.link)
+            {
+                diff.modified.push(FileChange {
+                    path: path.clone(),
+                    old: FileVersion::new(old_entry),
+                    new: FileVersion::new(new_entry),
+                });
+                if let (Some(old_data), Some(new_data)) = (&old_entry.data, &new_entry.data) {
+                    diff.metadata.extend(json_changes(path, old_data, new_data));
+                }
+            }
+        }
+        for (path, new_entry) in &new.files {
+            if new_entry.kind != EntryKind::Dir && !old.files.contains_key(path) {
+                diff.added.push(FileSummary::new(path, new_entry));
+            }
+        }
+
+        let size = |contents: &IpkContents| contents.field("Installed-Size")?.parse::<u64>().ok();
+        diff.installed_size.old = size(old);
+        diff.installed_size.new = size(new);
+        if let (Some(old_size), Some(new_size)) = (diff.installed_size.old, diff.installed_size.new)
+        {
+            diff.installed_size.delta = i64::try_from(new_size)
+                .ok()
+                .zip(i64::try_from(old_size).ok())
+                .map(|(n, o)| n - o);
+        }
+        diff
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.control.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.modes.is_empty()
+    }
+}
+
+impl FileSummary {
+    fn new(path: &str, entry: &IpkEntry) -> Self {
+        Self {
+            path: String::from(path),
+            kind: entry.kind.clone(),
+            size: entry.size,
+        }
+    }
+}
+
+impl FileVersion {
+    fn new(entry: &IpkEntry) -> Self {
+        Self {
+            kind: entry.kind.clone(),
+            size: entry.size,
+            sha256: entry.sha256.clone(),
+            link: entry.link.clone(),
+        }
+    }
+}
+
+/// Compare two JSON documents value by value. Documents that don't parse are
+/// compared as a whole.
+fn json_changes(path: &str, old: &[u8], new: &[u8]) -> Vec<JsonChange> {
+    let mut changes = Vec::new();
+    match (
+        serde_json::from_slice::<Value>(old),
+        serde_json::from_slice::<Value>(new),
+    ) {
+        (Ok(old), Ok(new)) => {
+            compare_json(path, String::new(), Some(&old), Some(&new), &mut changes);
+        }
+        _ => changes.push(JsonChange {
+            path: String::from(path),
+            pointer: String::new(),
+            old: Some(Value::String(String::from_utf8_lossy(old).into_owned())),
+            new: Some(Value::String(String::from_utf8_lossy(new).into_owned())),
+        }),
+    }
+    changes
+}
+
+fn compare_json(
+    path: &str,
+    pointer: String,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    changes: &mut Vec<JsonChange>,
+) {
+    if old == new {
+        return;
+    }
+    if let (Some(Value::Object(old)), Some(Value::Object(new))) = (old, new) {
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            let escaped = key.replace('~', "~0").replace('/', "~1");
+            compare_json(
+                path,
+                format!("{pointer}/{escaped}"),
+                old.get(key),
+                new.get(key),
+                changes,
+            );
+        }
+        return;
+    }
+    changes.push(JsonChange {
+        path: String::from(path),
+        pointer,
+        old: old.cloned(),
+        new: new.cloned(),
+    });
+}
+
+impl Display for IpkDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+        if !self.control.is_empty() {
+            writeln!(f, "Control:")?;
+            for field in &self.control {
+                match (&field.old, &field.new) {
+                    (Some(old), Some(new)) => writeln!(f, "  {}: {old} -> {new}", field.name)?,
+                    (None, Some(new)) => writeln!(f, "+ {}: {new}", field.name)?,
+                    (Some(old), None) => writeln!(f, "- {}: {old}", field.name)?,
+                    (None, None) => {}
+                }
+            }
+        }
+        if !self.added.is_empty() || !self.removed.is_empty() || !self.modified.is_empty() {
+            writeln!(f, "Files:")?;
+            for file in &self.added {
+                writeln!(f, "+ {} ({} bytes)", file.path, file.size)?;
+            }
+            for file in &self.removed {
+                writeln!(f, "- {} ({} bytes)", file.path, file.size)?;
+            }
+            for file in &self.modified {
+                if let (Some(old), Some(new)) = (&file.old.link, &file.new.link) {
+                    writeln!(f, "~ {} (-> {old} -> {new})", file.path)?;
+                } else {
+                    writeln!(
+                        f,
+                        "~ {} ({} -> {} bytes)",
+                        file.path, file.old.size, file.new.size
+                    )?;
+                }
+            }
+        }
+        if !self.modes.is_empty() {
+            writeln!(f, "Modes:")?;
+            for mode in &self.modes {
+                writeln!(f, "  {}: {} -> {}", mode.path, mode.old, mode.new)?;
+            }
+        }
+        if !self.metadata.is_empty() {
+            writeln!(f, "Metadata:")?;
+            let show = |value: &Option<Value>| {
+                value
+                    .as_ref()
+                    .map_or_else(|| String::from("(none)"), Value::to_string)
+            };
+            for change in &self.metadata {
+                writeln!(
+                    f,
+                    "  {}#{}: {} -> {}",
+                    change.path,
+                    change.pointer,
+                    show(&change.old),
+                    show(&change.new)
+                )?;
+            }
+        }
+        if let (Some(old), Some(new), Some(delta)) = (
+            self.installed_size.old,
+            self.installed_size.new,
+            self.installed_size.delta,
+        ) {
+            writeln!(f, "Installed-Size: {old} -> {new} ({delta:+})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sha2::Digest as _;
+
+    use super::IpkDiff;
+    use crate::reader::{EntryKind, IpkContents, IpkEntry};
+
+    fn file(content: &[u8], mode: u32) -> IpkEntry {
+        IpkEntry {
+            kind: EntryKind::File,
+            size: content.len() as u64,
+            mode,
+            sha256: Some(format!("{:x}", sha2::Sha256::digest(content))),
+            link: None,
+            data: Some(content.to_vec()),
+        }
+    }
+
+    fn contents(size: &str, files: &[(&str, IpkEntry)]) -> IpkContents {
+        IpkContents {
+            control: vec![
+                (String::from("Package"), String::from("com.example.app")),
+                (String::from("Installed-Size"), String::from(size)),
+            ],
+            files: files
+                .iter()
+                .map(|(path, entry)| (String::from(*path), entry.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn changes_are_sorted_out() {
+        let appinfo = "usr/palm/applications/com.example.app/appinfo.json";
+        let old = contents(
+            "100",
+            &[
+                (
+                    appinfo,
+                    file(br#"{"version": "1.0.0", "title": "App"}"#, 0o644),
+                ),
+                ("usr/palm/old.js", file(b"old", 0o644)),
+                ("usr/palm/run.sh", file(b"run", 0o644)),
+            ],
+        );
+        let new = contents(
+            "164",
+            &[
+                (
+                    appinfo,
+                    file(br#"{"version": "1.1.0", "title": "App"}"#, 0o644),
+                ),
+                ("usr/palm/new.js", file(b"new", 0o644)),
+                ("usr/palm/run.sh", file(b"run", 0o755)),
+            ],
+        );
+
+        let diff = IpkDiff::new(&old, &new);
+        assert_eq!(diff.control.len(), 1);
+        assert_eq!(diff.added[0].path, "usr/palm/new.js");
+        assert_eq!(diff.removed[0].path, "usr/palm/old.js");
+        assert_eq!(diff.modified[0].path, appinfo);
+        assert_eq!(diff.modes[0].new, "0755");
+        assert_eq!(diff.metadata.len(), 1);
+        assert_eq!(diff.metadata[0].pointer, "/version");
+        assert_eq!(diff.metadata[0].new, Some(json!("1.1.0")));
+        assert_eq!(diff.installed_size.delta, Some(64));
+
+        assert!(IpkDiff::new(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt::{Display, Formatter};
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum PackageError {
+    /// An `appinfo.json`, `services.json` or project setting is missing or
+    /// invalid.
+    Input(IoError),
+    /// The architecture can't be worked out, or doesn't match the binaries.
+    Arch(String),
+    /// A control field can't be set.
+    Control(String),
+    /// A file in the app or a service can't go into a package.
+    Entry { path: PathBuf, reason: String },
+    /// Reading the files or writing the package failed.
+    Io(IoError),
+}
+
+impl Display for PackageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageError::Input(e) | PackageError::Io(e) => write!(f, "{e}"),
+            PackageError::Arch(reason) | PackageError::Control(reason) => write!(f, "{reason}"),
+            PackageError::Entry { path, reason } => {
+                write!(f, "{}: {reason}", path.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PackageError {}
+
+impl From<IoError> for PackageError {
+    fn from(value: IoError) -> Self {
+        PackageError::Io(value)
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Result};
+
+use serde::Deserialize;
+
+use crate::ParseFrom;
+
+/// Control file fields read from a `package.json`-style file.
+///
+/// Keys this struct does not know are ignored, so an npm `package.json` works
+/// as is: its `description` and `homepage` land in the control file.
+#[derive(Debug, Default, Deserialize)]
+pub struct ControlFile {
+    pub maintainer: Option<String>,
+    pub description: Option<String>,
+    pub depends: Option<String>,
+    pub source: Option<String>,
+    pub homepage: Option<String>,
+    /// Any other control fields, written as given.
+    #[serde(default)]
+    pub control: BTreeMap<String, String>,
+}
+
+impl ParseFrom for ControlFile {
+    fn parse_from<R: Read>(reader: R) -> Result<ControlFile> {
+        serde_json::from_reader(reader).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid control file: {e:?}"),
+            )
+        })
+    }
+}
@@ -1,11 +1,13 @@
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Result};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use regex::Regex;
 
 use crate::input::app::AppInfo;
+use crate::input::pattern_regex;
+use crate::input::project::ProjectInfo;
 use crate::input::service::ServiceInfo;
 use crate::input::validation::{PackageArch, Validation, ValidationInfo};
 use crate::{PackageInfo, ParseFrom};
@@ -14,61 +16,68 @@ use crate::{PackageInfo, ParseFrom};
 pub struct DataInfo {
     pub package: PackageInfo,
     pub package_data: Vec<u8>,
-    pub app: ComponentInfo<AppInfo>,
+    pub app: Option<ComponentInfo<AppInfo>>,
     pub services: Vec<ComponentInfo<ServiceInfo>>,
     pub excludes: Option<Regex>,
+    /// Files to mark executable whatever mode they have on disk.
+    pub executables: Option<Regex>,
 }
 
 #[derive(Debug)]
 pub struct ComponentInfo<T> {
     pub path: PathBuf,
     pub info: T,
-    pub excludes: Option<Regex>,
 }
 
 impl DataInfo {
-    pub fn from_input<P1, P2, E>(
-        app_dir: P1,
-        service_dirs: &[P2],
-        excludes: &[E],
-    ) -> Result<DataInfo>
-    where
-        P1: AsRef<Path>,
-        P2: AsRef<Path>,
-        E: AsRef<str>,
-    {
-        let app_dir = app_dir.as_ref();
-        let app_info: AppInfo = AppInfo::parse_from(File::open(app_dir.join("appinfo.json"))?)?;
+    /// Read the app and services `project` lists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a component can't be read, or the package has no
+    /// id, version or contents.
+    pub fn from_input(project: &ProjectInfo) -> Result<DataInfo> {
+        let app = match &project.app {
+            Some(app_dir) => Some(ComponentInfo {
+                info: AppInfo::parse_from(File::open(app_dir.join("appinfo.json"))?)?,
+                path: app_dir.clone(),
+            }),
+            None => None,
+        };
         let mut services: Vec<ComponentInfo<ServiceInfo>> = Vec::new();
-        let mut exclude_queries = Vec::<String>::new();
-        for pattern in excludes {
-            let mut pattern = String::from(pattern.as_ref());
-            if pattern.starts_with('.') {
-                pattern = pattern.replacen('.', "^\\.", 1);
-            } else if pattern.starts_with('*') {
-                pattern = pattern.replacen('*', "", 1);
-            }
-            pattern.push('$');
-            exclude_queries.push(pattern);
-        }
-        let mut excludes: Option<Regex> = None;
-        if !exclude_queries.is_empty() {
-            excludes = Some(Regex::new(&format!("(?i){}", exclude_queries.join("|"))).unwrap());
-        }
-        for service_dir in service_dirs {
-            let service_dir = service_dir.as_ref();
+        let excludes = pattern_regex(&project.exclude)?;
+        let executables = pattern_regex(&project.executable)?;
+        for service_dir in &project.services {
             let service_info =
                 ServiceInfo::parse_from(File::open(service_dir.join("services.json"))?)?;
             services.push(ComponentInfo {
-                path: service_dir.to_path_buf(),
+                path: service_dir.clone(),
                 info: service_info,
-                excludes: excludes.clone(),
             });
         }
+        if app.is_none() && services.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Nothing to package: no app or services",
+            ));
+        }
+        let app_info = app.as_ref().map(|app| &app.info);
+        let (Some(id), Some(version)) = (
+            project.id.as_ref().or(app_info.map(|app| &app.id)),
+            project
+                .version
+                .as_ref()
+                .or(app_info.map(|app| &app.version)),
+        ) else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A package without an app needs an id and a version",
+            ));
+        };
         let package_info = PackageInfo {
-            id: app_info.id.clone(),
-            version: app_info.version.clone(),
-            app: app_info.id.clone(),
+            id: id.clone(),
+            version: version.clone(),
+            app: app_info.map(|app| app.id.clone()),
             services: services.iter().map(|info| info.info.id.clone()).collect(),
         };
         let mut package_info_data = serde_json::to_vec_pretty(&package_info)?;
@@ -76,33 +85,28 @@ impl DataInfo {
         Ok(DataInfo {
             package: package_info,
             package_data: package_info_data,
-            app: ComponentInfo {
-                path: app_dir.to_path_buf(),
-                info: app_info,
-                excludes: excludes.clone(),
-            },
+            app,
             services,
             excludes,
+            executables,
         })
     }
 }
 
 impl Validation for DataInfo {
     fn validate(&self, force_arch: bool) -> Result<ValidationInfo> {
-        let app_validation = self.app.validate(force_arch)?;
         let mut archs = HashSet::<PackageArch>::new();
-        let mut size_sum = self.package_data.len() as u64;
-        if let Some(arch) = &app_validation.arch {
-            archs.insert(arch.clone());
+        if let Some(app) = &self.app
+            && let Some(arch) = app.validate(force_arch)?.arch
+        {
+            archs.insert(arch);
         }
-        size_sum += app_validation.size;
 
         for info in &self.services {
             let service_validation = info.validate(force_arch)?;
             if let Some(arch) = &service_validation.arch {
                 archs.insert(arch.clone());
             }
-            size_sum += service_validation.size;
         }
 
         if archs.len() > 1 && !force_arch {
@@ -113,7 +117,6 @@ impl Validation for DataInfo {
         }
         Ok(ValidationInfo {
             arch: archs.iter().next().cloned(),
-            size: size_sum,
         })
     }
 }
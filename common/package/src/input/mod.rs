@@ -0,0 +1,55 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use path_slash::PathExt;
+use regex::Regex;
+use walkdir::DirEntry;
+
+pub mod app;
+pub mod control;
+pub mod data;
+pub mod project;
+pub mod service;
+pub mod validation;
+
+pub(crate) fn filter_by_excludes<P: AsRef<Path>>(
+    base: P,
+    entry: &DirEntry,
+    excludes: Option<&Regex>,
+) -> bool {
+    if let Some(exclude) = excludes {
+        return !exclude.is_match(
+            entry
+                .path()
+                .strip_prefix(base)
+                .unwrap()
+                .to_slash_lossy()
+                .as_ref(),
+        );
+    }
+    true
+}
+
+/// Turn `--app-exclude` style patterns into one case-insensitive regex, matched
+/// against a path relative to the component directory. A leading `.` anchors
+/// at the start of the path, and a leading `*` matches any prefix. Every
+/// pattern is anchored at the end. Returns `None` for no patterns.
+pub(crate) fn pattern_regex<E: AsRef<str>>(patterns: &[E]) -> Result<Option<Regex>> {
+    let mut queries = Vec::<String>::new();
+    for pattern in patterns {
+        let mut pattern = String::from(pattern.as_ref());
+        if pattern.starts_with('.') {
+            pattern = pattern.replacen('.', "^\\.", 1);
+        } else if pattern.starts_with('*') {
+            pattern = pattern.replacen('*', "", 1);
+        }
+        pattern.push('$');
+        queries.push(pattern);
+    }
+    if queries.is_empty() {
+        return Ok(None);
+    }
+    Regex::new(&format!("(?i){}", queries.join("|")))
+        .map(Some)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Bad pattern: {e}")))
+}
@@ -0,0 +1,147 @@
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::ParseFrom;
+use crate::input::validation::PackageArch;
+
+/// The default name of the package file.
+pub const DEFAULT_OUTPUT: &str = "{id}_{version}_{arch}.ipk";
+
+/// What goes into a package. Read from a project file such as
+/// `ares-package.json`, or made up from the command line.
+///
+/// Without `app`, the package holds only services, and `id` and `version` are
+/// required. Relative paths are taken from the project file's directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectInfo {
+    /// Package id. Defaults to the app id.
+    pub id: Option<String>,
+    /// Package version. Defaults to the app version.
+    pub version: Option<String>,
+    pub app: Option<PathBuf>,
+    #[serde(default)]
+    pub services: Vec<PathBuf>,
+    /// Patterns like `--app-exclude`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Patterns like `--executable`.
+    #[serde(default)]
+    pub executable: Vec<String>,
+    /// Pin the architecture, like `--force-arch`.
+    pub arch: Option<String>,
+    /// File name of the package, with `{id}`, `{version}` and `{arch}`
+    /// replaced. Defaults to [`DEFAULT_OUTPUT`].
+    pub output: Option<String>,
+}
+
+impl ProjectInfo {
+    /// Make relative app and service paths relative to `base` instead.
+    pub fn resolve_paths(&mut self, base: &Path) {
+        if let Some(app) = &mut self.app {
+            *app = base.join(&*app);
+        }
+        for service in &mut self.services {
+            *service = base.join(&*service);
+        }
+    }
+
+    /// The architecture the project pins, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] for an unknown architecture.
+    pub fn pinned_arch(&self) -> Result<Option<PackageArch>> {
+        self.arch
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e: String| Error::new(ErrorKind::InvalidInput, e))
+    }
+
+    /// The package file name for this project.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] if the name is empty or has a path
+    /// separator in it.
+    pub fn output_name(&self, id: &str, version: &str, arch: &str) -> Result<String> {
+        let name = self
+            .output
+            .as_deref()
+            .unwrap_or(DEFAULT_OUTPUT)
+            .replace("{id}", id)
+            .replace("{version}", version)
+            .replace("{arch}", arch);
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid output name {name:?}"),
+            ));
+        }
+        Ok(name)
+    }
+}
+
+impl ParseFrom for ProjectInfo {
+    fn parse_from<R: Read>(reader: R) -> Result<ProjectInfo> {
+        serde_json::from_reader(reader).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid project file: {e:?}"),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::ProjectInfo;
+    use crate::ParseFrom;
+
+    #[test]
+    fn services_only_project() {
+        let mut project = ProjectInfo::parse_from(
+            br#"{
+                "id": "com.example.svc",
+                "version": "1.2.0",
+                "services": ["services/a", "/opt/b"],
+                "output": "{id}-{version}.ipk"
+            }"#
+            .as_slice(),
+        )
+        .unwrap();
+        project.resolve_paths(Path::new("/src/project"));
+        assert!(project.app.is_none());
+        assert_eq!(project.services[0], Path::new("/src/project/services/a"));
+        assert_eq!(project.services[1], Path::new("/opt/b"));
+        assert_eq!(
+            project
+                .output_name("com.example.svc", "1.2.0", "arm")
+                .unwrap(),
+            "com.example.svc-1.2.0.ipk"
+        );
+    }
+
+    #[test]
+    fn output_names_stay_in_the_output_dir() {
+        let project = ProjectInfo {
+            output: Some(String::from("../{id}.ipk")),
+            ..ProjectInfo::default()
+        };
+        assert!(project.output_name("a", "1", "all").is_err());
+        assert_eq!(
+            ProjectInfo::default().output_name("a", "1", "all").unwrap(),
+            "a_1_all.ipk"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(ProjectInfo::parse_from(br#"{"service": ["a"]}"#.as_slice()).is_err());
+    }
+}
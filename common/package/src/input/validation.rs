@@ -10,7 +10,6 @@ use elf::to_str::e_machine_to_string;
 
 use crate::input::app::AppInfo;
 use crate::input::data::ComponentInfo;
-use crate::input::dir_size;
 use crate::input::service::ServiceInfo;
 
 #[derive(Eq, Hash, PartialEq, Debug, Clone)]
@@ -22,33 +21,36 @@ pub enum PackageArch {
 
 pub struct ValidationInfo {
     pub arch: Option<PackageArch>,
-    pub size: u64,
 }
 
 pub trait Validation {
+    /// Work out the architecture from the component's native binary, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the binary can't be read, or its machine type is
+    /// unknown and `force_arch` isn't set.
     fn validate(&self, force_arch: bool) -> Result<ValidationInfo>;
 }
 
 impl Validation for ComponentInfo<AppInfo> {
     fn validate(&self, force_arch: bool) -> Result<ValidationInfo> {
-        let size = dir_size(&self.path, self.excludes.as_ref())?;
         let mut arch: Option<PackageArch> = None;
         if self.info.r#type == "native" {
             arch = infer_arch(self.path.join(&self.info.main), force_arch)?;
         }
-        Ok(ValidationInfo { arch, size })
+        Ok(ValidationInfo { arch })
     }
 }
 
 impl Validation for ComponentInfo<ServiceInfo> {
     fn validate(&self, force_arch: bool) -> Result<ValidationInfo> {
-        let size = dir_size(&self.path, self.excludes.as_ref())?;
         let mut arch: Option<PackageArch> = None;
         if let (Some(engine), Some(executable)) = (&self.info.engine, &self.info.executable)
             && engine == "native" {
                 arch = infer_arch(self.path.join(executable), force_arch)?;
             }
-        Ok(ValidationInfo { arch, size })
+        Ok(ValidationInfo { arch })
     }
 }
 
@@ -0,0 +1,30 @@
+use std::io::Read;
+
+use serde::Serialize;
+
+pub mod builder;
+pub mod diff;
+pub mod error;
+pub mod input;
+pub mod manifest;
+pub mod packaging;
+pub mod reader;
+
+/// The `packageinfo.json` written into every package.
+#[derive(Debug, Serialize)]
+pub struct PackageInfo {
+    pub id: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<String>,
+}
+
+pub trait ParseFrom: Sized {
+    /// # Errors
+    ///
+    /// Returns [`std::io::ErrorKind::InvalidData`] if `reader` doesn't hold a
+    /// valid document.
+    fn parse_from<R: Read>(reader: R) -> std::io::Result<Self>;
+}
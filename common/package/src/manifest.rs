@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::input::app::AppInfo;
+use crate::input::data::DataInfo;
+use crate::packaging::control::ControlInfo;
+
+/// A per-app manifest for a webosbrew repository, as read by Homebrew Channel.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepoManifest {
+    pub id: String,
+    pub version: String,
+    pub r#type: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_description: Option<String>,
+    pub icon_uri: String,
+    pub source_url: String,
+    pub ipk_url: String,
+    pub ipk_hash: IpkHash,
+    pub ipk_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IpkHash {
+    pub sha256: String,
+}
+
+/// Where the manifest points: the app icon, the project and the ipk itself.
+pub struct ManifestLinks {
+    pub icon_uri: String,
+    pub source_url: String,
+    pub ipk_url: String,
+}
+
+impl ManifestLinks {
+    /// Fill in the links before packaging, so a missing one fails fast.
+    /// `sourceUrl` is the control file's `Source`, or else its `Homepage`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] if there is no icon or source URL,
+    /// or the package has no app to describe.
+    pub fn new(
+        data: &DataInfo,
+        icon_uri: Option<&str>,
+        ipk_url: String,
+        control: &ControlInfo,
+    ) -> Result<Self> {
+        manifest_app(data)?;
+        let icon_uri = icon_uri
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "A manifest needs an --icon-uri"))?;
+        let source_url = control
+            .source
+            .as_deref()
+            .or(control.homepage.as_deref())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "A manifest needs a --source or --homepage",
+                )
+            })?;
+        Ok(Self {
+            icon_uri: String::from(icon_uri),
+            source_url: String::from(source_url),
+            ipk_url,
+        })
+    }
+}
+
+impl RepoManifest {
+    /// Describe the finished package at `ipk`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the package can't be read.
+    pub fn new(
+        data: &DataInfo,
+        control: &ControlInfo,
+        links: ManifestLinks,
+        ipk: &Path,
+    ) -> Result<Self> {
+        let app = manifest_app(data)?;
+        let ipk_size = ipk.metadata()?.len();
+        let sha256 = sha256::try_digest(ipk).map_err(|e| {
+            Error::other(format!(
+                "Failed to generate checksum for {}: {e:?}",
+                ipk.to_string_lossy()
+            ))
+        })?;
+        Ok(Self {
+            id: data.package.id.clone(),
+            version: data.package.version.clone(),
+            r#type: app.r#type.clone(),
+            title: app.title.clone(),
+            app_description: Some(control.description.clone())
+                .filter(|description| description != &app.title),
+            icon_uri: links.icon_uri,
+            source_url: links.source_url,
+            ipk_url: links.ipk_url,
+            ipk_hash: IpkHash { sha256 },
+            ipk_size,
+        })
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if `path` can't be written.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+}
+
+fn manifest_app(data: &DataInfo) -> Result<&AppInfo> {
+    data.app.as_ref().map(|app| &app.info).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "A manifest describes an app, and this package has none",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{IpkHash, RepoManifest};
+
+    #[test]
+    fn manifest_uses_repository_keys() {
+        let manifest = RepoManifest {
+            id: String::from("com.example.app"),
+            version: String::from("1.0.0"),
+            r#type: String::from("web"),
+            title: String::from("Example"),
+            app_description: None,
+            icon_uri: String::from("https://example.com/icon.png"),
+            source_url: String::from("https://github.com/example/app"),
+            ipk_url: String::from("com.example.app_1.0.0_all.ipk"),
+            ipk_hash: IpkHash {
+                sha256: String::from("00"),
+            },
+            ipk_size: 1024,
+        };
+        assert_eq!(
+            serde_json::to_value(&manifest).unwrap(),
+            json!({
+                "id": "com.example.app",
+                "version": "1.0.0",
+                "type": "web",
+                "title": "Example",
+                "iconUri": "https://example.com/icon.png",
+                "sourceUrl": "https://github.com/example/app",
+                "ipkUrl": "com.example.app_1.0.0_all.ipk",
+                "ipkHash": { "sha256": "00" },
+                "ipkSize": 1024
+            })
+        );
+    }
+}
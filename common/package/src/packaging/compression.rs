@@ -0,0 +1,246 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use flate2::read::MultiGzDecoder;
+use gzp::deflate::Gzip;
+use gzp::{ZBuilder, ZWriter};
+use xz2::read::XzDecoder;
+use xz2::stream::{Check, MtStreamBuilder};
+use xz2::write::XzEncoder;
+
+/// How the tarballs inside an ipk are compressed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Codec {
+    #[default]
+    Gzip,
+    Xz,
+    None,
+}
+
+/// A codec and its level. `level` is `None` for the codec's own default.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Compression {
+    pub codec: Codec,
+    pub level: Option<u32>,
+}
+
+/// A compressing writer that hands back the inner writer once the stream is
+/// complete. Dropping one without [`Finish::finish`] leaves the stream cut off.
+pub trait Finish<W>: Write {
+    /// # Errors
+    ///
+    /// Returns an error if the end of the stream can't be written.
+    fn finish(self: Box<Self>) -> Result<W>;
+}
+
+impl Compression {
+    /// The highest level every codec takes.
+    pub const MAX_LEVEL: u32 = 9;
+
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] when a level is given for
+    /// [`Codec::None`], or is above [`Compression::MAX_LEVEL`].
+    pub fn new(codec: Codec, level: Option<u32>) -> Result<Self> {
+        match level {
+            Some(_) if codec == Codec::None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Uncompressed packages have no level",
+            )),
+            Some(level) if level > Self::MAX_LEVEL => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Compression level {level} is above {}", Self::MAX_LEVEL),
+            )),
+            _ => Ok(Self { codec, level }),
+        }
+    }
+
+    /// The ar member name for the tarball `base`, such as `data.tar.xz`. opkg
+    /// picks the decompressor from this name.
+    #[must_use]
+    pub fn member_name(&self, base: &str) -> Vec<u8> {
+        let suffix = match self.codec {
+            Codec::Gzip => ".tar.gz",
+            Codec::Xz => ".tar.xz",
+            Codec::None => ".tar",
+        };
+        format!("{base}{suffix}").into_bytes()
+    }
+
+    /// Wrap `writer` in this codec's encoder. Both codecs spread the work over
+    /// every CPU core.
+    #[must_use]
+    pub fn writer<W: Write + Send + 'static>(&self, writer: W) -> Box<dyn Finish<W>> {
+        match self.codec {
+            Codec::Gzip => Box::new(GzipWriter(
+                ZBuilder::<Gzip, _>::new()
+                    .compression_level(flate2::Compression::new(self.level.unwrap_or(6)))
+                    .from_writer(writer),
+            )),
+            Codec::Xz => {
+                // CRC32, because the xz decoder in busybox may be built without
+                // CRC64 support.
+                let stream = MtStreamBuilder::new()
+                    .threads(
+                        std::thread::available_parallelism()
+                            .map_or(1, |n| u32::try_from(n.get()).unwrap_or(1)),
+                    )
+                    .preset(self.level.unwrap_or(6))
+                    .check(Check::Crc32)
+                    .encoder();
+                match stream {
+                    Ok(stream) => Box::new(XzEncoder::new_stream(writer, stream)),
+                    // Only an out-of-memory liblzma gets here. A single thread
+                    // needs far less.
+                    Err(_) => Box::new(XzEncoder::new(writer, self.level.unwrap_or(6))),
+                }
+            }
+            Codec::None => Box::new(Plain(writer)),
+        }
+    }
+}
+
+impl Codec {
+    /// Split an ar member name such as `data.tar.xz` into its base name and
+    /// codec. Returns `None` for members that aren't tarballs.
+    #[must_use]
+    pub fn from_member_name(name: &str) -> Option<(&str, Codec)> {
+        [
+            (".tar.gz", Codec::Gzip),
+            (".tar.xz", Codec::Xz),
+            (".tar", Codec::None),
+        ]
+        .into_iter()
+        .find_map(|(suffix, codec)| Some((name.strip_suffix(suffix)?, codec)))
+    }
+
+    /// Wrap `reader` in this codec's decoder.
+    pub fn reader<'a, R: Read + 'a>(self, reader: R) -> Box<dyn Read + 'a> {
+        match self {
+            Codec::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Codec::Xz => Box::new(XzDecoder::new(reader)),
+            Codec::None => Box::new(reader),
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::Gzip => write!(f, "gzip"),
+            Codec::Xz => write!(f, "xz"),
+            Codec::None => write!(f, "none"),
+        }
+    }
+}
+
+struct GzipWriter<W>(Box<dyn ZWriter<W>>);
+
+impl<W> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W> Finish<W> for GzipWriter<W> {
+    fn finish(mut self: Box<Self>) -> Result<W> {
+        self.0.finish().map_err(Error::other)
+    }
+}
+
+impl<W: Write> Finish<W> for XzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<W> {
+        XzEncoder::finish(*self)
+    }
+}
+
+struct Plain<W>(W);
+
+impl<W: Write> Write for Plain<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Finish<W> for Plain<W> {
+    fn finish(mut self: Box<Self>) -> Result<W> {
+        self.0.flush()?;
+        Ok(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::read::GzDecoder;
+    use xz2::read::XzDecoder;
+
+    use super::{Codec, Compression};
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let mut writer = compression.writer(Vec::new());
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        let data = b"usr/palm/applications/com.example.app/index.html".repeat(1000);
+
+        let mut out = Vec::new();
+        let gz = compress(Compression::new(Codec::Gzip, Some(9)).unwrap(), &data);
+        GzDecoder::new(&*gz).read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        out.clear();
+        let xz = compress(Compression::new(Codec::Xz, Some(0)).unwrap(), &data);
+        XzDecoder::new(&*xz).read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        // Even nothing gets a gzip header and trailer.
+        assert!(!compress(Compression::default(), b"").is_empty());
+        assert_eq!(
+            compress(Compression::new(Codec::None, None).unwrap(), &data),
+            data
+        );
+    }
+
+    #[test]
+    fn member_names_follow_the_codec() {
+        let name = |codec| Compression::new(codec, None).unwrap().member_name("data");
+        assert_eq!(name(Codec::Gzip), b"data.tar.gz");
+        assert_eq!(name(Codec::Xz), b"data.tar.xz");
+        assert_eq!(name(Codec::None), b"data.tar");
+    }
+
+    #[test]
+    fn member_names_parse_back() {
+        for codec in [Codec::Gzip, Codec::Xz, Codec::None] {
+            let name = Compression::new(codec, None)
+                .unwrap()
+                .member_name("control");
+            assert_eq!(
+                Codec::from_member_name(std::str::from_utf8(&name).unwrap()),
+                Some(("control", codec))
+            );
+        }
+        assert_eq!(Codec::from_member_name("debian-binary"), None);
+    }
+
+    #[test]
+    fn levels_are_checked() {
+        assert!(Compression::new(Codec::Gzip, Some(10)).is_err());
+        assert!(Compression::new(Codec::None, Some(1)).is_err());
+        assert!(Compression::new(Codec::Xz, Some(9)).is_ok());
+    }
+}
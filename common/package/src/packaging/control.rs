@@ -0,0 +1,292 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Cursor, Error, ErrorKind, Write as IoWrite};
+
+use ar::{Builder as ArBuilder, Header as ArHeader};
+use tar::{Builder as TarBuilder, Header as TarHeader};
+
+use crate::packaging::compression::Compression;
+
+/// The version written as `webOS-Packager-Version`.
+pub const PACKAGER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Fields the packager always writes itself. Setting one of them by hand would
+/// make the control file disagree with the package.
+const GENERATED_FIELDS: [&str; 6] = [
+    "Package",
+    "Version",
+    "Architecture",
+    "Installed-Size",
+    "webOS-Package-Format-Version",
+    "webOS-Packager-Version",
+];
+
+pub struct ControlInfo {
+    pub package: String,
+    pub version: String,
+    pub installed_size: u64,
+    pub architecture: String,
+    pub section: String,
+    pub priority: String,
+    pub maintainer: String,
+    pub description: String,
+    pub depends: Option<String>,
+    pub source: Option<String>,
+    pub homepage: Option<String>,
+    /// Fields with no meaning to the packager, written in order after the rest.
+    pub extra: Vec<(String, String)>,
+}
+
+pub trait AppendControl {
+    /// Append the control tarball, holding the `control` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the archive fails.
+    fn append_control(
+        &mut self,
+        info: &ControlInfo,
+        mtime: u64,
+        compression: Compression,
+    ) -> std::io::Result<()>;
+}
+
+impl<W> AppendControl for ArBuilder<W>
+where
+    W: IoWrite,
+{
+    fn append_control(
+        &mut self,
+        info: &ControlInfo,
+        mtime: u64,
+        compression: Compression,
+    ) -> std::io::Result<()> {
+        let control = info.to_string().into_bytes();
+
+        let mut tar = TarBuilder::new(compression.writer(Vec::<u8>::new()));
+
+        let mut tar_header = TarHeader::new_gnu();
+        tar_header.set_mode(0o100_644);
+        tar_header.set_size(control.len() as u64);
+        tar_header.set_mtime(mtime);
+        tar_header.set_cksum();
+        tar.append_data(&mut tar_header, "control", &*control)?;
+        let control_tar = tar.into_inner()?.finish()?;
+
+        let mut ar_header =
+            ArHeader::new(compression.member_name("control"), control_tar.len() as u64);
+        ar_header.set_mode(0o100_644);
+        ar_header.set_mtime(mtime);
+        self.append(&ar_header, Cursor::new(control_tar))
+    }
+}
+
+impl ControlInfo {
+    /// A control file for `package`, with the fields opkg needs filled in from
+    /// the defaults ares-cli writes. Change them with [`ControlInfo::set`].
+    #[must_use]
+    pub fn new(
+        package: String,
+        version: String,
+        architecture: String,
+        installed_size: u64,
+    ) -> Self {
+        Self {
+            package,
+            version,
+            installed_size,
+            architecture,
+            section: String::from("misc"),
+            priority: String::from("optional"),
+            maintainer: String::from("N/A <nobody@example.com>"),
+            description: String::from("This is a webOS application."),
+            depends: None,
+            source: None,
+            homepage: None,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Set the field `name` to `value`. Names are matched without regard to
+    /// case, the way opkg reads them. A name the packager does not know is kept
+    /// as an extra field, and setting it again replaces the value.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidInput`] for a field the packager generates,
+    /// a name that is not a valid field name, or a value spanning lines.
+    pub fn set(&mut self, name: &str, value: &str) -> std::io::Result<()> {
+        if !is_field_name(name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid control field name {name:?}"),
+            ));
+        }
+        if GENERATED_FIELDS
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(name))
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Control field {name} is set by the packager"),
+            ));
+        }
+        let value = value.trim();
+        if value.is_empty() || value.contains(['\n', '\r']) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Control field {name} needs a value on one line"),
+            ));
+        }
+        let value = value.to_string();
+        match name.to_ascii_lowercase().as_str() {
+            "section" => self.section = value,
+            "priority" => self.priority = value,
+            "maintainer" => self.maintainer = value,
+            "description" => self.description = value,
+            "depends" => self.depends = Some(value),
+            "source" => self.source = Some(value),
+            "homepage" => self.homepage = Some(value),
+            _ => {
+                if let Some(field) = self
+                    .extra
+                    .iter_mut()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                {
+                    field.1 = value;
+                } else {
+                    self.extra.push((name.to_string(), value));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the fields of a control file in order. A line starting with a space
+/// or tab continues the field before it.
+#[must_use]
+pub fn parse_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    fields
+}
+
+/// A field name as Debian defines it: printable ASCII with no spaces or
+/// colons, not starting with `#` or `-`.
+fn is_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['#', '-'])
+        && name.chars().all(|c| c.is_ascii_graphic() && c != ':')
+}
+
+impl Display for ControlInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Package: {}\n", self.package))?;
+        f.write_fmt(format_args!("Version: {}\n", self.version))?;
+        f.write_fmt(format_args!("Section: {}\n", self.section))?;
+        f.write_fmt(format_args!("Priority: {}\n", self.priority))?;
+        f.write_fmt(format_args!("Architecture: {}\n", self.architecture))?;
+        f.write_fmt(format_args!("Installed-Size: {}\n", self.installed_size))?;
+        f.write_fmt(format_args!("Maintainer: {}\n", self.maintainer))?;
+        f.write_fmt(format_args!("Description: {}\n", self.description))?;
+        if let Some(depends) = &self.depends {
+            f.write_fmt(format_args!("Depends: {depends}\n"))?;
+        }
+        if let Some(source) = &self.source {
+            f.write_fmt(format_args!("Source: {source}\n"))?;
+        }
+        if let Some(homepage) = &self.homepage {
+            f.write_fmt(format_args!("Homepage: {homepage}\n"))?;
+        }
+        for (name, value) in &self.extra {
+            f.write_fmt(format_args!("{name}: {value}\n"))?;
+        }
+        f.write_fmt(format_args!("webOS-Package-Format-Version: {}\n", 2))?;
+        f.write_fmt(format_args!("webOS-Packager-Version: {PACKAGER_VERSION}\n"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlInfo, PACKAGER_VERSION, parse_fields};
+
+    fn control() -> ControlInfo {
+        ControlInfo::new(
+            String::from("com.example.app"),
+            String::from("1.0.0"),
+            String::from("all"),
+            1024,
+        )
+    }
+
+    #[test]
+    fn known_fields_replace_the_defaults() {
+        let mut info = control();
+        info.set("Maintainer", "Example <dev@example.com>").unwrap();
+        info.set("description", "Example App").unwrap();
+        info.set("HOMEPAGE", "https://example.com").unwrap();
+
+        let text = info.to_string();
+        assert!(
+            text.contains("Maintainer: Example <dev@example.com>\n"),
+            "{text}"
+        );
+        assert!(text.contains("Description: Example App\n"), "{text}");
+        assert!(text.contains("Homepage: https://example.com\n"), "{text}");
+        assert!(!text.contains("nobody@example.com"), "{text}");
+        assert!(
+            text.ends_with(&format!("webOS-Packager-Version: {PACKAGER_VERSION}\n")),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn unknown_fields_pass_through_once() {
+        let mut info = control();
+        info.set("X-Channel", "beta").unwrap();
+        info.set("x-channel", "stable").unwrap();
+
+        let text = info.to_string();
+        assert!(text.contains("X-Channel: stable\n"), "{text}");
+        assert!(!text.contains("beta"), "{text}");
+        // Optional fields stay out until somebody sets them.
+        assert!(!text.contains("Depends:"), "{text}");
+    }
+
+    #[test]
+    fn generated_and_malformed_fields_are_refused() {
+        let mut info = control();
+        assert!(info.set("Version", "2.0.0").is_err());
+        assert!(info.set("installed-size", "1").is_err());
+        assert!(info.set("Bad Name", "x").is_err());
+        assert!(info.set("-Flag", "x").is_err());
+        assert!(info.set("Depends", "a\nPackage: evil").is_err());
+        assert!(info.set("Depends", " ").is_err());
+    }
+
+    #[test]
+    fn fields_parse_back() {
+        let mut info = control();
+        info.set("X-Channel", "beta").unwrap();
+        let fields = parse_fields(&format!("{info}Notes: one\n two\n"));
+        assert_eq!(
+            fields[0],
+            (String::from("Package"), String::from("com.example.app"))
+        );
+        assert!(fields.contains(&(String::from("X-Channel"), String::from("beta"))));
+        assert_eq!(
+            fields.last().unwrap(),
+            &(String::from("Notes"), String::from("one\ntwo"))
+        );
+    }
+}
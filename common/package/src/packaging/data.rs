@@ -0,0 +1,402 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::{Error as IoError, Seek, SeekFrom, Write as IoWrite, Write};
+use std::path::{Component, Path, PathBuf};
+
+use ar::{Builder as ArBuilder, Header as ArHeader};
+use path_slash::PathExt as _;
+use tar::{Builder as TarBuilder, EntryType, Header as TarHeader};
+use walkdir::WalkDir;
+
+use crate::PackageInfo;
+use crate::error::PackageError;
+use crate::input::data::DataInfo;
+use crate::input::filter_by_excludes;
+use crate::packaging::compression::Compression;
+use crate::reader::EntryKind;
+
+/// An entry just added to the data tarball, for progress reports.
+#[derive(Debug)]
+pub struct AddedEntry<'a> {
+    /// Path in the package. Directories end with `/`.
+    pub path: &'a str,
+    pub kind: EntryKind,
+    /// Where a symlink points.
+    pub link: Option<&'a Path>,
+}
+
+/// A finished `data.tar`, compressed and waiting in a temporary file to go into the ipk.
+///
+/// The control file needs the installed size, and the control member comes
+/// before the data member in the ipk. Writing the data to a file first gets the
+/// size in the same pass that compresses the files, without keeping the
+/// archive in memory.
+pub struct DataArchive {
+    file: File,
+    compression: Compression,
+    /// Bytes the files take once installed. Directories count for nothing.
+    pub installed_size: u64,
+}
+
+impl DataArchive {
+    /// Compress the package contents into a temporary file in `temp_dir`, or
+    /// the system temporary directory. `progress` hears of every entry added.
+    ///
+    /// Pass the output directory as `temp_dir`. The system temporary
+    /// directory is often a tmpfs, which would put the archive back in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PackageError::Entry`] for a file that can't go into a
+    /// package, and [`PackageError::Io`] if a file can't be read or the
+    /// temporary file can't be written.
+    pub fn build(
+        details: &DataInfo,
+        mtime: u64,
+        compression: Compression,
+        temp_dir: Option<&Path>,
+        progress: &mut dyn FnMut(&AddedEntry),
+    ) -> Result<DataArchive, PackageError> {
+        let info = &details.package;
+        let file = match temp_dir {
+            Some(dir) => tempfile::tempfile_in(dir)?,
+            None => tempfile::tempfile()?,
+        };
+        let mut writer = DataWriter {
+            tar: TarBuilder::new(compression.writer(file)),
+            dir_entries: HashSet::new(),
+            mtime,
+            installed_size: 0,
+            progress,
+        };
+
+        if let Some(app) = &details.app {
+            writer.append_tree(
+                format!("usr/palm/applications/{}/", app.info.id),
+                &app.path,
+                details,
+                (app.info.r#type == "native").then_some(app.info.main.as_str()),
+            )?;
+        }
+        for service in &details.services {
+            let native = service.info.engine.as_deref() == Some("native");
+            writer.append_tree(
+                format!("usr/palm/services/{}/", service.info.id),
+                &service.path,
+                details,
+                service.info.executable.as_deref().filter(|_| native),
+            )?;
+        }
+        writer.append_package_info(info, details)?;
+
+        let installed_size = writer.installed_size;
+        let mut file = writer.tar.into_inner()?.finish()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(DataArchive {
+            file,
+            compression,
+            installed_size,
+        })
+    }
+}
+
+pub trait AppendData {
+    /// Append the data tarball built by [`DataArchive::build`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the archive fails.
+    fn append_data(&mut self, archive: DataArchive, mtime: u64) -> Result<(), IoError>;
+}
+
+impl<W> AppendData for ArBuilder<W>
+where
+    W: IoWrite,
+{
+    fn append_data(&mut self, archive: DataArchive, mtime: u64) -> Result<(), IoError> {
+        let size = archive.file.metadata()?.len();
+        let mut ar_header = ArHeader::new(archive.compression.member_name("data"), size);
+        ar_header.set_mode(0o100_644);
+        ar_header.set_mtime(mtime);
+        self.append(&ar_header, archive.file)
+    }
+}
+
+/// The tar stream of the data member, with what it has written so far.
+struct DataWriter<'p, W: Write> {
+    tar: TarBuilder<W>,
+    dir_entries: HashSet<PathBuf>,
+    mtime: u64,
+    installed_size: u64,
+    progress: &'p mut dyn FnMut(&AddedEntry),
+}
+
+impl<W: Write> DataWriter<'_, W> {
+    fn append_dirs<P>(&mut self, path: P) -> Result<(), IoError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut stack = Vec::new();
+        let empty = Vec::<u8>::new();
+        let mut p = path.as_ref();
+        while p != Path::new("") {
+            if self.dir_entries.contains(p) {
+                break;
+            }
+            stack.insert(0, p);
+            self.dir_entries.insert(p.to_path_buf());
+            if let Some(parent) = p.parent() {
+                p = parent;
+            }
+        }
+        for p in stack {
+            let mut header = TarHeader::new_gnu();
+            let mut dir = String::from(p.to_slash_lossy());
+            if !dir.ends_with('/') {
+                dir.push('/');
+            }
+            header.set_entry_type(EntryType::Directory);
+            header.set_mode(0o100_775);
+            header.set_size(0);
+            header.set_uid(0);
+            header.set_gid(5000);
+            header.set_mtime(self.mtime);
+            header.set_cksum();
+            self.tar.append_data(&mut header, &dir, &*empty)?;
+            (self.progress)(&AddedEntry {
+                path: &dir,
+                kind: EntryKind::Dir,
+                link: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add everything under `path`. `main` is the component's native
+    /// executable, which is marked executable whatever its mode on disk.
+    fn append_tree<S, P>(
+        &mut self,
+        prefix: S,
+        path: P,
+        details: &DataInfo,
+        main: Option<&str>,
+    ) -> Result<(), PackageError>
+    where
+        S: AsRef<str>,
+        P: AsRef<Path>,
+    {
+        let base_path = path.as_ref();
+        let main = main.map(normalize);
+        let excludes = details.excludes.as_ref();
+        let walker = WalkDir::new(base_path)
+            .follow_links(false)
+            .contents_first(false)
+            .sort_by_file_name();
+        for entry in walker
+            .into_iter()
+            .filter_entry(|entry| filter_by_excludes(base_path, entry, excludes))
+        {
+            let entry = entry.map_err(IoError::from)?;
+            let entry_type = entry.file_type();
+            let entry_metadata = entry.metadata().map_err(IoError::from)?;
+            let entry_path = entry.path();
+            let rel_path = entry_path.strip_prefix(base_path).unwrap();
+            let tar_path = tar_path(&prefix, rel_path);
+            if entry_type.is_dir() {
+                self.append_dirs(&tar_path)?;
+                continue;
+            }
+            if entry_type.is_symlink() {
+                let link_target = fs::read_link(entry_path)?;
+                if !link_in_root(rel_path, &link_target) {
+                    return Err(PackageError::Entry {
+                        path: entry_path.to_path_buf(),
+                        reason: format!(
+                            "symlink to {} points outside {}",
+                            link_target.to_string_lossy(),
+                            base_path.to_string_lossy()
+                        ),
+                    });
+                }
+                if let Some(parent) = tar_path.parent() {
+                    self.append_dirs(parent)?;
+                }
+                let mut header = TarHeader::new_gnu();
+                header.set_metadata(&entry_metadata);
+                header.set_entry_type(EntryType::Symlink);
+                header.set_mode(0o120_777);
+                header.set_size(0);
+                header.set_uid(0);
+                header.set_gid(5000);
+                self.tar.append_link(&mut header, &tar_path, &link_target)?;
+                (self.progress)(&AddedEntry {
+                    path: &tar_path.to_string_lossy(),
+                    kind: EntryKind::Symlink,
+                    link: Some(&link_target),
+                });
+            } else if entry_type.is_file() {
+                if hard_links(&entry_metadata) > 1 {
+                    return Err(PackageError::Entry {
+                        path: entry_path.to_path_buf(),
+                        reason: String::from("hard linked, use a copy or a symlink instead"),
+                    });
+                }
+                let executable = main.as_deref() == Some(rel_path)
+                    || details
+                        .executables
+                        .as_ref()
+                        .is_some_and(|re| re.is_match(&rel_path.to_slash_lossy()));
+                if let Some(parent) = tar_path.parent() {
+                    self.append_dirs(parent)?;
+                }
+                let mut header = TarHeader::new_gnu();
+                header.set_metadata(&entry_metadata);
+                header.set_mode(file_mode(disk_mode(&entry_metadata), executable));
+                header.set_uid(0);
+                header.set_gid(5000);
+                header.set_cksum();
+                self.tar
+                    .append_data(&mut header, &tar_path, &mut File::open(entry_path)?)?;
+                self.installed_size += entry_metadata.len();
+                (self.progress)(&AddedEntry {
+                    path: &tar_path.to_string_lossy(),
+                    kind: EntryKind::File,
+                    link: None,
+                });
+            } else {
+                return Err(PackageError::Entry {
+                    path: entry_path.to_path_buf(),
+                    reason: String::from("not a file, directory or symlink"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn append_package_info(
+        &mut self,
+        info: &PackageInfo,
+        details: &DataInfo,
+    ) -> Result<(), IoError> {
+        let package_dir = format!("usr/palm/packages/{}/", info.id);
+        self.append_dirs(&package_dir)?;
+        let mut header = TarHeader::new_gnu();
+        let pkg_info_path = format!("usr/palm/packages/{}/packageinfo.json", info.id);
+        header.set_mode(0o100_644);
+        header.set_size(details.package_data.len() as u64);
+        header.set_mtime(self.mtime);
+        header.set_uid(0);
+        header.set_gid(5000);
+        header.set_cksum();
+        self.tar
+            .append_data(&mut header, &pkg_info_path, &*details.package_data)?;
+        self.installed_size += details.package_data.len() as u64;
+        (self.progress)(&AddedEntry {
+            path: &pkg_info_path,
+            kind: EntryKind::File,
+            link: None,
+        });
+        Ok(())
+    }
+}
+
+fn tar_path<S, P>(prefix: S, path: P) -> PathBuf
+where
+    S: AsRef<str>,
+    P: AsRef<Path>,
+{
+    PathBuf::from(format!(
+        "{}{}",
+        prefix.as_ref(),
+        path.as_ref().to_slash_lossy()
+    ))
+}
+
+/// `path` without `.` components, so `./bin/main` matches `bin/main`.
+fn normalize(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// Whether a symlink at `path`, relative to the component directory, points
+/// at something inside that directory. This only looks at the path, so the
+/// target doesn't have to exist yet.
+fn link_in_root(path: &Path, target: &Path) -> bool {
+    let mut depth = path.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// The tar mode of a regular file: its permission bits, without setuid and
+/// friends, plus `+x` for everyone if `executable`.
+fn file_mode(disk_mode: u32, executable: bool) -> u32 {
+    let mut mode = disk_mode & 0o777;
+    if executable {
+        mode |= 0o111;
+    }
+    0o100_000 | mode
+}
+
+#[cfg(unix)]
+fn disk_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+/// Systems without Unix modes get `0644`, so only forced files are executable.
+#[cfg(not(unix))]
+fn disk_mode(_metadata: &fs::Metadata) -> u32 {
+    0o644
+}
+
+#[cfg(unix)]
+fn hard_links(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn hard_links(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{file_mode, link_in_root, normalize};
+
+    #[test]
+    fn links_must_stay_in_the_component() {
+        let link = |path, target| link_in_root(Path::new(path), Path::new(target));
+        assert!(link("lib/libfoo.so", "libfoo.so.1"));
+        assert!(link("lib/libfoo.so", "../lib/./libfoo.so.1"));
+        assert!(link("current", "versions/1.0"));
+        assert!(!link("current", "../other"));
+        assert!(!link("lib/libfoo.so", "../../libfoo.so"));
+        assert!(!link("lib/libfoo.so", "/usr/lib/libfoo.so"));
+    }
+
+    #[test]
+    fn modes_keep_permissions_only() {
+        assert_eq!(file_mode(0o104_755, false), 0o100_755);
+        assert_eq!(file_mode(0o100_600, true), 0o100_711);
+        assert_eq!(file_mode(0o644, true), 0o100_755);
+    }
+
+    #[test]
+    fn main_paths_ignore_dots() {
+        assert_eq!(normalize("./bin/main"), Path::new("bin/main"));
+        assert_eq!(normalize("main"), Path::new("main"));
+    }
+}
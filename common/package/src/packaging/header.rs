@@ -3,6 +3,11 @@ use std::io::{Result, Write as IoWrite};
 use ar::{Builder as ArBuilder, Header};
 
 pub trait AppendHeader {
+    /// Append `debian-binary`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the archive fails.
     fn append_header(&mut self, mtime: u64) -> Result<()>;
 }
 
@@ -1,3 +1,4 @@
+pub mod compression;
 pub mod control;
 pub mod data;
 pub mod header;
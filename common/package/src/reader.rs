@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::io::{self, Error, ErrorKind, Read, Result};
+
+use ar::Archive as ArArchive;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tar::{Archive as TarArchive, EntryType};
+
+use crate::packaging::compression::Codec;
+use crate::packaging::control::parse_fields;
+
+/// Metadata files kept whole, so a diff can compare their contents.
+const METADATA_FILES: [&str; 2] = ["appinfo.json", "services.json"];
+
+/// What an ipk holds: its control fields and its files, read back from the
+/// ar archive the packager writes.
+#[derive(Debug, Default)]
+pub struct IpkContents {
+    pub control: Vec<(String, String)>,
+    /// Entries of the data tarball by path, without `./` or a trailing `/`.
+    pub files: BTreeMap<String, IpkEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpkEntry {
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mode: u32,
+    /// Lowercase hex sha256 of a file's contents.
+    pub sha256: Option<String>,
+    /// Where a symlink points.
+    pub link: Option<String>,
+    /// The contents of `appinfo.json` and `services.json`.
+    pub data: Option<Vec<u8>>,
+}
+
+impl IpkContents {
+    /// Read a whole ipk. Members may be compressed with any [`Codec`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidData`] if `reader` isn't an ipk, or any
+    /// error reading it.
+    pub fn read<R: Read>(reader: R) -> Result<IpkContents> {
+        let mut contents = IpkContents::default();
+        let mut archive = ArArchive::new(reader);
+        let (mut has_control, mut has_data) = (false, false);
+        while let Some(entry) = archive.next_entry() {
+            let entry = entry?;
+            let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+            match Codec::from_member_name(&name) {
+                Some(("control", codec)) => {
+                    contents.load_control(codec.reader(entry))?;
+                    has_control = true;
+                }
+                Some(("data", codec)) => {
+                    contents.load_data(codec.reader(entry))?;
+                    has_data = true;
+                }
+                _ => {}
+            }
+        }
+        if !has_control || !has_data {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Not an ipk: no control or data member",
+            ));
+        }
+        Ok(contents)
+    }
+
+    /// Read only the control fields of an ipk, leaving `files` empty. This
+    /// stops before the data member, so it is quick on a large package.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::InvalidData`] if `reader` isn't an ipk, or any
+    /// error reading it.
+    pub fn read_control<R: Read>(reader: R) -> Result<IpkContents> {
+        let mut contents = IpkContents::default();
+        let mut archive = ArArchive::new(reader);
+        while let Some(entry) = archive.next_entry() {
+            let entry = entry?;
+            let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+            if let Some(("control", codec)) = Codec::from_member_name(&name) {
+                contents.load_control(codec.reader(entry))?;
+                return Ok(contents);
+            }
+        }
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            "Not an ipk: no control member",
+        ))
+    }
+
+    /// The value of the control field `name`, matched without regard to case.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.control
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `id` in the packaged `appinfo.json`, which is what the device
+    /// lists the app as. `None` for a package with services only.
+    #[must_use]
+    pub fn app_id(&self) -> Option<String> {
+        self.files.iter().find_map(|(path, entry)| {
+            let dir = path
+                .strip_prefix("usr/palm/applications/")?
+                .strip_suffix("/appinfo.json")?;
+            if dir.contains('/') {
+                return None;
+            }
+            let appinfo: serde_json::Value = serde_json::from_slice(entry.data.as_ref()?).ok()?;
+            appinfo.get("id")?.as_str().map(String::from)
+        })
+    }
+
+    fn load_control<R: Read>(&mut self, reader: R) -> Result<()> {
+        for entry in TarArchive::new(reader).entries()? {
+            let mut entry = entry?;
+            if entry_path(&entry)? == "control" {
+                let mut text = String::new();
+                entry.read_to_string(&mut text)?;
+                self.control = parse_fields(&text);
+                return Ok(());
+            }
+        }
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            "No control file in the control member",
+        ))
+    }
+
+    fn load_data<R: Read>(&mut self, reader: R) -> Result<()> {
+        for entry in TarArchive::new(reader).entries()? {
+            let mut entry = entry?;
+            let path = entry_path(&entry)?;
+            if path.is_empty() {
+                continue;
+            }
+            let header = entry.header();
+            let mode = header.mode()? & 0o7777;
+            let kind = match header.entry_type() {
+                EntryType::Regular | EntryType::Continuous => EntryKind::File,
+                EntryType::Directory => EntryKind::Dir,
+                EntryType::Symlink => EntryKind::Symlink,
+                _ => EntryKind::Other,
+            };
+            let link = entry
+                .link_name()?
+                .map(|target| target.to_string_lossy().into_owned());
+            let (mut size, mut sha256, mut data) = (0, None, None);
+            if kind == EntryKind::File {
+                let keep = METADATA_FILES
+                    .iter()
+                    .any(|name| path.rsplit('/').next() == Some(*name));
+                let mut hasher = Sha256::new();
+                if keep {
+                    let mut kept = Vec::new();
+                    entry.read_to_end(&mut kept)?;
+                    hasher.update(&kept);
+                    size = kept.len() as u64;
+                    data = Some(kept);
+                } else {
+                    size = io::copy(&mut entry, &mut hasher)?;
+                }
+                sha256 = Some(format!("{:x}", hasher.finalize()));
+            }
+            self.files.insert(
+                path,
+                IpkEntry {
+                    kind,
+                    size,
+                    mode,
+                    sha256,
+                    link,
+                    data,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The entry's path the way the packager writes it: no leading `./`, and no
+/// trailing `/` on directories. ares-cli's own packages start with `./`.
+fn entry_path<R: Read>(entry: &tar::Entry<R>) -> Result<String> {
+    let path = entry.path()?;
+    let path = path.to_string_lossy();
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    Ok(path.trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ar::{Builder as ArBuilder, Header as ArHeader};
+    use tar::{Builder as TarBuilder, EntryType, Header as TarHeader};
+
+    use super::{EntryKind, IpkContents, IpkEntry};
+    use crate::packaging::compression::{Codec, Compression};
+    use crate::packaging::control::{AppendControl, ControlInfo};
+    use crate::packaging::header::AppendHeader;
+
+    #[test]
+    fn reads_what_the_packager_writes() {
+        let compression = Compression::new(Codec::Xz, Some(0)).unwrap();
+        let control = ControlInfo::new(
+            String::from("com.example.app"),
+            String::from("1.0.0"),
+            String::from("all"),
+            5,
+        );
+
+        let mut tar = TarBuilder::new(compression.writer(Vec::new()));
+        let mut header = TarHeader::new_gnu();
+        header.set_mode(0o100_755);
+        header.set_size(5);
+        header.set_cksum();
+        tar.append_data(&mut header, "./usr/palm/appinfo.json", &b"{} \n\n"[..])
+            .unwrap();
+        let mut header = TarHeader::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o120_777);
+        header.set_size(0);
+        tar.append_link(&mut header, "usr/palm/link", "appinfo.json")
+            .unwrap();
+        let data = tar.into_inner().unwrap().finish().unwrap();
+
+        let mut ar = ArBuilder::new(Vec::new());
+        ar.append_header(0).unwrap();
+        ar.append_control(&control, 0, compression).unwrap();
+        ar.append(
+            &ArHeader::new(compression.member_name("data"), data.len() as u64),
+            Cursor::new(data),
+        )
+        .unwrap();
+
+        let ipk = ar.into_inner().unwrap();
+        let control_only = IpkContents::read_control(Cursor::new(&ipk)).unwrap();
+        assert_eq!(control_only.field("version"), Some("1.0.0"));
+        assert!(control_only.files.is_empty());
+
+        let contents = IpkContents::read(Cursor::new(ipk)).unwrap();
+        assert_eq!(contents.field("package"), Some("com.example.app"));
+        let file = &contents.files["usr/palm/appinfo.json"];
+        assert_eq!(
+            (file.kind.clone(), file.size, file.mode),
+            (EntryKind::File, 5, 0o755)
+        );
+        assert_eq!(file.data.as_deref(), Some(&b"{} \n\n"[..]));
+        assert_eq!(
+            file.sha256.as_deref(),
+            Some("d196b64524392c46fd4a8cef5978137305fcd9671557b6546ab818eea9e5a074")
+        );
+        let link = &contents.files["usr/palm/link"];
+        assert_eq!(link.link.as_deref(), Some("appinfo.json"));
+    }
+
+    #[test]
+    fn the_app_id_comes_from_appinfo() {
+        let entry = |data: &[u8]| IpkEntry {
+            kind: EntryKind::File,
+            size: data.len() as u64,
+            mode: 0o644,
+            sha256: None,
+            link: None,
+            data: Some(data.to_vec()),
+        };
+        let mut contents = IpkContents::default();
+        contents.files.insert(
+            String::from("usr/palm/services/com.example.app.service/services.json"),
+            entry(b"{}"),
+        );
+        assert_eq!(contents.app_id(), None);
+
+        contents.files.insert(
+            String::from("usr/palm/applications/com.example.app/appinfo.json"),
+            entry(br#"{"id":"com.example.app","version":"1.0.0"}"#),
+        );
+        assert_eq!(contents.app_id().as_deref(), Some("com.example.app"));
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(IpkContents::read(&b"!<arch>\n"[..]).is_err());
+        assert!(IpkContents::read(&b"not an archive"[..]).is_err());
+    }
+}